@@ -0,0 +1,474 @@
+use crate::api::{json_response, status_response};
+use crate::file_with_size::FileWithSize;
+use crate::forwarded_identity::ForwardedIdentity;
+use crate::image_format::AcceptedImageFormats;
+use crate::json_store::JsonStore;
+use crate::libraries::rebase;
+use crate::options::Options;
+use crate::statistics::*;
+use crate::{add_access_control_allow_origin_if_needed, send_thumb, VaryByAccept};
+use rocket::http::Status;
+use rocket::{Data, Response, State};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+/// an album with thousands of items still fits
+const MAX_ALBUM_BODY_BYTES: u64 = 1024 * 1024;
+
+/// A named and ordered collection of media coming
+/// from any folder. Items are kept as public paths.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Album {
+    pub name: String,
+    pub owner: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub cover: Option<String>,
+    #[serde(default)]
+    pub items: Vec<String>,
+    /// emails and #groups that can open the album
+    #[serde(default)]
+    pub shared_with: Vec<String>,
+    /// when set, being in shared_with is enough to see
+    /// the items the owner can still reach, whatever
+    /// the folder rules say about the viewer
+    #[serde(default)]
+    pub share_grants_access: bool,
+}
+
+/// The albums of every identity, by id. The ids are never
+/// reused: the links to a deleted album must not open another
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct AlbumCollection {
+    next_id: u64,
+    by_id: BTreeMap<u64, Album>,
+}
+
+pub type Albums = JsonStore<AlbumCollection>;
+
+/// keeps the items and the covers of the moved or renamed items
+pub fn move_album_items(albums: &Albums, from: &str, to: &str) -> std::io::Result<()> {
+    albums.update(|albums| {
+        albums.by_id.values_mut().for_each(|album| {
+            album
+                .items
+                .iter_mut()
+                .chain(album.cover.iter_mut())
+                .for_each(|public_path| {
+                    if let Some(moved) = rebase(public_path, from, to) {
+                        *public_path = moved;
+                    }
+                });
+        })
+    })
+}
+
+/// What the owner sends to create or replace an album
+#[derive(Clone, Debug, Deserialize)]
+pub struct AlbumChange {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub cover: Option<String>,
+    #[serde(default)]
+    pub items: Vec<String>,
+    #[serde(default)]
+    pub shared_with: Vec<String>,
+    #[serde(default)]
+    pub share_grants_access: bool,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct AlbumSummary {
+    pub id: u64,
+    pub name: String,
+    pub owner: String,
+    pub description: Option<String>,
+    pub cover: Option<String>,
+    pub items: usize,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct AlbumView {
+    pub id: u64,
+    pub name: String,
+    pub owner: String,
+    pub description: Option<String>,
+    pub cover: Option<String>,
+    pub items: Vec<FileWithSize>,
+    /// only the owner sees who the album is shared with
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shared_with: Option<Vec<String>>,
+    pub share_grants_access: bool,
+}
+
+impl Album {
+    fn can_open(&self, options: &Options, user: &str) -> bool {
+        self.owner == user || options.is_shared_with(&self.shared_with, user)
+    }
+
+    /// the host path of the item if the user can see it: through
+    /// the folder rules or, if the share grants it, through the owner
+    fn visible_item(&self, options: &Options, user: &str, public_path: &str) -> Option<PathBuf> {
+        let path = options.libraries.resolve(Path::new(public_path))?;
+        if !path.is_file() || options.hidden_files.is_hidden(&path) {
+            return None;
+        }
+
+        let required = options.media_permission(&path);
+        let has_level = |user: &str| {
+            options
+                .folder_permission(&path, user)
+                .is_some_and(|permission| permission >= required)
+        };
+
+        let is_allowed = has_level(user)
+            || (self.share_grants_access
+                && self.owner != user
+                && options.is_shared_with(&self.shared_with, user)
+                && has_level(&self.owner));
+
+        if is_allowed {
+            Some(path)
+        } else {
+            None
+        }
+    }
+
+    fn visible_cover(&self, options: &Options, user: &str) -> Option<String> {
+        let cover = self.cover.as_ref()?;
+        self.visible_item(options, user, cover)?;
+        Some(cover.to_owned())
+    }
+
+    fn summary(&self, options: &Options, user: &str, id: u64) -> AlbumSummary {
+        AlbumSummary {
+            id,
+            name: self.name.to_owned(),
+            owner: self.owner.to_owned(),
+            description: self.description.clone(),
+            cover: self.visible_cover(options, user),
+            items: self.items.len(),
+        }
+    }
+
+    fn view(&self, options: &Options, user: &str, id: u64) -> AlbumView {
+        let items = self
+            .items
+            .iter()
+            .filter_map(|public_path| {
+                let path = self.visible_item(options, user, public_path)?;
+                let size = path.metadata().ok()?.len();
+                Some(FileWithSize::with_size(public_path.to_owned(), size))
+            })
+            .collect();
+
+        AlbumView {
+            id,
+            name: self.name.to_owned(),
+            owner: self.owner.to_owned(),
+            description: self.description.clone(),
+            cover: self.visible_cover(options, user),
+            items,
+            shared_with: if self.owner == user {
+                Some(self.shared_with.clone())
+            } else {
+                None
+            },
+            share_grants_access: self.share_grants_access,
+        }
+    }
+}
+
+impl AlbumChange {
+    fn from_data(data: Data) -> Option<Self> {
+        let mut body = Vec::new();
+        data.open()
+            .take(MAX_ALBUM_BODY_BYTES)
+            .read_to_end(&mut body)
+            .ok()?;
+        serde_json::from_slice(&body).ok()
+    }
+
+    /// builds the album, None if the owner sends
+    /// items they cannot see or an empty name
+    fn into_album(self, options: &Options, owner: &str) -> Option<Album> {
+        if self.name.trim().is_empty() {
+            return None;
+        }
+
+        // the items are stored in the canonical public form
+        let canonical = |public_path: &str| {
+            let path = options.libraries.resolve(Path::new(public_path))?;
+            if !path.is_file()
+                || options.hidden_files.is_hidden(&path)
+                || options.folder_permission(&path, owner).is_none()
+            {
+                return None;
+            }
+            options.libraries.to_public(&path)
+        };
+
+        let items = self
+            .items
+            .iter()
+            .map(|item| canonical(item))
+            .collect::<Option<Vec<_>>>()?;
+        let cover = match self.cover {
+            Some(cover) => Some(canonical(&cover).filter(|cover| items.contains(cover))?),
+            None => None,
+        };
+
+        Some(Album {
+            name: self.name,
+            owner: owner.to_owned(),
+            description: self.description,
+            cover,
+            items,
+            shared_with: self.shared_with,
+            share_grants_access: self.share_grants_access,
+        })
+    }
+}
+
+#[get("/albums")]
+pub(crate) fn list_albums<'r>(
+    options: State<'_, Options>,
+    statistics: State<'_, Arc<RwLock<Statistics>>>,
+    albums: State<'_, Albums>,
+    forwarded_identity: ForwardedIdentity,
+) -> Response<'r> {
+    if !options.identity_allowed(&forwarded_identity) {
+        return status_response(Status::Unauthorized);
+    }
+    track_album_access(&options, &statistics);
+
+    let email = &forwarded_identity.email;
+    let summaries = albums.read(|albums| {
+        albums
+            .by_id
+            .iter()
+            .filter(|(_, album)| album.can_open(&options, email))
+            .map(|(id, album)| album.summary(&options, email, *id))
+            .collect::<Vec<_>>()
+    });
+
+    options.audit(email, "album", "", "list", true);
+    json_response(&options, Status::Ok, &summaries)
+}
+
+#[post("/albums", data = "<data>")]
+pub(crate) fn create_album<'r>(
+    options: State<'_, Options>,
+    statistics: State<'_, Arc<RwLock<Statistics>>>,
+    albums: State<'_, Albums>,
+    forwarded_identity: ForwardedIdentity,
+    data: Data,
+) -> Response<'r> {
+    if !options.identity_allowed(&forwarded_identity) {
+        return status_response(Status::Unauthorized);
+    }
+
+    let email = &forwarded_identity.email;
+    let album =
+        match AlbumChange::from_data(data).and_then(|change| change.into_album(&options, email)) {
+            Some(album) => album,
+            None => return status_response(Status::BadRequest),
+        };
+
+    track_album_change(&options, &statistics);
+    let saved = albums.update(|albums| {
+        albums.next_id += 1;
+        let id = albums.next_id;
+        albums.by_id.insert(id, album);
+        id
+    });
+    let id = match saved {
+        Ok(id) => id,
+        Err(err) => {
+            error!("cannot save the albums: {}", err);
+            return status_response(Status::InternalServerError);
+        }
+    };
+
+    options.audit(email, "album", &id.to_string(), "create", true);
+    json_response(&options, Status::Created, &serde_json::json!({ "id": id }))
+}
+
+#[get("/albums/<id>")]
+pub(crate) fn album<'r>(
+    options: State<'_, Options>,
+    statistics: State<'_, Arc<RwLock<Statistics>>>,
+    albums: State<'_, Albums>,
+    forwarded_identity: ForwardedIdentity,
+    id: u64,
+) -> Response<'r> {
+    if !options.identity_allowed(&forwarded_identity) {
+        return status_response(Status::Unauthorized);
+    }
+
+    let email = &forwarded_identity.email;
+    let album = match albums.read(|albums| albums.by_id.get(&id).cloned()) {
+        Some(album) => album,
+        None => return status_response(Status::NotFound),
+    };
+
+    if !album.can_open(&options, email) {
+        track_unauthorized_dynamic(&options, &statistics);
+        options.audit(email, "album", &id.to_string(), "get", false);
+        return status_response(Status::Unauthorized);
+    }
+
+    track_album_access(&options, &statistics);
+    options.audit(email, "album", &id.to_string(), "get", true);
+    json_response(&options, Status::Ok, &album.view(&options, email, id))
+}
+
+/// replaces the album, only the owner can do it
+#[put("/albums/<id>", data = "<data>")]
+pub(crate) fn update_album<'r>(
+    options: State<'_, Options>,
+    statistics: State<'_, Arc<RwLock<Statistics>>>,
+    albums: State<'_, Albums>,
+    forwarded_identity: ForwardedIdentity,
+    id: u64,
+    data: Data,
+) -> Response<'r> {
+    if !options.identity_allowed(&forwarded_identity) {
+        return status_response(Status::Unauthorized);
+    }
+
+    let email = &forwarded_identity.email;
+    let album =
+        match AlbumChange::from_data(data).and_then(|change| change.into_album(&options, email)) {
+            Some(album) => album,
+            None => return status_response(Status::BadRequest),
+        };
+
+    track_album_change(&options, &statistics);
+    let saved = albums.update(|albums| match albums.by_id.get_mut(&id) {
+        Some(current) if current.owner == *email => {
+            *current = album;
+            Status::NoContent
+        }
+        Some(_) => Status::Unauthorized,
+        None => Status::NotFound,
+    });
+
+    match saved {
+        Ok(status) => {
+            options.audit(
+                email,
+                "album",
+                &id.to_string(),
+                "update",
+                status == Status::NoContent,
+            );
+            let mut response = status_response(status);
+            add_access_control_allow_origin_if_needed(&mut response, &options);
+            response
+        }
+        Err(err) => {
+            error!("cannot save the albums: {}", err);
+            status_response(Status::InternalServerError)
+        }
+    }
+}
+
+#[delete("/albums/<id>")]
+pub(crate) fn delete_album<'r>(
+    options: State<'_, Options>,
+    statistics: State<'_, Arc<RwLock<Statistics>>>,
+    albums: State<'_, Albums>,
+    forwarded_identity: ForwardedIdentity,
+    id: u64,
+) -> Response<'r> {
+    if !options.identity_allowed(&forwarded_identity) {
+        return status_response(Status::Unauthorized);
+    }
+
+    let email = &forwarded_identity.email;
+
+    track_album_change(&options, &statistics);
+    let saved = albums.update(|albums| match albums.by_id.get(&id) {
+        Some(album) if album.owner == *email => {
+            albums.by_id.remove(&id);
+            Status::NoContent
+        }
+        Some(_) => Status::Unauthorized,
+        None => Status::NotFound,
+    });
+
+    match saved {
+        Ok(status) => {
+            options.audit(
+                email,
+                "album",
+                &id.to_string(),
+                "delete",
+                status == Status::NoContent,
+            );
+            let mut response = status_response(status);
+            add_access_control_allow_origin_if_needed(&mut response, &options);
+            response
+        }
+        Err(err) => {
+            error!("cannot save the albums: {}", err);
+            status_response(Status::InternalServerError)
+        }
+    }
+}
+
+/// the thumbnails of the items the share grants,
+/// the viewer might not reach them through /thumb/
+#[get("/albums/<id>/thumb/<max_size>/<path..>")]
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn album_thumb(
+    options: State<'_, Options>,
+    statistics: State<'_, Arc<RwLock<Statistics>>>,
+    albums: State<'_, Albums>,
+    forwarded_identity: ForwardedIdentity,
+    accepted_formats: AcceptedImageFormats,
+    id: u64,
+    max_size: u64,
+    path: PathBuf,
+) -> Option<VaryByAccept> {
+    if !options.identity_allowed(&forwarded_identity) {
+        track_unauthorized_thumb(&options, &statistics);
+        return None;
+    }
+
+    let email = &forwarded_identity.email;
+    let album = albums.read(|albums| albums.by_id.get(&id).cloned())?;
+    // the items are stored in the canonical public form
+    let host_path = options.libraries.resolve(&path)?;
+    let public_path = options.libraries.to_public(&host_path)?;
+
+    let path = if album.can_open(&options, email) && album.items.contains(&public_path) {
+        album.visible_item(&options, email, &public_path)
+    } else {
+        None
+    };
+    // the share can grant what the folder rules do not,
+    // the check is audited like theirs
+    options.audit(
+        email,
+        "album",
+        host_path.to_str().unwrap(),
+        "thumb",
+        path.is_some(),
+    );
+
+    match path {
+        Some(path) => send_thumb(&options, &statistics, &accepted_formats, max_size, &path),
+        None => {
+            track_unauthorized_thumb(&options, &statistics);
+            None
+        }
+    }
+}
@@ -0,0 +1,302 @@
+use crate::add_access_control_allow_origin_if_needed;
+use crate::api::{authorize_media, json_response, status_response};
+use crate::file_with_size::FileWithSize;
+use crate::forwarded_identity::ForwardedIdentity;
+use crate::json_store::JsonStore;
+use crate::libraries::rebase;
+use crate::options::Options;
+use crate::permission::{Capability, Permission};
+use crate::statistics::*;
+use rocket::http::Status;
+use rocket::{Data, Response, State};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Arc, RwLock};
+
+const MAX_ANNOTATION_BODY_BYTES: u64 = 64 * 1024;
+const MAX_TAG_CHARS: usize = 64;
+const MAX_CAPTION_CHARS: usize = 4096;
+
+/// The free-form tags and the caption of a media file
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Annotation {
+    #[serde(default)]
+    pub tags: BTreeSet<String>,
+    #[serde(default)]
+    pub caption: Option<String>,
+}
+
+impl Annotation {
+    fn is_empty(&self) -> bool {
+        self.tags.is_empty() && self.caption.is_none()
+    }
+
+    /// trims the tags and the caption, None if
+    /// they are too long to be a sensible annotation
+    fn normalized(self) -> Option<Self> {
+        let tags = self
+            .tags
+            .iter()
+            .map(|tag| tag.trim())
+            .filter(|tag| !tag.is_empty())
+            .map(|tag| {
+                if tag.chars().count() > MAX_TAG_CHARS {
+                    None
+                } else {
+                    Some(tag.to_owned())
+                }
+            })
+            .collect::<Option<BTreeSet<_>>>()?;
+
+        let caption = match self.caption.as_deref().map(str::trim) {
+            Some(caption) if caption.chars().count() > MAX_CAPTION_CHARS => return None,
+            Some(caption) if !caption.is_empty() => Some(caption.to_owned()),
+            _ => None,
+        };
+
+        Some(Self { tags, caption })
+    }
+
+    /// every word must be one of the tags or
+    /// appear in the caption, ignoring the case
+    fn matches(&self, words: &[String]) -> bool {
+        let caption = self.caption.as_deref().unwrap_or("").to_lowercase();
+        words.iter().all(|word| {
+            self.tags.iter().any(|tag| tag.to_lowercase() == *word) || caption.contains(word)
+        })
+    }
+}
+
+/// The annotations of the whole library, by public path
+#[derive(Debug)]
+pub struct Annotations {
+    store: JsonStore<BTreeMap<String, Annotation>>,
+}
+
+impl Annotations {
+    pub fn open(data_folder_path: &str) -> Self {
+        Self {
+            store: JsonStore::open(data_folder_path, "annotations"),
+        }
+    }
+
+    pub fn get(&self, options: &Options, path: &Path) -> Option<Annotation> {
+        let public_path = options.libraries.to_public(path)?;
+        self.store
+            .read(|annotations| annotations.get(&public_path).cloned())
+    }
+
+    fn set(&self, public_path: String, annotation: Annotation) -> std::io::Result<()> {
+        self.store.update(|annotations| {
+            if annotation.is_empty() {
+                annotations.remove(&public_path);
+            } else {
+                annotations.insert(public_path, annotation);
+            }
+        })
+    }
+
+    /// keeps the annotations of the moved or renamed items
+    pub fn move_paths(&self, from: &str, to: &str) -> std::io::Result<()> {
+        self.store.update(|annotations| {
+            let moved = annotations
+                .keys()
+                .filter_map(|public_path| {
+                    Some((public_path.to_owned(), rebase(public_path, from, to)?))
+                })
+                .collect::<Vec<_>>();
+            moved.into_iter().for_each(|(old, new)| {
+                if let Some(annotation) = annotations.remove(&old) {
+                    annotations.insert(new, annotation);
+                }
+            });
+        })
+    }
+
+    fn search(&self, words: &[String]) -> Vec<(String, Annotation)> {
+        self.store.read(|annotations| {
+            annotations
+                .iter()
+                .filter(|(_, annotation)| annotation.matches(words))
+                .map(|(public_path, annotation)| (public_path.to_owned(), annotation.clone()))
+                .collect()
+        })
+    }
+}
+
+/// the sidecar the photo managers look for, next to the original
+fn xmp_sidecar_path(path: &Path) -> PathBuf {
+    let mut file_name = path.file_name().unwrap().to_owned();
+    file_name.push(".xmp");
+    path.with_file_name(file_name)
+}
+
+/// writes the annotation in the XMP sidecar with exiftool, an
+/// existing sidecar is updated so the other tags are kept
+fn write_xmp_sidecar(path: &Path, annotation: &Annotation) -> Result<(), String> {
+    let sidecar = xmp_sidecar_path(path);
+
+    let mut args = vec!["-XMP-dc:Subject=".to_owned()];
+    annotation
+        .tags
+        .iter()
+        .for_each(|tag| args.push(format!("-XMP-dc:Subject={}", tag)));
+    args.push(format!(
+        "-XMP-dc:Description={}",
+        annotation.caption.as_deref().unwrap_or("")
+    ));
+
+    let mut cmd = Command::new("exiftool");
+    let cmd = if sidecar.exists() {
+        cmd.arg("-overwrite_original").args(&args).arg(&sidecar)
+    } else {
+        // the new sidecar starts from the metadata of the original
+        cmd.arg("-o").arg(&sidecar).args(&args).arg(path)
+    };
+    trace!("{:#?}", cmd);
+
+    let output = cmd.output().map_err(|err| err.to_string())?;
+    trace!("{:?}", output);
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).into_owned())
+    }
+}
+
+#[get("/annotations/<path..>")]
+pub(crate) fn annotation<'r>(
+    options: State<'_, Options>,
+    statistics: State<'_, Arc<RwLock<Statistics>>>,
+    annotations: State<'_, Annotations>,
+    forwarded_identity: ForwardedIdentity,
+    path: PathBuf,
+) -> Response<'r> {
+    // the documents are only shown to the users seeing the extra files
+    let permission = options
+        .libraries
+        .resolve(&path)
+        .map_or(Permission::Preview, |path| options.media_permission(&path));
+    let path = match authorize_media(&options, &statistics, &forwarded_identity, path, permission) {
+        Ok(path) => path,
+        Err(status) => return status_response(status),
+    };
+
+    track_authorized_dynamic(&options, &statistics);
+    let annotation = annotations.get(&options, &path).unwrap_or_default();
+    json_response(&options, Status::Ok, &annotation)
+}
+
+/// replaces the tags and the caption, an empty
+/// annotation removes them. Needs the annotate capability
+#[put("/annotations/<path..>", data = "<data>")]
+pub(crate) fn annotate<'r>(
+    options: State<'_, Options>,
+    statistics: State<'_, Arc<RwLock<Statistics>>>,
+    annotations: State<'_, Annotations>,
+    forwarded_identity: ForwardedIdentity,
+    path: PathBuf,
+    data: Data,
+) -> Response<'r> {
+    let permission = options
+        .libraries
+        .resolve(&path)
+        .map_or(Permission::Preview, |path| options.media_permission(&path));
+    let path = match authorize_media(&options, &statistics, &forwarded_identity, path, permission) {
+        Ok(path) => path,
+        Err(status) => return status_response(status),
+    };
+    if !options.has_capability(&path, &forwarded_identity.email, Capability::Annotate) {
+        track_unauthorized_dynamic(&options, &statistics);
+        return status_response(Status::Unauthorized);
+    }
+
+    let mut body = Vec::new();
+    let annotation = data
+        .open()
+        .take(MAX_ANNOTATION_BODY_BYTES)
+        .read_to_end(&mut body)
+        .ok()
+        .and_then(|_| serde_json::from_slice::<Annotation>(&body).ok())
+        .and_then(Annotation::normalized);
+    let annotation = match annotation {
+        Some(annotation) => annotation,
+        None => return status_response(Status::BadRequest),
+    };
+
+    track_annotation_change(&options, &statistics);
+    let public_path = options.libraries.to_public(&path).unwrap();
+    if let Err(err) = annotations.set(public_path, annotation.clone()) {
+        error!("cannot save the annotations: {}", err);
+        return status_response(Status::InternalServerError);
+    }
+
+    options.audit(
+        &forwarded_identity.email,
+        "annotation",
+        path.to_str().unwrap(),
+        "tag",
+        true,
+    );
+
+    // the local store is the reference, a read only
+    // library must not prevent the annotation
+    if options.xmp_sidecars_enabled {
+        if let Err(err) = write_xmp_sidecar(&path, &annotation) {
+            warn!("cannot write the XMP sidecar of {:?}: {}", path, err);
+        }
+    }
+
+    let mut response = status_response(Status::NoContent);
+    add_access_control_allow_origin_if_needed(&mut response, &options);
+    response
+}
+
+/// finds the media whose tags or caption contain every word of
+/// the query, among the ones the user can see
+#[get("/search?<q>")]
+pub(crate) fn search<'r>(
+    options: State<'_, Options>,
+    statistics: State<'_, Arc<RwLock<Statistics>>>,
+    annotations: State<'_, Annotations>,
+    forwarded_identity: ForwardedIdentity,
+    q: String,
+) -> Response<'r> {
+    if !options.identity_allowed(&forwarded_identity) {
+        return status_response(Status::Unauthorized);
+    }
+    track_search(&options, &statistics);
+
+    let words = q
+        .split_whitespace()
+        .map(|word| word.to_lowercase())
+        .collect::<Vec<_>>();
+    let items = if words.is_empty() {
+        Vec::new()
+    } else {
+        annotations
+            .search(&words)
+            .into_iter()
+            .filter_map(|(public_path, annotation)| {
+                let path = options.libraries.resolve(Path::new(&public_path))?;
+                let required = options.media_permission(&path);
+                if !path.is_file()
+                    || options.hidden_files.is_hidden(&path)
+                    || !options
+                        .folder_permission(&path, &forwarded_identity.email)
+                        .is_some_and(|permission| permission >= required)
+                {
+                    return None;
+                }
+                let size = path.metadata().ok()?.len();
+                Some(FileWithSize::with_size(public_path, size).with_annotation(Some(annotation)))
+            })
+            .collect::<Vec<_>>()
+    };
+
+    options.audit(&forwarded_identity.email, "annotation", &q, "search", true);
+    json_response(&options, Status::Ok, &items)
+}
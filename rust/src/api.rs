@@ -0,0 +1,124 @@
+use crate::add_access_control_allow_origin_if_needed;
+use crate::forwarded_identity::ForwardedIdentity;
+use crate::media_type::MediaKind;
+use crate::options::Options;
+use crate::permission::Permission;
+use crate::statistics::*;
+use crate::video_probe::{probe, VideoInfo};
+use rocket::http::{ContentType, Status};
+use rocket::{Response, State};
+use serde::Serialize;
+use std::io::Cursor;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+
+pub(crate) fn status_response<'r>(status: Status) -> Response<'r> {
+    let mut response = Response::new();
+    response.set_status(status);
+    response
+}
+
+pub(crate) fn json_response<'r, T: Serialize>(
+    options: &Options,
+    status: Status,
+    value: &T,
+) -> Response<'r> {
+    let mut response = status_response(status);
+    response.set_header(ContentType::JSON);
+    add_access_control_allow_origin_if_needed(&mut response, options);
+    response.set_sized_body(Cursor::new(serde_json::to_string(value).unwrap()));
+    response
+}
+
+/// resolves the path and checks the permission
+fn authorize_path(
+    options: &State<'_, Options>,
+    statistics: &State<'_, Arc<RwLock<Statistics>>>,
+    forwarded_identity: &ForwardedIdentity,
+    path: PathBuf,
+    permission: Permission,
+) -> Result<PathBuf, Status> {
+    let path = options.libraries.resolve(&path).ok_or(Status::NotFound)?;
+    trace!("requesting: {:?}", &path);
+    trace!("Authenticated as {}", &forwarded_identity);
+    let is_folder_allowed =
+        options.is_folder_allowed_with(&path, &forwarded_identity.email, permission);
+    trace!("is_folder_allowed == {}", is_folder_allowed);
+
+    if !is_folder_allowed {
+        track_unauthorized_dynamic(options, statistics);
+        return Err(Status::Unauthorized);
+    }
+
+    Ok(path)
+}
+
+/// checks the permission and makes sure the
+/// path is a visible file, not a directory
+pub(crate) fn authorize_file(
+    options: &State<'_, Options>,
+    statistics: &State<'_, Arc<RwLock<Statistics>>>,
+    forwarded_identity: &ForwardedIdentity,
+    path: PathBuf,
+    permission: Permission,
+) -> Result<PathBuf, Status> {
+    let path = authorize_path(options, statistics, forwarded_identity, path, permission)?;
+
+    if !path.is_file() || options.hidden_files.is_hidden(&path) {
+        track_authorized_not_found(options, statistics);
+        return Err(Status::NotFound);
+    }
+
+    Ok(path)
+}
+
+/// checks the permission and makes sure the
+/// path is a visible media file
+pub(crate) fn authorize_media(
+    options: &State<'_, Options>,
+    statistics: &State<'_, Arc<RwLock<Statistics>>>,
+    forwarded_identity: &ForwardedIdentity,
+    path: PathBuf,
+    permission: Permission,
+) -> Result<PathBuf, Status> {
+    let path = authorize_file(options, statistics, forwarded_identity, path, permission)?;
+
+    if options.media_types.lookup(&path).is_none() {
+        track_authorized_not_found(options, statistics);
+        return Err(Status::NotFound);
+    }
+
+    Ok(path)
+}
+
+/// checks the permission and makes sure the
+/// path is a video ffprobe can understand
+pub(crate) fn authorize_video(
+    options: &State<'_, Options>,
+    statistics: &State<'_, Arc<RwLock<Statistics>>>,
+    forwarded_identity: &ForwardedIdentity,
+    path: PathBuf,
+    permission: Permission,
+) -> Result<(PathBuf, VideoInfo), Status> {
+    let path = authorize_path(options, statistics, forwarded_identity, path, permission)?;
+
+    let is_video = !path.is_dir()
+        && !options.hidden_files.is_hidden(&path)
+        && options
+            .media_types
+            .lookup(&path)
+            .is_some_and(|media_type| media_type.kind == MediaKind::Video);
+    let info = if is_video { probe(&path) } else { None };
+
+    match info {
+        Some(info) => {
+            trace!("info == {:?}", info);
+            track_authorized_dynamic(options, statistics);
+            Ok((path, info))
+        }
+        None => {
+            track_authorized_not_found(options, statistics);
+            Err(Status::NotFound)
+        }
+    }
+}
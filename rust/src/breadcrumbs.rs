@@ -0,0 +1,110 @@
+use crate::api::{json_response, status_response};
+use crate::first_level_folders::FirstLevelFolders;
+use crate::folder_metadata::FolderMetadata;
+use crate::forwarded_identity::ForwardedIdentity;
+use crate::options::Options;
+use crate::statistics::*;
+use rocket::http::Status;
+use rocket::{Response, State};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Breadcrumb {
+    pub path: String,
+    pub name: String,
+    pub allowed: bool,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Breadcrumbs {
+    /// from the root down to the requested path
+    pub breadcrumbs: Vec<Breadcrumb>,
+    /// the deepest first level folder of the user
+    /// containing the requested path
+    pub root: Option<String>,
+}
+
+/// the title of the folder sidecar, if the user can open
+/// the folder, or the last segment of its public path
+fn display_name(path: &Path, public_path: &str, allowed: bool) -> String {
+    let title = if allowed {
+        FolderMetadata::load(path).and_then(|metadata| metadata.title)
+    } else {
+        None
+    };
+
+    title.unwrap_or_else(|| match public_path.rsplit('/').next() {
+        Some(name) if !name.is_empty() => name.to_owned(),
+        _ => "/".to_owned(),
+    })
+}
+
+#[get("/breadcrumbs/<path..>")]
+pub(crate) fn breadcrumbs<'r>(
+    options: State<'r, Options>,
+    statistics: State<'_, Arc<RwLock<Statistics>>>,
+    first_folder_by_email: State<'r, RwLock<FirstLevelFolders>>,
+    forwarded_identity: ForwardedIdentity,
+    path: PathBuf,
+) -> Response<'r> {
+    let path = match options.libraries.resolve(&path) {
+        Some(path) => path,
+        None => return status_response(Status::NotFound),
+    };
+    trace!("Authenticated as {}", &forwarded_identity);
+    trace!("requested path == {:?}", &path);
+
+    if !options.identity_allowed(&forwarded_identity) {
+        track_unauthorized_breadcrumbs(&options, &statistics);
+        return status_response(Status::Unauthorized);
+    }
+    track_authorized_breadcrumbs(&options, &statistics);
+
+    // the ancestors above the libraries are not exposed
+    let breadcrumbs = options
+        .check_ancestors(&path, &forwarded_identity.email)
+        .into_iter()
+        .filter_map(|(ancestor, permission_check)| {
+            // hidden folders are never reachable
+            let allowed =
+                permission_check.permission.is_some() && !options.hidden_files.is_hidden(&ancestor);
+            let public_path = options.libraries.to_public(&ancestor)?;
+            Some(Breadcrumb {
+                name: display_name(&ancestor, &public_path, allowed),
+                path: public_path,
+                allowed,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    if first_folder_by_email.read().unwrap().is_stale(&options) {
+        debug!("ACL changed, recalculating the first level folders");
+        *first_folder_by_email.write().unwrap() = FirstLevelFolders::calculate(&options);
+    }
+    let root = first_folder_by_email
+        .read()
+        .unwrap()
+        .get(&forwarded_identity.email)
+        .and_then(|first_level_folders| {
+            let public_path = options.libraries.to_public(&path)?;
+            options
+                .public_first_level_folders(&forwarded_identity.email, first_level_folders)
+                .into_iter()
+                .filter(|first_level_folder| {
+                    Path::new(&public_path).starts_with(first_level_folder)
+                })
+                .max_by_key(|first_level_folder| first_level_folder.len())
+        });
+
+    options.audit(
+        &forwarded_identity.email,
+        "breadcrumbs",
+        path.to_str().unwrap(),
+        "list",
+        true,
+    );
+
+    json_response(&options, Status::Ok, &Breadcrumbs { breadcrumbs, root })
+}
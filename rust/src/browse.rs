@@ -0,0 +1,151 @@
+use crate::annotations::Annotations;
+use crate::api::{json_response, status_response};
+use crate::file_with_size::FileWithSize;
+use crate::folder_metadata::FolderMetadata;
+use crate::forwarded_identity::ForwardedIdentity;
+use crate::options::Options;
+use crate::permission::{Capability, Permission};
+use crate::statistics::*;
+use rocket::http::Status;
+use rocket::{Response, State};
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+
+/// Tells if an ancestor of the browsed folder can be opened
+#[derive(Clone, Debug, Serialize)]
+pub struct AncestorAllowance {
+    pub path: String,
+    pub allowed: bool,
+}
+
+/// Everything the three /list/ calls and the
+/// /allowed/ ones of the breadcrumb return
+#[derive(Clone, Debug, Serialize)]
+pub struct BrowseResult {
+    pub permission: Permission,
+    /// what the user can change in the folder
+    pub capabilities: BTreeSet<Capability>,
+    pub previews: Vec<FileWithSize>,
+    /// only with the full permission level
+    pub extras: Option<Vec<FileWithSize>>,
+    pub folders: Vec<FileWithSize>,
+    pub ancestors: Vec<AncestorAllowance>,
+}
+
+/// lists a folder in a single call: the directory is read once,
+/// the permissions of the subfolders are checked without
+/// auditing them and a single audit entry is written
+#[get("/browse/<path..>")]
+pub(crate) fn browse<'r>(
+    options: State<'_, Options>,
+    statistics: State<'_, Arc<RwLock<Statistics>>>,
+    annotations: State<'_, Annotations>,
+    forwarded_identity: ForwardedIdentity,
+    path: PathBuf,
+) -> Response<'r> {
+    let path = match options.libraries.resolve(&path) {
+        Some(path) => path,
+        None => return status_response(Status::NotFound),
+    };
+    trace!("Authenticated as {}", &forwarded_identity);
+    trace!("requested path == {:?}", &path);
+
+    let email = &forwarded_identity.email;
+    // the last one is the browsed folder itself
+    let mut ancestors = options.check_ancestors(&path, email);
+    let check = ancestors.pop().map(|(_, check)| check).unwrap_or_default();
+    let permission = check.permission;
+    trace!("permission == {:?}", permission);
+
+    let permission = match permission {
+        Some(permission) => permission,
+        None => {
+            track_unauthorized_browse(&options, &statistics);
+            options.audit(email, "folder", path.to_str().unwrap(), "browse", false);
+            return status_response(Status::Unauthorized);
+        }
+    };
+
+    if !path.is_dir() || options.hidden_files.is_hidden(&path) {
+        track_authorized_not_found(&options, &statistics);
+        return status_response(Status::NotFound);
+    }
+
+    track_authorized_browse(&options, &statistics);
+    options.audit(email, "folder", path.to_str().unwrap(), "browse", true);
+
+    let read_dir = match path.read_dir() {
+        Ok(read_dir) => read_dir,
+        Err(err) => {
+            error!("cannot read {:?}: {}", path, err);
+            return status_response(Status::InternalServerError);
+        }
+    };
+
+    let include_extras = permission >= Permission::Full;
+    let mut previews = Vec::new();
+    let mut extras = Vec::new();
+    let mut folders = Vec::new();
+
+    read_dir
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| !options.hidden_files.is_hidden_child(&entry.path()))
+        .for_each(|entry| {
+            let child = entry.path();
+            let metadata = match entry.metadata() {
+                Ok(metadata) => metadata,
+                Err(err) => {
+                    warn!("cannot read the metadata of {:?}: {}", child, err);
+                    return;
+                }
+            };
+            let child_str = options.libraries.to_public(&child).unwrap();
+
+            if metadata.is_dir() {
+                if options.folder_permission(&child, email).is_some() {
+                    let folder_metadata = FolderMetadata::load(&child).unwrap_or_default();
+                    let cover = folder_metadata
+                        .cover_path(&options, &child)
+                        .and_then(|cover| options.libraries.to_public(&cover));
+                    folders.push(
+                        FileWithSize::without_size(child_str)
+                            .with_folder_metadata(folder_metadata, cover),
+                    );
+                }
+            } else if options.media_types.is_previewable(&child) {
+                previews.push(
+                    FileWithSize::with_size(child_str, metadata.len())
+                        .with_annotation(annotations.get(&options, &child)),
+                );
+            } else if include_extras {
+                extras.push(
+                    FileWithSize::with_size(child_str, metadata.len())
+                        .with_annotation(annotations.get(&options, &child)),
+                );
+            }
+        });
+
+    // the ancestors above the libraries are not exposed
+    let ancestors = ancestors
+        .into_iter()
+        .filter_map(|(ancestor, permission_check)| {
+            Some(AncestorAllowance {
+                path: options.libraries.to_public(&ancestor)?,
+                allowed: permission_check.permission.is_some(),
+            })
+        })
+        .collect();
+
+    let result = BrowseResult {
+        permission,
+        capabilities: check.capabilities,
+        previews,
+        extras: if include_extras { Some(extras) } else { None },
+        folders,
+        ancestors,
+    };
+
+    json_response(&options, Status::Ok, &result)
+}
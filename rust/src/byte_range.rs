@@ -0,0 +1,211 @@
+use rocket::http::Status;
+use rocket::request::{FromRequest, Request};
+use rocket::Outcome;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+
+/// The single byte range asked with the Range header, the one
+/// browsers send to seek in audio and video files. Multiple
+/// ranges are not supported: the request gets the whole file,
+/// as it does when the header cannot be parsed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ByteRange {
+    /// bytes=start- or bytes=start-end
+    From { start: u64, end: Option<u64> },
+    /// bytes=-length, the last length bytes
+    Suffix { length: u64 },
+}
+
+impl ByteRange {
+    pub fn parse(header: &str) -> Option<Self> {
+        let spec = header.trim().strip_prefix("bytes=")?;
+        if spec.contains(',') {
+            return None;
+        }
+
+        let mut tokens = spec.splitn(2, '-');
+        let start = tokens.next()?.trim();
+        let end = tokens.next()?.trim();
+
+        if start.is_empty() {
+            Some(ByteRange::Suffix {
+                length: end.parse().ok()?,
+            })
+        } else {
+            let start = start.parse().ok()?;
+            let end = if end.is_empty() {
+                None
+            } else {
+                Some(end.parse().ok()?)
+            };
+            match end {
+                Some(end) if end < start => None,
+                _ => Some(ByteRange::From { start, end }),
+            }
+        }
+    }
+
+    /// returns the first and the last byte (both included)
+    /// of the range or None if it cannot be satisfied
+    pub fn resolve(self, file_length: u64) -> Option<(u64, u64)> {
+        if file_length == 0 {
+            return None;
+        }
+
+        match self {
+            ByteRange::From { start, end } if start < file_length => Some((
+                start,
+                end.map_or(file_length - 1, |end| end.min(file_length - 1)),
+            )),
+            ByteRange::Suffix { length } if length > 0 => {
+                Some((file_length - length.min(file_length), file_length - 1))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for ByteRange {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> Outcome<Self, (Status, Self::Error), ()> {
+        match request
+            .headers()
+            .get_one("Range")
+            .and_then(ByteRange::parse)
+        {
+            Some(byte_range) => Outcome::Success(byte_range),
+            None => Outcome::Forward(()),
+        }
+    }
+}
+
+/// A window over a file, so a byte range can
+/// be sent with a Content-Length
+#[derive(Debug)]
+pub struct FileWindow {
+    file: File,
+    start: u64,
+    length: u64,
+    position: u64,
+}
+
+impl FileWindow {
+    pub fn new(mut file: File, start: u64, length: u64) -> std::io::Result<Self> {
+        file.seek(SeekFrom::Start(start))?;
+        Ok(Self {
+            file,
+            start,
+            length,
+            position: 0,
+        })
+    }
+}
+
+impl Read for FileWindow {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let remaining = self.length.saturating_sub(self.position);
+        let max = (buf.len() as u64).min(remaining) as usize;
+        if max == 0 {
+            return Ok(0);
+        }
+
+        let read = self.file.read(&mut buf[..max])?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl Seek for FileWindow {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.length.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        }
+        .ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
+
+        self.file.seek(SeekFrom::Start(self.start + position))?;
+        self.position = position;
+        Ok(position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_the_single_ranges() {
+        assert_eq!(
+            ByteRange::parse("bytes=10-"),
+            Some(ByteRange::From {
+                start: 10,
+                end: None
+            })
+        );
+        assert_eq!(
+            ByteRange::parse(" bytes=10-20 "),
+            Some(ByteRange::From {
+                start: 10,
+                end: Some(20)
+            })
+        );
+        assert_eq!(
+            ByteRange::parse("bytes=-5"),
+            Some(ByteRange::Suffix { length: 5 })
+        );
+        assert_eq!(ByteRange::parse("bytes=20-10"), None);
+        assert_eq!(ByteRange::parse("bytes=0-1,5-6"), None);
+        assert_eq!(ByteRange::parse("bytes=-"), None);
+        assert_eq!(ByteRange::parse("items=0-1"), None);
+    }
+
+    #[test]
+    fn resolves_against_the_file_length() {
+        let from = |start, end| ByteRange::From { start, end };
+
+        assert_eq!(from(0, None).resolve(10), Some((0, 9)));
+        assert_eq!(from(2, Some(50)).resolve(10), Some((2, 9)));
+        assert_eq!(from(10, None).resolve(10), None);
+        // a suffix longer than the file is the whole file
+        assert_eq!(ByteRange::Suffix { length: 50 }.resolve(10), Some((0, 9)));
+        assert_eq!(ByteRange::Suffix { length: 3 }.resolve(10), Some((7, 9)));
+        assert_eq!(ByteRange::Suffix { length: 0 }.resolve(10), None);
+        // nothing can be satisfied in an empty file
+        assert_eq!(from(0, None).resolve(0), None);
+        assert_eq!(ByteRange::Suffix { length: 5 }.resolve(0), None);
+    }
+
+    #[test]
+    fn window_reads_and_seeks_inside_its_range() {
+        let path =
+            std::env::temp_dir().join(format!("nas_gallery_byte_range_{}", std::process::id()));
+        std::fs::write(&path, b"0123456789").unwrap();
+        let mut window = FileWindow::new(File::open(&path).unwrap(), 2, 5).unwrap();
+
+        let mut content = String::new();
+        window.read_to_string(&mut content).unwrap();
+        assert_eq!(content, "23456");
+
+        assert_eq!(window.seek(SeekFrom::End(-2)).unwrap(), 3);
+        content.clear();
+        window.read_to_string(&mut content).unwrap();
+        assert_eq!(content, "56");
+
+        assert_eq!(window.seek(SeekFrom::Start(1)).unwrap(), 1);
+        assert_eq!(window.seek(SeekFrom::Current(1)).unwrap(), 2);
+        content.clear();
+        window.read_to_string(&mut content).unwrap();
+        assert_eq!(content, "456");
+
+        assert!(window.seek(SeekFrom::Current(-10)).is_err());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
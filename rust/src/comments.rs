@@ -0,0 +1,307 @@
+use crate::add_access_control_allow_origin_if_needed;
+use crate::api::{authorize_media, json_response, status_response};
+use crate::forwarded_identity::ForwardedIdentity;
+use crate::json_store::JsonStore;
+use crate::libraries::rebase;
+use crate::options::Options;
+use crate::permission::Permission;
+use crate::statistics::*;
+use chrono::{DateTime, Utc};
+use rocket::http::Status;
+use rocket::{Data, Response, State};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+const MAX_COMMENT_BODY_BYTES: u64 = 16 * 1024;
+const MAX_COMMENT_CHARS: usize = 4000;
+/// how many comments the moderation listing returns
+const MODERATION_LIST_LENGTH: usize = 200;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Comment {
+    pub id: u64,
+    pub author: String,
+    pub created: DateTime<Utc>,
+    pub text: String,
+}
+
+/// The comment threads, by public path of the media
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct CommentThreads {
+    next_id: u64,
+    threads: BTreeMap<String, Vec<Comment>>,
+}
+
+impl CommentThreads {
+    /// keeps the threads of the moved or renamed items
+    pub fn move_paths(&mut self, from: &str, to: &str) {
+        let moved = self
+            .threads
+            .keys()
+            .filter_map(|public_path| {
+                Some((public_path.to_owned(), rebase(public_path, from, to)?))
+            })
+            .collect::<Vec<_>>();
+        moved.into_iter().for_each(|(old, new)| {
+            if let Some(thread) = self.threads.remove(&old) {
+                self.threads.insert(new, thread);
+            }
+        });
+    }
+}
+
+pub type Comments = JsonStore<CommentThreads>;
+
+#[derive(Clone, Debug, Deserialize)]
+struct NewComment {
+    text: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+struct CommentView {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    path: Option<String>,
+    #[serde(flatten)]
+    comment: Comment,
+    can_delete: bool,
+}
+
+impl CommentView {
+    fn new(options: &Options, user: &str, path: Option<String>, comment: Comment) -> Self {
+        let can_delete = comment.author == user || options.is_admin(user);
+        Self {
+            path,
+            comment,
+            can_delete,
+        }
+    }
+}
+
+#[get("/comments/<path..>")]
+pub(crate) fn comments<'r>(
+    options: State<'_, Options>,
+    statistics: State<'_, Arc<RwLock<Statistics>>>,
+    comments: State<'_, Comments>,
+    forwarded_identity: ForwardedIdentity,
+    path: PathBuf,
+) -> Response<'r> {
+    let path = match authorize_media(
+        &options,
+        &statistics,
+        &forwarded_identity,
+        path,
+        Permission::Preview,
+    ) {
+        Ok(path) => path,
+        Err(status) => return status_response(status),
+    };
+
+    track_comment_access(&options, &statistics);
+    let email = &forwarded_identity.email;
+    let public_path = options.libraries.to_public(&path).unwrap();
+    let thread = comments
+        .read(|comments| comments.threads.get(&public_path).cloned())
+        .unwrap_or_default()
+        .into_iter()
+        .map(|comment| CommentView::new(&options, email, None, comment))
+        .collect::<Vec<_>>();
+
+    options.audit(email, "comment", path.to_str().unwrap(), "list", true);
+    json_response(&options, Status::Ok, &thread)
+}
+
+#[post("/comments/<path..>", data = "<data>")]
+pub(crate) fn add_comment<'r>(
+    options: State<'_, Options>,
+    statistics: State<'_, Arc<RwLock<Statistics>>>,
+    comments: State<'_, Comments>,
+    forwarded_identity: ForwardedIdentity,
+    path: PathBuf,
+    data: Data,
+) -> Response<'r> {
+    let path = match authorize_media(
+        &options,
+        &statistics,
+        &forwarded_identity,
+        path,
+        Permission::Preview,
+    ) {
+        Ok(path) => path,
+        Err(status) => return status_response(status),
+    };
+
+    let mut body = Vec::new();
+    let text = data
+        .open()
+        .take(MAX_COMMENT_BODY_BYTES)
+        .read_to_end(&mut body)
+        .ok()
+        .and_then(|_| serde_json::from_slice::<NewComment>(&body).ok())
+        .map(|comment| comment.text.trim().to_owned())
+        .filter(|text| !text.is_empty() && text.chars().count() <= MAX_COMMENT_CHARS);
+    let text = match text {
+        Some(text) => text,
+        None => return status_response(Status::BadRequest),
+    };
+
+    track_comment_change(&options, &statistics);
+    let email = &forwarded_identity.email;
+    let public_path = options.libraries.to_public(&path).unwrap();
+    let saved = comments.update(|comments| {
+        comments.next_id += 1;
+        let comment = Comment {
+            id: comments.next_id,
+            author: email.to_owned(),
+            created: Utc::now(),
+            text,
+        };
+        comments
+            .threads
+            .entry(public_path)
+            .or_default()
+            .push(comment.clone());
+        comment
+    });
+    let comment = match saved {
+        Ok(comment) => comment,
+        Err(err) => {
+            error!("cannot save the comments: {}", err);
+            return status_response(Status::InternalServerError);
+        }
+    };
+
+    options.audit(email, "comment", path.to_str().unwrap(), "add", true);
+    json_response(
+        &options,
+        Status::Created,
+        &CommentView::new(&options, email, None, comment),
+    )
+}
+
+/// the admins only moderate what they can see
+fn can_moderate(options: &Options, admin: &str, public_path: &str) -> bool {
+    options
+        .libraries
+        .resolve(Path::new(public_path))
+        .is_some_and(|path| {
+            !options.hidden_files.is_hidden(&path)
+                && options.folder_permission(&path, admin).is_some()
+        })
+}
+
+/// the author can delete their own comments, the
+/// admins can delete everyone's on the media they see
+#[delete("/comments/<id>")]
+pub(crate) fn delete_comment<'r>(
+    options: State<'_, Options>,
+    statistics: State<'_, Arc<RwLock<Statistics>>>,
+    comments: State<'_, Comments>,
+    forwarded_identity: ForwardedIdentity,
+    id: u64,
+) -> Response<'r> {
+    if !options.identity_allowed(&forwarded_identity) {
+        return status_response(Status::Unauthorized);
+    }
+
+    let email = &forwarded_identity.email;
+    let is_admin = options.is_admin(email);
+
+    track_comment_change(&options, &statistics);
+    let saved = comments.update(|comments| {
+        let (public_path, thread) = match comments
+            .threads
+            .iter_mut()
+            .find(|(_, thread)| thread.iter().any(|comment| comment.id == id))
+        {
+            Some(found) => found,
+            None => return (Status::NotFound, None, "delete"),
+        };
+        let position = thread.iter().position(|comment| comment.id == id).unwrap();
+
+        let operation = if thread[position].author == *email {
+            "delete"
+        } else if is_admin && can_moderate(&options, email, public_path) {
+            "moderate"
+        } else {
+            return (Status::Unauthorized, Some(public_path.to_owned()), "delete");
+        };
+
+        thread.remove(position);
+        let public_path = public_path.to_owned();
+        if thread.is_empty() {
+            comments.threads.remove(&public_path);
+        }
+        (Status::NoContent, Some(public_path), operation)
+    });
+
+    match saved {
+        Ok((status, public_path, operation)) => {
+            if let Some(public_path) = public_path {
+                // the audit log uses the host paths
+                let path = options
+                    .libraries
+                    .resolve(Path::new(&public_path))
+                    .and_then(|path| path.to_str().map(|path| path.to_owned()))
+                    .unwrap_or(public_path);
+                options.audit(
+                    email,
+                    "comment",
+                    &path,
+                    operation,
+                    status == Status::NoContent,
+                );
+            }
+            let mut response = status_response(status);
+            add_access_control_allow_origin_if_needed(&mut response, &options);
+            response
+        }
+        Err(err) => {
+            error!("cannot save the comments: {}", err);
+            status_response(Status::InternalServerError)
+        }
+    }
+}
+
+/// the latest comments of the whole library, for the admins
+#[get("/comments")]
+pub(crate) fn latest_comments<'r>(
+    options: State<'_, Options>,
+    statistics: State<'_, Arc<RwLock<Statistics>>>,
+    comments: State<'_, Comments>,
+    forwarded_identity: ForwardedIdentity,
+) -> Response<'r> {
+    let email = &forwarded_identity.email;
+    if !options.is_admin(email) {
+        track_unauthorized_dynamic(&options, &statistics);
+        options.audit(email, "comment", "", "moderation_list", false);
+        return status_response(Status::Unauthorized);
+    }
+
+    track_comment_access(&options, &statistics);
+    let mut latest = comments.read(|comments| {
+        comments
+            .threads
+            .iter()
+            .filter(|(public_path, _)| can_moderate(&options, email, public_path))
+            .flat_map(|(public_path, thread)| {
+                thread
+                    .iter()
+                    .map(move |comment| (public_path.to_owned(), comment.clone()))
+            })
+            .collect::<Vec<_>>()
+    });
+    latest.sort_by_key(|(_, comment)| Reverse(comment.id));
+    latest.truncate(MODERATION_LIST_LENGTH);
+
+    let latest = latest
+        .into_iter()
+        .map(|(public_path, comment)| CommentView::new(&options, email, Some(public_path), comment))
+        .collect::<Vec<_>>();
+
+    options.audit(email, "comment", "", "moderation_list", true);
+    json_response(&options, Status::Ok, &latest)
+}
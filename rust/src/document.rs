@@ -0,0 +1,202 @@
+use crate::api::{authorize_file, status_response};
+use crate::byte_range::ByteRange;
+use crate::forwarded_identity::ForwardedIdentity;
+use crate::options::Options;
+use crate::permission::Permission;
+use crate::statistics::*;
+use crate::{add_access_control_allow_origin_if_needed, get_file_range};
+use rocket::http::{ContentType, Status};
+use rocket::{Response, State};
+use std::io::{Cursor, Read};
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    text.chars().for_each(|c| match c {
+        '&' => escaped.push_str("&amp;"),
+        '<' => escaped.push_str("&lt;"),
+        '>' => escaped.push_str("&gt;"),
+        '"' => escaped.push_str("&quot;"),
+        '\'' => escaped.push_str("&#39;"),
+        _ => escaped.push(c),
+    });
+    escaped
+}
+
+/// encodes the file name as RFC 5987 wants
+/// for the filename* parameter
+fn encode_rfc5987(file_name: &str) -> String {
+    file_name
+        .bytes()
+        .map(|b| match b {
+            b'a'..=b'z'
+            | b'A'..=b'Z'
+            | b'0'..=b'9'
+            | b'!'
+            | b'#'
+            | b'$'
+            | b'&'
+            | b'+'
+            | b'-'
+            | b'.'
+            | b'^'
+            | b'_'
+            | b'`'
+            | b'|'
+            | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+fn content_disposition(file_name: &str) -> String {
+    // old browsers only understand the plain
+    // filename, so keep it ASCII and unquoted safe
+    let fallback = file_name
+        .chars()
+        .map(|c| {
+            if c.is_ascii() && !c.is_ascii_control() && c != '"' && c != '\\' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
+
+    format!(
+        "attachment; filename=\"{}\"; filename*=UTF-8''{}",
+        fallback,
+        encode_rfc5987(file_name)
+    )
+}
+
+/// shows a text file as an HTML page: the content is escaped
+/// and truncated at text_preview_max_bytes. The text files are
+/// extra files, they need the same level to be read
+#[get("/text/<path..>")]
+pub(crate) fn text<'r>(
+    options: State<'_, Options>,
+    statistics: State<'_, Arc<RwLock<Statistics>>>,
+    forwarded_identity: ForwardedIdentity,
+    path: PathBuf,
+) -> Response<'r> {
+    let path = match authorize_file(
+        &options,
+        &statistics,
+        &forwarded_identity,
+        path,
+        Permission::Full,
+    ) {
+        Ok(path) => path,
+        Err(status) => return status_response(status),
+    };
+    track_authorized_dynamic(&options, &statistics);
+
+    let is_text = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| {
+            options
+                .text_preview_extensions
+                .contains(&extension.to_lowercase())
+        });
+    if !is_text {
+        return status_response(Status::NotFound);
+    }
+
+    track_text_preview_access(&options, &statistics);
+    options.audit(
+        &forwarded_identity.email,
+        "text",
+        path.to_str().unwrap(),
+        "get",
+        true,
+    );
+
+    let mut content = Vec::new();
+    let read = std::fs::File::open(&path).and_then(|file| {
+        // one more byte to know if we are truncating
+        file.take(options.text_preview_max_bytes + 1)
+            .read_to_end(&mut content)
+    });
+    if let Err(err) = read {
+        error!("cannot read {:?}: {}", path, err);
+        return status_response(Status::InternalServerError);
+    }
+
+    let truncated = content.len() as u64 > options.text_preview_max_bytes;
+    content.truncate(options.text_preview_max_bytes as usize);
+
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n<body>\n<pre>",
+        escape_html(path.file_name().unwrap().to_str().unwrap())
+    );
+    // a truncated file can end in the middle of a character
+    html.push_str(&escape_html(&String::from_utf8_lossy(&content)));
+    html.push_str("</pre>\n");
+    if truncated {
+        html.push_str(&format!(
+            "<p>truncated after {} bytes</p>\n",
+            options.text_preview_max_bytes
+        ));
+    }
+    html.push_str("</body>\n</html>\n");
+
+    let mut response = status_response(Status::Ok);
+    response.set_header(ContentType::HTML);
+    // the page must never run anything, whatever the file contains
+    response.set_raw_header("Content-Security-Policy", "default-src 'none'");
+    response.set_raw_header("X-Content-Type-Options", "nosniff");
+    add_access_control_allow_origin_if_needed(&mut response, &options);
+    response.set_sized_body(Cursor::new(html));
+    response
+}
+
+/// sends any visible file as an attachment,
+/// it is how the extra files are opened
+#[get("/download/<path..>")]
+pub(crate) fn download<'r>(
+    options: State<'_, Options>,
+    statistics: State<'_, Arc<RwLock<Statistics>>>,
+    forwarded_identity: ForwardedIdentity,
+    byte_range: Option<ByteRange>,
+    path: PathBuf,
+) -> Response<'r> {
+    let path = match authorize_file(
+        &options,
+        &statistics,
+        &forwarded_identity,
+        path,
+        Permission::Full,
+    ) {
+        Ok(path) => path,
+        Err(status) => return status_response(status),
+    };
+    track_authorized_dynamic(&options, &statistics);
+
+    track_download_access(&options, &statistics);
+    options.audit(
+        &forwarded_identity.email,
+        "file",
+        path.to_str().unwrap(),
+        "download",
+        true,
+    );
+
+    debug!("sending == {:?}", &path);
+    match get_file_range(&path, &options.media_types, byte_range) {
+        Ok(mut response) => {
+            response.set_raw_header(
+                "Content-Disposition",
+                content_disposition(path.file_name().unwrap().to_str().unwrap()),
+            );
+            add_access_control_allow_origin_if_needed(&mut response, &options);
+            response
+        }
+        Err(err) => {
+            error!("cannot send {:?}: {}", path, err);
+            status_response(Status::NotFound)
+        }
+    }
+}
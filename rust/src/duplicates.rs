@@ -0,0 +1,456 @@
+use crate::api::{json_response, status_response};
+use crate::forwarded_identity::ForwardedIdentity;
+use crate::json_store::JsonStore;
+use crate::media_type::Thumbnailer;
+use crate::options::Options;
+use crate::statistics::*;
+use rocket::http::Status;
+use rocket::{Response, State};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::UNIX_EPOCH;
+
+/// the fingerprints are saved every this many files, an
+/// interrupted scan does not start from scratch
+const SAVE_EVERY_FILES: usize = 500;
+
+/// What identifies the content of a previewable file
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Fingerprint {
+    pub size: u64,
+    /// seconds since the epoch, a changed file is hashed again
+    pub modified: u64,
+    pub sha256: String,
+    /// the difference hash of the picture, None when
+    /// there is no picture to hash (audio files)
+    pub dhash: Option<u64>,
+}
+
+/// The fingerprints of the library, by host path
+pub type Fingerprints = JsonStore<BTreeMap<String, Fingerprint>>;
+
+/// Groups of files with the same content, or with
+/// pictures that look the same
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct DuplicateReport {
+    pub duplicates: Vec<Vec<String>>,
+    pub near_duplicates: Vec<Vec<String>>,
+}
+
+/// The background scan: every duplicate_scan_interval_hours
+/// the libraries are walked and the new or changed files are
+/// fingerprinted. The reports only read the stored fingerprints.
+#[derive(Debug, Clone)]
+pub struct Duplicates {
+    fingerprints: Arc<Fingerprints>,
+}
+
+impl Duplicates {
+    /// opens the fingerprints without scanning
+    pub fn open(options: &Options) -> Self {
+        Self {
+            fingerprints: Arc::new(Fingerprints::open(
+                &options.data_folder_path,
+                "fingerprints",
+            )),
+        }
+    }
+
+    /// opens the fingerprints and starts the background
+    /// scan if duplicate_scan_enabled
+    pub fn new(options: &Options) -> Self {
+        let duplicates = Self::open(options);
+
+        if options.duplicate_scan_enabled {
+            let options = options.clone();
+            let fingerprints = duplicates.fingerprints.clone();
+            thread::spawn(move || loop {
+                scan(&options, &fingerprints);
+                thread::sleep(std::time::Duration::from_secs(
+                    options.duplicate_scan_interval_hours * 60 * 60,
+                ));
+            });
+        }
+
+        duplicates
+    }
+
+    /// scans right away, in the calling thread
+    pub fn scan_now(&self, options: &Options) {
+        scan(options, &self.fingerprints);
+    }
+
+    /// the report of the files the user can open
+    pub fn report(&self, options: &Options, user: &str) -> DuplicateReport {
+        let visible = self.fingerprints.read(|fingerprints| {
+            fingerprints
+                .iter()
+                .filter(|(path, _)| {
+                    let path = PathBuf::from(path);
+                    path.is_file()
+                        && !options.hidden_files.is_hidden(&path)
+                        && options.folder_permission(&path, user).is_some()
+                })
+                .filter_map(|(path, fingerprint)| {
+                    let public_path = options.libraries.to_public(Path::new(path))?;
+                    Some((public_path, fingerprint.clone()))
+                })
+                .collect::<Vec<_>>()
+        });
+
+        DuplicateReport {
+            duplicates: exact_groups(&visible),
+            near_duplicates: near_groups(&visible, options.near_duplicate_distance),
+        }
+    }
+}
+
+/// the folders to walk
+pub(crate) fn scan_roots(options: &Options) -> Vec<PathBuf> {
+    options.libraries.content_roots(&options.folders)
+}
+
+pub(crate) fn previewable_files(options: &Options, folder: &Path, files: &mut Vec<PathBuf>) {
+    let entries = match folder.read_dir() {
+        Ok(entries) => entries,
+        Err(err) => {
+            warn!("cannot read {:?}: {}", folder, err);
+            return;
+        }
+    };
+
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| !options.hidden_files.is_hidden_child(&entry.path()))
+        .for_each(|entry| {
+            let path = entry.path();
+            // the file type does not follow the symbolic links: a
+            // linked folder might loop back to one of its ancestors
+            let is_dir = entry.file_type().is_ok_and(|file_type| file_type.is_dir());
+            if is_dir {
+                previewable_files(options, &path, files);
+            } else if !path.is_dir() && options.media_types.is_previewable(&path) {
+                files.push(path);
+            }
+        });
+}
+
+fn scan(options: &Options, fingerprints: &Fingerprints) {
+    info!("scanning for duplicates");
+    let mut files = Vec::new();
+    scan_roots(options)
+        .iter()
+        .for_each(|root| previewable_files(options, root, &mut files));
+
+    // forget the files that do not exist anymore
+    let existing = files
+        .iter()
+        .filter_map(|path| path.to_str())
+        .collect::<HashSet<_>>();
+    if let Err(err) = fingerprints
+        .update(|fingerprints| fingerprints.retain(|path, _| existing.contains(path.as_str())))
+    {
+        error!("cannot save the fingerprints: {}", err);
+        return;
+    }
+
+    let mut computed = Vec::new();
+    files.iter().for_each(|path| {
+        let metadata = match path.metadata() {
+            Ok(metadata) => metadata,
+            Err(_) => return,
+        };
+        let size = metadata.len();
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map(|modified| modified.as_secs())
+            .unwrap_or(0);
+
+        let path_str = match path.to_str() {
+            Some(path_str) => path_str,
+            None => return,
+        };
+        let unchanged = fingerprints.read(|fingerprints| {
+            fingerprints.get(path_str).is_some_and(|fingerprint| {
+                fingerprint.size == size && fingerprint.modified == modified
+            })
+        });
+        if unchanged {
+            return;
+        }
+
+        trace!("fingerprinting {:?}", path);
+        let sha256 = match sha256_of(path) {
+            Ok(sha256) => sha256,
+            Err(err) => {
+                warn!("cannot hash {:?}: {}", path, err);
+                return;
+            }
+        };
+        computed.push((
+            path_str.to_owned(),
+            Fingerprint {
+                size,
+                modified,
+                sha256,
+                dhash: dhash_of(options, path),
+            },
+        ));
+
+        if computed.len() >= SAVE_EVERY_FILES {
+            save(fingerprints, &mut computed);
+        }
+    });
+    save(fingerprints, &mut computed);
+    info!("duplicate scan completed, {} files", files.len());
+}
+
+fn save(fingerprints: &Fingerprints, computed: &mut Vec<(String, Fingerprint)>) {
+    if computed.is_empty() {
+        return;
+    }
+    if let Err(err) = fingerprints.update(|fingerprints| fingerprints.extend(computed.drain(..))) {
+        error!("cannot save the fingerprints: {}", err);
+    }
+}
+
+/// the SHA-256 of the content, computed by sha256sum
+fn sha256_of(path: &Path) -> Result<String, String> {
+    let mut cmd = Command::new("sha256sum");
+    let cmd = cmd.arg(path);
+    trace!("{:#?}", cmd);
+
+    let output = cmd.output().map_err(|err| err.to_string())?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).into_owned());
+    }
+    String::from_utf8_lossy(&output.stdout)
+        .split_whitespace()
+        .next()
+        .filter(|sha256| sha256.len() == 64)
+        .map(|sha256| sha256.to_owned())
+        .ok_or_else(|| "unexpected sha256sum output".to_owned())
+}
+
+/// the difference hash: the picture is reduced to 9x8 gray
+/// pixels and every bit tells if a pixel is brighter than the
+/// one on its right. Resized or recompressed copies keep
+/// (almost) the same hash
+fn dhash_of(options: &Options, path: &Path) -> Option<u64> {
+    let media_type = options.media_types.lookup(path)?;
+    let pixels = match media_type.thumbnailer() {
+        Thumbnailer::Picture => gray_pixels(&format!("{}[0]", path.to_str()?), None),
+        Thumbnailer::EmbeddedPreview => {
+            let preview = ["JpgFromRaw", "PreviewImage", "ThumbnailImage"]
+                .iter()
+                .find_map(|tag| {
+                    let output = Command::new("exiftool")
+                        .args(["-b", &format!("-{}", tag), path.to_str()?])
+                        .output()
+                        .ok()?;
+                    if output.status.success() && !output.stdout.is_empty() {
+                        Some(output.stdout)
+                    } else {
+                        None
+                    }
+                })?;
+            gray_pixels("-", Some(&preview))
+        }
+        Thumbnailer::Video => {
+            let mut cmd = Command::new("ffmpeg");
+            let cmd = cmd.args([
+                "-v",
+                "error",
+                "-ss",
+                &options.video_thumb_seek_seconds.to_string(),
+                "-i",
+                path.to_str()?,
+                "-frames:v",
+                "1",
+                "-vf",
+                "scale=9:8,format=gray",
+                "-f",
+                "rawvideo",
+                "-",
+            ]);
+            trace!("{:#?}", cmd);
+            cmd.output().ok().map(|output| output.stdout)
+        }
+        Thumbnailer::CoverArt | Thumbnailer::FirstPage => None,
+    }?;
+
+    if pixels.len() != 72 {
+        debug!("cannot compute the dhash of {:?}", path);
+        return None;
+    }
+
+    let mut hash = 0u64;
+    pixels.chunks(9).for_each(|row| {
+        row.windows(2).for_each(|pair| {
+            hash = (hash << 1) | u64::from(pair[0] > pair[1]);
+        });
+    });
+    Some(hash)
+}
+
+/// runs ImageMagick to get the 9x8 gray pixels, from
+/// the file or from the bytes sent to its input
+fn gray_pixels(input: &str, bytes: Option<&[u8]>) -> Option<Vec<u8>> {
+    let mut cmd = Command::new("convert");
+    let cmd = cmd
+        .args([
+            input,
+            "-auto-orient",
+            "-colorspace",
+            "Gray",
+            "-resize",
+            "9x8!",
+            "-depth",
+            "8",
+            "gray:-",
+        ])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null());
+    trace!("{:#?}", cmd);
+
+    let mut child = cmd.spawn().ok()?;
+    if let Some(bytes) = bytes {
+        child.stdin.take()?.write_all(bytes).ok()?;
+    }
+    drop(child.stdin.take());
+    let output = child.wait_with_output().ok()?;
+    if output.status.success() {
+        Some(output.stdout)
+    } else {
+        None
+    }
+}
+
+fn exact_groups(files: &[(String, Fingerprint)]) -> Vec<Vec<String>> {
+    let mut by_sha256: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+    files.iter().for_each(|(path, fingerprint)| {
+        by_sha256
+            .entry(&fingerprint.sha256)
+            .or_default()
+            .push(path.to_owned());
+    });
+
+    let mut groups = by_sha256
+        .into_values()
+        .filter(|group| group.len() > 1)
+        .map(|mut group| {
+            group.sort();
+            group
+        })
+        .collect::<Vec<_>>();
+    groups.sort();
+    groups
+}
+
+fn find(parents: &mut [usize], item: usize) -> usize {
+    let mut root = item;
+    while parents[root] != root {
+        root = parents[root];
+    }
+    parents[item] = root;
+    root
+}
+
+/// the files whose hashes differ by at most max_distance bits.
+/// The hash is split in max_distance + 1 blocks: two close hashes
+/// have at least one equal block, so only those are compared
+fn near_groups(files: &[(String, Fingerprint)], max_distance: u32) -> Vec<Vec<String>> {
+    let hashed = files
+        .iter()
+        .filter_map(|(path, fingerprint)| Some((path, &fingerprint.sha256, fingerprint.dhash?)))
+        .collect::<Vec<_>>();
+
+    let blocks = (max_distance + 1).min(64);
+    let block_bits = 64 / blocks;
+    let block = |hash: u64, index: u32| {
+        let shift = index * block_bits;
+        let bits = if index == blocks - 1 {
+            64 - shift
+        } else {
+            block_bits
+        };
+        (hash >> shift) & (u64::MAX >> (64 - bits))
+    };
+
+    let mut parents = (0..hashed.len()).collect::<Vec<_>>();
+    let mut by_block: HashMap<(u32, u64), Vec<usize>> = HashMap::new();
+    hashed.iter().enumerate().for_each(|(index, (_, _, hash))| {
+        (0..blocks).for_each(|block_index| {
+            by_block
+                .entry((block_index, block(*hash, block_index)))
+                .or_default()
+                .push(index);
+        });
+    });
+    by_block.values().for_each(|candidates| {
+        candidates.iter().enumerate().for_each(|(position, &a)| {
+            candidates[position + 1..].iter().for_each(|&b| {
+                if (hashed[a].2 ^ hashed[b].2).count_ones() <= max_distance {
+                    let (root_a, root_b) = (find(&mut parents, a), find(&mut parents, b));
+                    parents[root_a] = root_b;
+                }
+            });
+        });
+    });
+
+    let mut groups: HashMap<usize, Vec<usize>> = HashMap::new();
+    (0..hashed.len()).for_each(|index| {
+        let root = find(&mut parents, index);
+        groups.entry(root).or_default().push(index);
+    });
+
+    // the groups of identical files are already in the exact report
+    let mut groups = groups
+        .into_values()
+        .filter(|group| {
+            group
+                .iter()
+                .any(|&index| hashed[index].1 != hashed[group[0]].1)
+        })
+        .map(|group| {
+            let mut group = group
+                .into_iter()
+                .map(|index| hashed[index].0.to_owned())
+                .collect::<Vec<_>>();
+            group.sort();
+            group
+        })
+        .collect::<Vec<_>>();
+    groups.sort();
+    groups
+}
+
+/// the duplicates among the files the admin can open
+#[get("/admin/duplicates")]
+pub(crate) fn duplicates<'r>(
+    options: State<'_, Options>,
+    statistics: State<'_, Arc<RwLock<Statistics>>>,
+    duplicates: State<'_, Duplicates>,
+    forwarded_identity: ForwardedIdentity,
+) -> Response<'r> {
+    let email = &forwarded_identity.email;
+    if !options.is_admin(email) {
+        track_unauthorized_dynamic(&options, &statistics);
+        options.audit(email, "duplicates", "", "report", false);
+        return status_response(Status::Unauthorized);
+    }
+
+    track_duplicate_report(&options, &statistics);
+    options.audit(email, "duplicates", "", "report", true);
+    let report = duplicates.report(&options, email);
+
+    json_response(&options, Status::Ok, &report)
+}
@@ -0,0 +1,175 @@
+use crate::add_access_control_allow_origin_if_needed;
+use crate::api::{json_response, status_response};
+use crate::file_with_size::FileWithSize;
+use crate::forwarded_identity::ForwardedIdentity;
+use crate::json_store::JsonStore;
+use crate::libraries::rebase;
+use crate::options::Options;
+use crate::statistics::*;
+use rocket::http::Status;
+use rocket::{Response, State};
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+/// The public paths starred by every identity. Public paths
+/// survive a remount of the libraries, host paths would not.
+pub type Favorites = JsonStore<HashMap<String, BTreeSet<String>>>;
+
+/// keeps the favorites of the moved or renamed items
+pub fn move_favorites(favorites: &Favorites, from: &str, to: &str) -> std::io::Result<()> {
+    favorites.update(|favorites| {
+        favorites.values_mut().for_each(|public_paths| {
+            *public_paths = public_paths
+                .iter()
+                .map(|public_path| {
+                    rebase(public_path, from, to).unwrap_or_else(|| public_path.to_owned())
+                })
+                .collect();
+        })
+    })
+}
+
+#[put("/favorites/<path..>")]
+pub(crate) fn add_favorite<'r>(
+    options: State<'_, Options>,
+    statistics: State<'_, Arc<RwLock<Statistics>>>,
+    favorites: State<'_, Favorites>,
+    forwarded_identity: ForwardedIdentity,
+    path: PathBuf,
+) -> Response<'r> {
+    let path = match options.libraries.resolve(&path) {
+        Some(path) => path,
+        None => return status_response(Status::NotFound),
+    };
+    trace!("requesting: {:?}", &path);
+    trace!("Authenticated as {}", &forwarded_identity);
+    let is_folder_allowed = options.is_folder_allowed(&path, &forwarded_identity.email);
+    trace!("is_folder_allowed == {}", is_folder_allowed);
+
+    if !is_folder_allowed {
+        track_unauthorized_dynamic(&options, &statistics);
+        return status_response(Status::Unauthorized);
+    }
+
+    if !path.exists() || options.hidden_files.is_hidden(&path) {
+        track_authorized_not_found(&options, &statistics);
+        return status_response(Status::NotFound);
+    }
+
+    track_favorite_change(&options, &statistics);
+    let public_path = options.libraries.to_public(&path).unwrap();
+    let saved = favorites.update(|favorites| {
+        favorites
+            .entry(forwarded_identity.email.to_owned())
+            .or_default()
+            .insert(public_path);
+    });
+    if let Err(err) = saved {
+        error!("cannot save the favorites: {}", err);
+        return status_response(Status::InternalServerError);
+    }
+
+    options.audit(
+        &forwarded_identity.email,
+        "favorite",
+        path.to_str().unwrap(),
+        "add",
+        true,
+    );
+
+    let mut response = status_response(Status::NoContent);
+    add_access_control_allow_origin_if_needed(&mut response, &options);
+    response
+}
+
+/// removing a favorite needs no permission: it must
+/// work even after the access has been revoked
+#[delete("/favorites/<path..>")]
+pub(crate) fn remove_favorite<'r>(
+    options: State<'_, Options>,
+    statistics: State<'_, Arc<RwLock<Statistics>>>,
+    favorites: State<'_, Favorites>,
+    forwarded_identity: ForwardedIdentity,
+    path: PathBuf,
+) -> Response<'r> {
+    if !options.identity_allowed(&forwarded_identity) {
+        return status_response(Status::Unauthorized);
+    }
+
+    // the library might not exist anymore
+    let public_path = options
+        .libraries
+        .resolve(&path)
+        .and_then(|path| options.libraries.to_public(&path))
+        .unwrap_or_else(|| path.to_str().unwrap().to_owned());
+    trace!("public_path == {}", public_path);
+
+    track_favorite_change(&options, &statistics);
+    let saved = favorites.update(|favorites| {
+        if let Some(user_favorites) = favorites.get_mut(&forwarded_identity.email) {
+            user_favorites.remove(&public_path);
+            if user_favorites.is_empty() {
+                favorites.remove(&forwarded_identity.email);
+            }
+        }
+    });
+    if let Err(err) = saved {
+        error!("cannot save the favorites: {}", err);
+        return status_response(Status::InternalServerError);
+    }
+
+    options.audit(
+        &forwarded_identity.email,
+        "favorite",
+        &public_path,
+        "remove",
+        true,
+    );
+
+    let mut response = status_response(Status::NoContent);
+    add_access_control_allow_origin_if_needed(&mut response, &options);
+    response
+}
+
+/// lists the favorites the user can still see: the
+/// permissions are checked again at every call
+#[get("/favorites")]
+pub(crate) fn list_favorites<'r>(
+    options: State<'_, Options>,
+    statistics: State<'_, Arc<RwLock<Statistics>>>,
+    favorites: State<'_, Favorites>,
+    forwarded_identity: ForwardedIdentity,
+) -> Response<'r> {
+    if !options.identity_allowed(&forwarded_identity) {
+        return status_response(Status::Unauthorized);
+    }
+    track_favorite_list(&options, &statistics);
+
+    let public_paths = favorites
+        .read(|favorites| favorites.get(&forwarded_identity.email).cloned())
+        .unwrap_or_default();
+
+    let items = public_paths
+        .iter()
+        .filter_map(|public_path| {
+            let path = options.libraries.resolve(Path::new(public_path))?;
+            if !path.exists()
+                || options.hidden_files.is_hidden(&path)
+                || !options.is_folder_allowed(&path, &forwarded_identity.email)
+            {
+                return None;
+            }
+
+            Some(if path.is_dir() {
+                FileWithSize::without_size(public_path.to_owned())
+            } else {
+                FileWithSize::with_size(public_path.to_owned(), path.metadata().ok()?.len())
+            })
+        })
+        .collect::<Vec<_>>();
+
+    options.audit(&forwarded_identity.email, "favorite", "", "list", true);
+
+    json_response(&options, Status::Ok, &items)
+}
@@ -1,9 +1,24 @@
+use crate::annotations::Annotation;
+use crate::folder_metadata::{FolderMetadata, SortOrder};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct FileWithSize {
     pub path: String,
     pub size: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cover: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sort_order: Option<SortOrder>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tags: Option<BTreeSet<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub caption: Option<String>,
 }
 
 impl FileWithSize {
@@ -11,10 +26,41 @@ impl FileWithSize {
         Self {
             path,
             size: Some(size),
+            title: None,
+            description: None,
+            cover: None,
+            sort_order: None,
+            tags: None,
+            caption: None,
         }
     }
 
     pub fn without_size(path: String) -> Self {
-        Self { path, size: None }
+        Self {
+            path,
+            size: None,
+            title: None,
+            description: None,
+            cover: None,
+            sort_order: None,
+            tags: None,
+            caption: None,
+        }
+    }
+
+    pub fn with_annotation(mut self, annotation: Option<Annotation>) -> Self {
+        if let Some(annotation) = annotation {
+            self.tags = Some(annotation.tags).filter(|tags| !tags.is_empty());
+            self.caption = annotation.caption;
+        }
+        self
+    }
+
+    pub fn with_folder_metadata(mut self, metadata: FolderMetadata, cover: Option<String>) -> Self {
+        self.title = metadata.title;
+        self.description = metadata.description;
+        self.sort_order = metadata.sort_order;
+        self.cover = cover;
+        self
     }
 }
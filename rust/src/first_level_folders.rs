@@ -0,0 +1,33 @@
+use crate::options::Options;
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+
+/// The first level folders of every user. Since rules and
+/// group memberships can be limited in time the result
+/// is recalculated whenever one of them starts or expires.
+#[derive(Debug, Clone)]
+pub struct FirstLevelFolders {
+    calculated_at: DateTime<Utc>,
+    by_email: HashMap<String, Vec<String>>,
+}
+
+impl FirstLevelFolders {
+    pub fn calculate(options: &Options) -> Self {
+        let calculated_at = Utc::now();
+        let by_email = options.calculate_first_level_folders_for_every_user();
+        debug!("first_folders_by_email == {:#?}", by_email);
+
+        Self {
+            calculated_at,
+            by_email,
+        }
+    }
+
+    pub fn is_stale(&self, options: &Options) -> bool {
+        options.acl_changed_between(&self.calculated_at, &Utc::now())
+    }
+
+    pub fn get(&self, email: &str) -> Option<&Vec<String>> {
+        self.by_email.get(email)
+    }
+}
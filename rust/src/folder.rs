@@ -1,3 +1,5 @@
+use crate::permission::{Capability, Permission};
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
 
@@ -8,6 +10,35 @@ pub struct Folder {
     pub breaks_inheritance: Option<bool>,
     pub allowed: Option<Vec<String>>,
     pub denied: Option<Vec<String>>,
+    pub permission: Option<Permission>,
+    pub capabilities: Option<Vec<Capability>>,
+    pub valid_from: Option<DateTime<Utc>>,
+    pub valid_until: Option<DateTime<Utc>>,
+    pub hidden_patterns: Option<Vec<String>>,
+}
+
+impl Folder {
+    pub fn is_valid_at(&self, instant: &DateTime<Utc>) -> bool {
+        is_valid_at(&self.valid_from, &self.valid_until, instant)
+    }
+
+    pub fn is_expired_at(&self, instant: &DateTime<Utc>) -> bool {
+        is_expired_at(&self.valid_until, instant)
+    }
+}
+
+/// a missing bound means the window is open on that side
+pub(crate) fn is_valid_at(
+    valid_from: &Option<DateTime<Utc>>,
+    valid_until: &Option<DateTime<Utc>>,
+    instant: &DateTime<Utc>,
+) -> bool {
+    valid_from.is_none_or(|valid_from| valid_from <= *instant)
+        && valid_until.is_none_or(|valid_until| *instant < valid_until)
+}
+
+pub(crate) fn is_expired_at(valid_until: &Option<DateTime<Utc>>, instant: &DateTime<Utc>) -> bool {
+    valid_until.is_some_and(|valid_until| valid_until <= *instant)
 }
 
 impl PartialEq for Folder {
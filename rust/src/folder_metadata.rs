@@ -0,0 +1,111 @@
+use crate::media_type::MediaKind;
+use crate::options::Options;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// the sidecar files, in order of precedence
+static FOLDER_METADATA_FILE_NAMES: &[&str] = &["folder.toml", ".gallery.json"];
+
+/// How the content of a folder should be shown
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    Name,
+    NameDesc,
+    Date,
+    DateDesc,
+}
+
+/// The optional description of a folder, read from
+/// a folder.toml or .gallery.json file in the folder
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct FolderMetadata {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    /// the file name of a picture of the folder
+    pub cover: Option<String>,
+    pub sort_order: Option<SortOrder>,
+}
+
+impl FolderMetadata {
+    /// returns None if the folder has no sidecar or
+    /// if it cannot be parsed, the error is logged
+    pub fn load(folder: &Path) -> Option<Self> {
+        let sidecar = FOLDER_METADATA_FILE_NAMES
+            .iter()
+            .map(|file_name| folder.join(file_name))
+            .find(|sidecar| sidecar.is_file())?;
+        trace!("reading folder metadata from {:?}", sidecar);
+
+        let content = match std::fs::read_to_string(&sidecar) {
+            Ok(content) => content,
+            Err(err) => {
+                warn!("cannot read {:?}: {}", sidecar, err);
+                return None;
+            }
+        };
+
+        let parsed = if sidecar
+            .extension()
+            .is_some_and(|extension| extension == "json")
+        {
+            serde_json::from_str::<Self>(&content).map_err(|err| err.to_string())
+        } else {
+            toml::from_str::<Self>(&content).map_err(|err| err.to_string())
+        };
+
+        match parsed {
+            Ok(metadata) => Some(metadata),
+            Err(err) => {
+                warn!("cannot parse {:?}: {}", sidecar, err);
+                None
+            }
+        }
+    }
+
+    /// returns the cover of the folder: the one of the sidecar,
+    /// if it is a visible media file of the folder, otherwise
+    /// the first picture (or the first media file) by name
+    pub fn cover_path(&self, options: &Options, folder: &Path) -> Option<PathBuf> {
+        let chosen = self
+            .cover
+            .as_ref()
+            // only files of the folder itself, so they
+            // share the permissions of the folder
+            .filter(|cover| !cover.contains('/') && cover.as_str() != "..")
+            .map(|cover| folder.join(cover))
+            .filter(|cover| is_visible_media(options, cover));
+
+        match chosen {
+            Some(cover) => Some(cover),
+            None => {
+                let mut candidates = folder
+                    .read_dir()
+                    .ok()?
+                    .filter_map(|entry| entry.ok())
+                    .map(|entry| entry.path())
+                    .filter(|path| is_visible_media(options, path))
+                    .collect::<Vec<_>>();
+                candidates.sort();
+
+                let is_image = |path: &&PathBuf| {
+                    options
+                        .media_types
+                        .lookup(path)
+                        .is_some_and(|media_type| media_type.kind == MediaKind::Image)
+                };
+                candidates
+                    .iter()
+                    .find(is_image)
+                    .or_else(|| candidates.first())
+                    .cloned()
+            }
+        }
+    }
+}
+
+fn is_visible_media(options: &Options, path: &Path) -> bool {
+    path.is_file()
+        && !options.hidden_files.is_hidden(path)
+        && options.media_types.is_previewable(path)
+}
@@ -0,0 +1,229 @@
+use crate::folder::Folder;
+use glob::Pattern;
+use std::path::{Path, PathBuf};
+
+static DEFAULT_HIDDEN_PATTERNS: &[&str] = &[
+    "@eaDir",
+    ".@__thumb",
+    "#recycle",
+    ".thumbnails",
+    "Thumbs.db",
+    ".DS_Store",
+];
+/// the trash kept inside the libraries, never shown
+pub(crate) const TRASH_FOLDER_NAME: &str = ".nas_gallery_trash";
+/// our own files, hidden even when hidden_patterns
+/// replaces the default patterns
+static ALWAYS_HIDDEN_PATTERNS: &[&str] =
+    &[TRASH_FOLDER_NAME, "folder.toml", ".gallery.json", "*.xmp"];
+static DEFAULT_HIDE_MARKERS: &[&str] = &[".nomedia", ".nogallery"];
+
+/// Decides which files and directories must not be shown.
+/// The patterns are matched against the file name: the global
+/// ones apply everywhere, the ones of a folder rule apply
+/// to that folder and its subfolders. A directory containing
+/// one of the marker files is hidden too, and everything
+/// inside a hidden directory is hidden.
+#[derive(Clone, Debug, Default)]
+pub struct HiddenFiles {
+    global: Vec<Pattern>,
+    by_folder: Vec<(PathBuf, Vec<Pattern>)>,
+    markers: Vec<String>,
+    /// the ancestors are checked up to these folders
+    roots: Vec<PathBuf>,
+    invalid_patterns: Vec<String>,
+}
+
+impl HiddenFiles {
+    pub fn new(
+        hidden_patterns: Option<&[String]>,
+        hide_markers: Option<&[String]>,
+        folders: &[Folder],
+        roots: Vec<PathBuf>,
+    ) -> Self {
+        let mut invalid_patterns = Vec::new();
+
+        let mut global = match hidden_patterns {
+            Some(hidden_patterns) => compile(hidden_patterns.iter(), &mut invalid_patterns),
+            None => compile(DEFAULT_HIDDEN_PATTERNS.iter(), &mut invalid_patterns),
+        };
+        global.extend(
+            ALWAYS_HIDDEN_PATTERNS
+                .iter()
+                .map(|pattern| Pattern::new(pattern).unwrap()),
+        );
+
+        let by_folder = folders
+            .iter()
+            .filter_map(|folder| {
+                folder.hidden_patterns.as_ref().map(|hidden_patterns| {
+                    (
+                        PathBuf::from(&folder.path),
+                        compile(hidden_patterns.iter(), &mut invalid_patterns),
+                    )
+                })
+            })
+            .collect::<_>();
+
+        let markers = match hide_markers {
+            Some(hide_markers) => hide_markers.to_vec(),
+            None => DEFAULT_HIDE_MARKERS
+                .iter()
+                .map(|marker| (*marker).to_owned())
+                .collect(),
+        };
+
+        Self {
+            global,
+            by_folder,
+            markers,
+            roots,
+            invalid_patterns,
+        }
+    }
+
+    pub fn invalid_patterns(&self) -> &[String] {
+        &self.invalid_patterns
+    }
+
+    /// checks the path and its ancestors below the library
+    /// (or the outermost rule) containing it
+    pub fn is_hidden(&self, path: &Path) -> bool {
+        let root = self
+            .roots
+            .iter()
+            .filter(|root| path.starts_with(root))
+            .max_by_key(|root| root.as_os_str().len());
+
+        path.ancestors()
+            .take_while(|ancestor| Some(*ancestor) != root.map(|root| root.as_path()))
+            .any(|ancestor| self.is_hidden_entry(ancestor))
+    }
+
+    /// checks the entry of a folder already known to be
+    /// visible: only its name and its markers, not the ancestors
+    pub fn is_hidden_child(&self, path: &Path) -> bool {
+        self.is_hidden_entry(path)
+    }
+
+    /// checks the name of the path only
+    fn is_hidden_entry(&self, path: &Path) -> bool {
+        let file_name = match path.file_name().and_then(|file_name| file_name.to_str()) {
+            Some(file_name) => file_name,
+            None => return false,
+        };
+
+        if self.global.iter().any(|pattern| pattern.matches(file_name)) {
+            return true;
+        }
+
+        if self
+            .by_folder
+            .iter()
+            .filter(|(folder, _)| path.starts_with(folder))
+            .any(|(_, patterns)| patterns.iter().any(|pattern| pattern.matches(file_name)))
+        {
+            return true;
+        }
+
+        self.has_marker(path)
+    }
+
+    /// returns true if the path is a directory
+    /// containing one of the marker files
+    pub fn has_marker(&self, path: &Path) -> bool {
+        path.is_dir() && self.markers.iter().any(|marker| path.join(marker).exists())
+    }
+}
+
+fn compile<'a, I, S>(patterns: I, invalid_patterns: &mut Vec<String>) -> Vec<Pattern>
+where
+    I: Iterator<Item = &'a S>,
+    S: AsRef<str> + ?Sized + 'a,
+{
+    patterns
+        .filter_map(|pattern| match Pattern::new(pattern.as_ref()) {
+            Ok(compiled) => Some(compiled),
+            Err(_) => {
+                invalid_patterns.push(pattern.as_ref().to_owned());
+                None
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hidden_files(folders: &[Folder], root: &Path) -> HiddenFiles {
+        HiddenFiles::new(None, None, folders, vec![root.to_path_buf()])
+    }
+
+    #[test]
+    fn hidden_folder_hides_its_content() {
+        let hidden_files = hidden_files(&[], Path::new("/lib"));
+
+        assert!(hidden_files.is_hidden(Path::new("/lib/@eaDir")));
+        assert!(hidden_files.is_hidden(Path::new("/lib/a/@eaDir/b/c.jpg")));
+        assert!(!hidden_files.is_hidden(Path::new("/lib/a/b/c.jpg")));
+        assert!(!hidden_files.is_hidden(Path::new("/lib")));
+    }
+
+    #[test]
+    fn sidecars_are_hidden_with_custom_patterns() {
+        let hidden_patterns = vec!["raw".to_owned()];
+        let hidden_files = HiddenFiles::new(
+            Some(&hidden_patterns),
+            None,
+            &[],
+            vec![PathBuf::from("/lib")],
+        );
+
+        assert!(hidden_files.is_hidden(Path::new("/lib/raw")));
+        assert!(hidden_files.is_hidden(Path::new("/lib/a/folder.toml")));
+        assert!(hidden_files.is_hidden(Path::new("/lib/a/.gallery.json")));
+        assert!(hidden_files.is_hidden(Path::new("/lib/a/b.jpg.xmp")));
+        assert!(hidden_files.is_hidden(Path::new("/lib/.nas_gallery_trash/b.jpg")));
+        assert!(!hidden_files.is_hidden(Path::new("/lib/a/@eaDir")));
+    }
+
+    #[test]
+    fn ancestors_above_the_root_are_not_checked() {
+        let hidden_files = hidden_files(&[], Path::new("/volume1/@eaDir/lib"));
+
+        assert!(!hidden_files.is_hidden(Path::new("/volume1/@eaDir/lib/a.jpg")));
+    }
+
+    #[test]
+    fn folder_patterns_apply_to_subfolders() {
+        let folders = vec![Folder {
+            path: "/lib/private".to_owned(),
+            hidden_patterns: Some(vec!["raw".to_owned()]),
+            ..Folder::default()
+        }];
+        let hidden_files = hidden_files(&folders, Path::new("/lib"));
+
+        assert!(hidden_files.is_hidden(Path::new("/lib/private/raw/a.jpg")));
+        assert!(!hidden_files.is_hidden(Path::new("/lib/raw/a.jpg")));
+        assert!(!hidden_files.is_hidden(Path::new("/lib/private2/raw/a.jpg")));
+    }
+
+    #[test]
+    fn marker_hides_the_subfolders() {
+        let root =
+            std::env::temp_dir().join(format!("nas_gallery_hidden_files_{}", std::process::id()));
+        std::fs::create_dir_all(root.join("x/sub")).unwrap();
+        std::fs::create_dir_all(root.join("y")).unwrap();
+        std::fs::write(root.join("x/.nomedia"), "").unwrap();
+        let hidden_files = hidden_files(&[], &root);
+
+        assert!(hidden_files.is_hidden(&root.join("x")));
+        assert!(hidden_files.is_hidden(&root.join("x/sub/a.jpg")));
+        assert!(!hidden_files.is_hidden(&root.join("y/a.jpg")));
+        assert!(hidden_files.is_hidden_child(&root.join("x")));
+        assert!(!hidden_files.is_hidden_child(&root.join("y")));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
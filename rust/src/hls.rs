@@ -0,0 +1,631 @@
+use crate::api::{authorize_video, status_response};
+use crate::forwarded_identity::ForwardedIdentity;
+use crate::json_store::unique_id;
+use crate::options::Options;
+use crate::permission::Permission;
+use crate::statistics::*;
+use crate::video_probe::VideoInfo;
+use crate::{add_access_control_allow_origin_if_needed, encode_path, generate_cache_folder_path};
+use rocket::http::{ContentType, Status};
+use rocket::{Response, State};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+
+static SOURCE_VARIANT: &str = "source";
+static EVICTION_LOCK: Mutex<()> = Mutex::new(());
+/// the source segments of a video are all written by a single
+/// ffmpeg, the players of the same video wait for it
+static SEGMENTING_LOCKS: Mutex<BTreeMap<PathBuf, Arc<Mutex<()>>>> = Mutex::new(BTreeMap::new());
+/// the bytes written since the last eviction, the cache is only
+/// walked once they reach a sixteenth of hls_cache_max_bytes
+static GENERATED_BYTES: AtomicU64 = AtomicU64::new(0);
+
+/// How the videos are converted to HLS segments
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HlsMode {
+    /// copies every stream as it is
+    Remux,
+    /// copies the video, converts the audio to AAC
+    Copy,
+    /// converts to H.264/AAC at every height of the ladder
+    #[default]
+    Transcode,
+}
+
+/// the variants we can offer for the video, from the best one
+fn variants(options: &Options, info: &VideoInfo) -> Vec<String> {
+    match options.hls_mode {
+        HlsMode::Remux | HlsMode::Copy => vec![SOURCE_VARIANT.to_owned()],
+        HlsMode::Transcode => {
+            // never upscale, but always offer at least
+            // the smallest step of the ladder
+            let mut heights = options
+                .hls_ladder
+                .iter()
+                .filter(|height| **height <= info.height)
+                .copied()
+                .collect::<Vec<_>>();
+            if heights.is_empty() {
+                heights.extend(options.hls_ladder.iter().min().copied());
+            }
+            heights.sort_unstable_by(|a, b| b.cmp(a));
+            heights.iter().map(|height| height.to_string()).collect()
+        }
+    }
+}
+
+/// rough estimate in bits per second, used both for
+/// the encoder and for the master playlist
+fn bitrate(height: u64) -> u64 {
+    height * 4_000
+}
+
+fn segment_count(options: &Options, info: &VideoInfo) -> u64 {
+    (info.duration / options.hls_segment_seconds as f64).ceil() as u64
+}
+
+fn playlist_response<'r>(options: &Options, playlist: String) -> Response<'r> {
+    let mut response = Response::new();
+    response.set_status(Status::Ok);
+    response.set_header(ContentType::new("application", "vnd.apple.mpegurl"));
+    add_access_control_allow_origin_if_needed(&mut response, options);
+    response.set_sized_body(Cursor::new(playlist));
+    response
+}
+
+#[get("/hls/<path..>")]
+pub(crate) fn master_playlist<'r>(
+    options: State<'_, Options>,
+    statistics: State<'_, Arc<RwLock<Statistics>>>,
+    forwarded_identity: ForwardedIdentity,
+    path: PathBuf,
+) -> Response<'r> {
+    let (path, info) = match authorize_video(
+        &options,
+        &statistics,
+        &forwarded_identity,
+        path,
+        Permission::Original,
+    ) {
+        Ok(authorized) => authorized,
+        Err(status) => return status_response(status),
+    };
+
+    options.audit(
+        &forwarded_identity.email,
+        "video",
+        path.to_str().unwrap(),
+        "hls",
+        true,
+    );
+
+    let source_bitrate = path
+        .metadata()
+        .map(|metadata| (metadata.len() as f64 * 8.0 / info.duration.max(1.0)) as u64)
+        .unwrap_or_else(|_| bitrate(info.height));
+
+    let encoded_path = match encode_path(&options, &path) {
+        Some(encoded_path) => encoded_path,
+        None => return status_response(Status::NotFound),
+    };
+    let mut playlist = String::from("#EXTM3U\n#EXT-X-VERSION:3\n");
+    variants(&options, &info).iter().for_each(|variant| {
+        let (bandwidth, width, height) = match variant.parse::<u64>() {
+            Ok(height) => (
+                bitrate(height),
+                // keep the width even, as libx264 wants
+                (info.width * height / info.height.max(1)) / 2 * 2,
+                height,
+            ),
+            Err(_) => (source_bitrate, info.width, info.height),
+        };
+        playlist.push_str(&format!(
+            "#EXT-X-STREAM-INF:BANDWIDTH={},RESOLUTION={}x{}\n/hls_variant/{}/{}\n",
+            bandwidth, width, height, variant, encoded_path
+        ));
+    });
+
+    playlist_response(&options, playlist)
+}
+
+#[get("/hls_variant/<variant>/<path..>")]
+pub(crate) fn variant_playlist<'r>(
+    options: State<'_, Options>,
+    statistics: State<'_, Arc<RwLock<Statistics>>>,
+    forwarded_identity: ForwardedIdentity,
+    variant: String,
+    path: PathBuf,
+) -> Response<'r> {
+    let (path, info) = match authorize_video(
+        &options,
+        &statistics,
+        &forwarded_identity,
+        path,
+        Permission::Original,
+    ) {
+        Ok(authorized) => authorized,
+        Err(status) => return status_response(status),
+    };
+
+    if !variants(&options, &info).contains(&variant) {
+        return status_response(Status::NotFound);
+    }
+
+    let encoded_path = match encode_path(&options, &path) {
+        Some(encoded_path) => encoded_path,
+        None => return status_response(Status::NotFound),
+    };
+    let durations = match segment_durations(&options, &statistics, &path, &info) {
+        Some(durations) => durations,
+        None => return status_response(Status::InternalServerError),
+    };
+    let target_duration = durations
+        .iter()
+        .copied()
+        .fold(options.hls_segment_seconds as f64, f64::max)
+        .ceil();
+    let mut playlist = format!(
+        "#EXTM3U\n#EXT-X-VERSION:3\n#EXT-X-PLAYLIST-TYPE:VOD\n#EXT-X-TARGETDURATION:{}\n#EXT-X-MEDIA-SEQUENCE:0\n",
+        target_duration
+    );
+    durations.iter().enumerate().for_each(|(index, duration)| {
+        playlist.push_str(&format!(
+            "#EXTINF:{:.3},\n/hls_segment/{}/{}/{}\n",
+            duration, variant, index, encoded_path
+        ));
+    });
+    playlist.push_str("#EXT-X-ENDLIST\n");
+
+    playlist_response(&options, playlist)
+}
+
+#[get("/hls_segment/<variant>/<index>/<path..>")]
+pub(crate) fn segment<'r>(
+    options: State<'_, Options>,
+    statistics: State<'_, Arc<RwLock<Statistics>>>,
+    forwarded_identity: ForwardedIdentity,
+    variant: String,
+    index: u64,
+    path: PathBuf,
+) -> Response<'r> {
+    let (path, info) = match authorize_video(
+        &options,
+        &statistics,
+        &forwarded_identity,
+        path,
+        Permission::Original,
+    ) {
+        Ok(authorized) => authorized,
+        Err(status) => return status_response(status),
+    };
+
+    // the variant ends up in the cache path so
+    // it must be one of the ones we know
+    if !variants(&options, &info).contains(&variant) {
+        return status_response(Status::NotFound);
+    }
+
+    let segment_file_name = match options.hls_mode {
+        HlsMode::Transcode if index < segment_count(&options, &info) => {
+            generate_segment(&options, &statistics, &path, &variant, index)
+        }
+        HlsMode::Transcode => return status_response(Status::NotFound),
+        HlsMode::Remux | HlsMode::Copy => {
+            match generate_source_segment(&options, &statistics, &path, index) {
+                Some(segment_file_name) => segment_file_name,
+                None => return status_response(Status::NotFound),
+            }
+        }
+    };
+
+    match std::fs::File::open(&segment_file_name) {
+        Ok(file) => {
+            // the segment is open, the eviction cannot take it away
+            evict_if_needed(&options, &segment_file_name);
+            let mut response = Response::new();
+            response.set_status(Status::Ok);
+            response.set_header(ContentType::new("video", "mp2t"));
+            add_access_control_allow_origin_if_needed(&mut response, &options);
+            response.set_sized_body(file);
+            response
+        }
+        Err(err) => {
+            error!("cannot open segment {:?}: {}", segment_file_name, err);
+            status_response(Status::InternalServerError)
+        }
+    }
+}
+
+fn generate_segment(
+    options: &State<'_, Options>,
+    statistics: &State<'_, Arc<RwLock<Statistics>>>,
+    original_path: &PathBuf,
+    variant: &str,
+    index: u64,
+) -> PathBuf {
+    let output_file_name = segment_file_name(options, original_path, variant, index);
+    trace!("output_file_name == {:#?}", output_file_name);
+    track_hls_segment_access(options, statistics);
+
+    // if we already have the segment, do not regenerate it
+    if !output_file_name.exists() {
+        track_hls_segment_generation(options, statistics);
+
+        // two players might ask for the same segment at the same
+        // time so each one writes its own file and renames it once
+        // complete. The transcoding starts exactly at -ss, unlike
+        // the stream copy
+        let partial_file_name = output_file_name.with_file_name(format!(
+            "{}.{}.ts.partial",
+            output_file_name.file_stem().unwrap().to_str().unwrap(),
+            unique_id()
+        ));
+        let segment_seconds = options.hls_segment_seconds;
+        let start = (index * segment_seconds).to_string();
+        let bitrate = format!("{}k", bitrate(variant.parse::<u64>().unwrap()) / 1000);
+        let scale = format!("scale=-2:{}", variant);
+        let force_key_frames = format!("expr:gte(t,n_forced*{})", segment_seconds);
+
+        let mut cmd = Command::new("ffmpeg");
+        let cmd = cmd.args(&[
+            "-ss",
+            &start,
+            "-i",
+            original_path.to_str().unwrap(),
+            "-t",
+            &segment_seconds.to_string(),
+            "-map",
+            "0:v:0",
+            "-map",
+            "0:a:0?",
+            "-c:v",
+            "libx264",
+            "-preset",
+            "veryfast",
+            "-b:v",
+            &bitrate,
+            "-vf",
+            &scale,
+            "-force_key_frames",
+            &force_key_frames,
+            "-c:a",
+            "aac",
+            "-ac",
+            "2",
+            "-output_ts_offset",
+            &start,
+            "-f",
+            "mpegts",
+            "-y",
+            partial_file_name.to_str().unwrap(),
+        ]);
+        trace!("about to send == {:#?}", cmd);
+        let output = cmd.output().unwrap();
+        trace!("{:?}", output);
+
+        if !output.status.success() {
+            error!(
+                "ffmpeg failed to generate segment {} of {:?}: {}",
+                index,
+                original_path,
+                String::from_utf8_lossy(&output.stderr)
+            );
+            let _ = std::fs::remove_file(&partial_file_name);
+        } else if let Err(err) = std::fs::rename(&partial_file_name, &output_file_name) {
+            error!("cannot save segment {:?}: {}", output_file_name, err);
+            let _ = std::fs::remove_file(&partial_file_name);
+        } else {
+            count_generated(&output_file_name);
+        }
+    }
+
+    output_file_name
+}
+
+fn segment_file_name(
+    options: &Options,
+    original_path: &PathBuf,
+    variant: &str,
+    index: u64,
+) -> PathBuf {
+    generate_cache_folder_path(options, &format!("hls/{}", variant), original_path).join(format!(
+        "{}.{}.ts",
+        original_path.file_name().unwrap().to_str().unwrap(),
+        index
+    ))
+}
+
+/// the folder holding the copied segments of a video and their
+/// list. It is replaced as a whole when they are cut again
+fn source_segments_folder(options: &Options, original_path: &PathBuf) -> PathBuf {
+    generate_cache_folder_path(options, &format!("hls/{}", SOURCE_VARIANT), original_path).join(
+        format!(
+            "{}.segments",
+            original_path.file_name().unwrap().to_str().unwrap()
+        ),
+    )
+}
+
+/// the list of the source segments written by ffmpeg,
+/// one "file name,start,end" line for each of them
+fn segment_list_file_name(segments_folder: &Path) -> PathBuf {
+    segments_folder.join("segments.csv")
+}
+
+/// the duration of every segment of the variants: the transcoded
+/// ones are cut every hls_segment_seconds, the copied streams
+/// can only be cut on their key frames
+fn segment_durations(
+    options: &State<'_, Options>,
+    statistics: &State<'_, Arc<RwLock<Statistics>>>,
+    original_path: &PathBuf,
+    info: &VideoInfo,
+) -> Option<Vec<f64>> {
+    match options.hls_mode {
+        HlsMode::Transcode => {
+            let segment_seconds = options.hls_segment_seconds as f64;
+            Some(
+                (0..segment_count(options, info))
+                    .map(|index| {
+                        (info.duration - index as f64 * segment_seconds).min(segment_seconds)
+                    })
+                    .collect(),
+            )
+        }
+        HlsMode::Remux | HlsMode::Copy => {
+            let segment_list_file_name =
+                segment_list_file_name(&source_segments_folder(options, original_path));
+            generate_source_segments(options, statistics, original_path, || {
+                segment_list_file_name.exists()
+            });
+            read_segment_list(&segment_list_file_name)
+        }
+    }
+}
+
+fn read_segment_list(segment_list_file_name: &Path) -> Option<Vec<f64>> {
+    let segment_list = std::fs::read_to_string(segment_list_file_name).ok()?;
+    segment_list
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            // the file name comes first and might hold commas
+            let mut fields = line.rsplitn(3, ',');
+            let end = fields.next()?.parse::<f64>().ok()?;
+            let start = fields.next()?.parse::<f64>().ok()?;
+            Some(end - start)
+        })
+        .collect()
+}
+
+/// the copied segments are all cut by the same ffmpeg, they
+/// are cut again together if one of them was evicted
+fn generate_source_segment(
+    options: &State<'_, Options>,
+    statistics: &State<'_, Arc<RwLock<Statistics>>>,
+    original_path: &PathBuf,
+    index: u64,
+) -> Option<PathBuf> {
+    let segments_folder = source_segments_folder(options, original_path);
+    let output_file_name = segments_folder.join(format!("{}.ts", index));
+    trace!("output_file_name == {:#?}", output_file_name);
+    track_hls_segment_access(options, statistics);
+
+    // an index past the end must not cut the video again
+    let segment_list_file_name = segment_list_file_name(&segments_folder);
+    let is_listed = || {
+        read_segment_list(&segment_list_file_name)
+            .is_some_and(|durations| index < durations.len() as u64)
+    };
+    generate_source_segments(options, statistics, original_path, || {
+        segment_list_file_name.exists() && (output_file_name.exists() || !is_listed())
+    });
+
+    if is_listed() && output_file_name.exists() {
+        Some(output_file_name)
+    } else {
+        None
+    }
+}
+
+/// cuts the whole video in a fresh folder then swaps it in: the
+/// players streaming the previous segments keep reading them
+fn generate_source_segments(
+    options: &State<'_, Options>,
+    statistics: &State<'_, Arc<RwLock<Statistics>>>,
+    original_path: &PathBuf,
+    is_done: impl Fn() -> bool,
+) {
+    if is_done() {
+        return;
+    }
+    let lock = SEGMENTING_LOCKS
+        .lock()
+        .unwrap()
+        .entry(original_path.to_owned())
+        .or_default()
+        .clone();
+    {
+        let _lock = lock.lock().unwrap();
+        // another player might have asked for them meanwhile
+        if !is_done() {
+            cut_source_segments(options, statistics, original_path);
+        }
+    }
+    drop(lock);
+    SEGMENTING_LOCKS
+        .lock()
+        .unwrap()
+        .retain(|_, lock| Arc::strong_count(lock) > 1);
+}
+
+fn cut_source_segments(
+    options: &State<'_, Options>,
+    statistics: &State<'_, Arc<RwLock<Statistics>>>,
+    original_path: &PathBuf,
+) {
+    track_hls_segment_generation(options, statistics);
+
+    let segments_folder = source_segments_folder(options, original_path);
+    let folder_name = segments_folder.file_name().unwrap().to_str().unwrap();
+    let partial_folder =
+        segments_folder.with_file_name(format!("{}.{}.partial", folder_name, unique_id()));
+    if let Err(err) = std::fs::create_dir_all(&partial_folder) {
+        error!("cannot create {:?}: {}", partial_folder, err);
+        return;
+    }
+    // ffmpeg numbers the segments itself
+    let segment_pattern = format!(
+        "{}/%d.ts",
+        partial_folder.to_str().unwrap().replace('%', "%%")
+    );
+    let mut cmd = Command::new("ffmpeg");
+    let mut cmd = cmd.args(&[
+        "-i",
+        original_path.to_str().unwrap(),
+        "-map",
+        "0:v:0",
+        "-map",
+        "0:a:0?",
+    ]);
+    cmd = match options.hls_mode {
+        HlsMode::Copy => cmd.args(&["-c:v", "copy", "-c:a", "aac", "-ac", "2"]),
+        _ => cmd.args(&["-c", "copy"]),
+    };
+    let cmd = cmd.args(&[
+        "-f",
+        "segment",
+        "-segment_time",
+        &options.hls_segment_seconds.to_string(),
+        "-segment_format",
+        "mpegts",
+        "-segment_list_type",
+        "csv",
+        "-segment_list",
+        segment_list_file_name(&partial_folder).to_str().unwrap(),
+        "-y",
+        &segment_pattern,
+    ]);
+    trace!("about to send == {:#?}", cmd);
+    let output = match cmd.output() {
+        Ok(output) => output,
+        Err(err) => {
+            error!("cannot run ffmpeg: {}", err);
+            let _ = std::fs::remove_dir_all(&partial_folder);
+            return;
+        }
+    };
+    trace!("{:?}", output);
+
+    if !output.status.success() {
+        error!(
+            "ffmpeg failed to segment {:?}: {}",
+            original_path,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        let _ = std::fs::remove_dir_all(&partial_folder);
+        return;
+    }
+
+    // the previous folder is renamed away first, a
+    // folder cannot replace another one in one rename
+    let previous_folder =
+        segments_folder.with_file_name(format!("{}.{}.old", folder_name, unique_id()));
+    let has_previous = std::fs::rename(&segments_folder, &previous_folder).is_ok();
+    match std::fs::rename(&partial_folder, &segments_folder) {
+        Ok(_) => (0..)
+            .map(|index| segments_folder.join(format!("{}.ts", index)))
+            .take_while(|segment_file_name| segment_file_name.exists())
+            .for_each(|segment_file_name| count_generated(&segment_file_name)),
+        Err(err) => {
+            error!("cannot save the segments of {:?}: {}", original_path, err);
+            let _ = std::fs::remove_dir_all(&partial_folder);
+        }
+    }
+    if has_previous {
+        let _ = std::fs::remove_dir_all(&previous_folder);
+    }
+}
+
+fn count_generated(path: &Path) {
+    if let Ok(metadata) = path.metadata() {
+        GENERATED_BYTES.fetch_add(metadata.len(), Ordering::Relaxed);
+    }
+}
+
+fn evict_if_needed(options: &Options, serving: &Path) {
+    let threshold = (options.hls_cache_max_bytes / 16).max(1);
+    if GENERATED_BYTES.load(Ordering::Relaxed) >= threshold
+        && GENERATED_BYTES.swap(0, Ordering::Relaxed) >= threshold
+    {
+        evict(options, serving);
+    }
+}
+
+/// removes the oldest segments until the cache fits in
+/// hls_cache_max_bytes, but the one being served
+fn evict(options: &Options, serving: &Path) {
+    let _lock = EVICTION_LOCK.lock().unwrap();
+
+    let mut segments = Vec::new();
+    collect_files(
+        &Path::new(&options.thumb_folder_path).join("hls"),
+        &mut segments,
+    );
+    // the segments still being written are not counted
+    let is_partial = |path: &Path| {
+        path.extension()
+            .is_some_and(|extension| extension == "partial")
+    };
+    segments.retain(|(path, _, _)| {
+        path != serving
+            && path.extension().is_some_and(|extension| extension == "ts")
+            && !path.parent().is_some_and(is_partial)
+    });
+
+    let mut total_size: u64 = segments.iter().map(|(_, size, _)| size).sum();
+    debug!(
+        "HLS cache size == {}, max == {}",
+        total_size, options.hls_cache_max_bytes
+    );
+    if total_size <= options.hls_cache_max_bytes {
+        return;
+    }
+
+    segments.sort_by_key(|(_, _, modified)| *modified);
+    for (path, size, _) in segments {
+        if total_size <= options.hls_cache_max_bytes {
+            break;
+        }
+        debug!("evicting {:?}", path);
+        match std::fs::remove_file(&path) {
+            Ok(_) => total_size -= size,
+            Err(err) => warn!("cannot evict {:?}: {}", path, err),
+        }
+    }
+}
+
+fn collect_files(path: &Path, files: &mut Vec<(PathBuf, u64, std::time::SystemTime)>) {
+    let entries = match path.read_dir() {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+
+    entries.filter_map(|entry| entry.ok()).for_each(|entry| {
+        let path = entry.path();
+        if path.is_dir() {
+            collect_files(&path, files);
+        } else if let Ok(metadata) = entry.metadata() {
+            let modified = metadata
+                .modified()
+                .unwrap_or(std::time::SystemTime::UNIX_EPOCH);
+            files.push((path, metadata.len(), modified));
+        }
+    });
+}
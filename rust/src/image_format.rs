@@ -0,0 +1,73 @@
+use rocket::http::Status;
+use rocket::request::{FromRequest, Request};
+use rocket::Outcome;
+use serde::{Deserialize, Serialize};
+
+/// The format of the images we generate
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageFormat {
+    #[default]
+    Jpeg,
+    Webp,
+    Avif,
+}
+
+impl ImageFormat {
+    /// the extension used in the cache, ImageMagick
+    /// picks the encoder from it
+    pub fn extension(self) -> &'static str {
+        match self {
+            ImageFormat::Jpeg => "jpg",
+            ImageFormat::Webp => "webp",
+            ImageFormat::Avif => "avif",
+        }
+    }
+}
+
+/// The image formats the browser declared
+/// in the Accept header, besides JPEG
+#[derive(Debug, Copy, Clone, Default)]
+pub struct AcceptedImageFormats {
+    pub webp: bool,
+    pub avif: bool,
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for AcceptedImageFormats {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> Outcome<Self, (Status, Self::Error), ()> {
+        let mut accepted = AcceptedImageFormats::default();
+
+        request
+            .headers()
+            .get("Accept")
+            .flat_map(|accept| accept.split(','))
+            .filter_map(|media_range| {
+                // we do not rank the formats, but a zero
+                // weight means the browser refuses it
+                let mut parameters = media_range.split(';');
+                let media_range = parameters.next().unwrap().trim();
+                let refused = parameters.any(|parameter| {
+                    let mut tokens = parameter.splitn(2, '=');
+                    tokens.next().unwrap().trim() == "q"
+                        && tokens
+                            .next()
+                            .and_then(|weight| weight.trim().parse::<f32>().ok())
+                            .is_some_and(|weight| weight <= 0.0)
+                });
+                if refused {
+                    None
+                } else {
+                    Some(media_range)
+                }
+            })
+            .for_each(|media_range| match media_range {
+                "image/webp" => accepted.webp = true,
+                "image/avif" => accepted.avif = true,
+                _ => {}
+            });
+
+        Outcome::Success(accepted)
+    }
+}
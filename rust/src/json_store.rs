@@ -0,0 +1,76 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+static ID_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// a key that stays unique across restarts
+pub fn unique_id() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    format!(
+        "{:x}-{:x}",
+        nanos,
+        ID_COUNTER.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+/// A small persistent state, kept in memory and saved as a
+/// JSON file under data_folder_path after every change. The
+/// file is replaced atomically so a crash never truncates it.
+#[derive(Debug)]
+pub struct JsonStore<T> {
+    file_name: PathBuf,
+    data: RwLock<T>,
+}
+
+impl<T> JsonStore<T>
+where
+    T: Serialize + DeserializeOwned + Default,
+{
+    /// starts empty if the file does not exist yet. A file that
+    /// cannot be parsed stops the program, we must not overwrite it
+    pub fn open(data_folder_path: &str, name: &str) -> Self {
+        let file_name = Path::new(data_folder_path).join(format!("{}.json", name));
+
+        let data = if file_name.exists() {
+            let content = std::fs::read_to_string(&file_name)
+                .unwrap_or_else(|err| panic!("cannot read {:?}: {}", file_name, err));
+            serde_json::from_str(&content)
+                .unwrap_or_else(|err| panic!("cannot parse {:?}: {}", file_name, err))
+        } else {
+            debug!("{:?} does not exist, starting empty", file_name);
+            T::default()
+        };
+
+        Self {
+            file_name,
+            data: RwLock::new(data),
+        }
+    }
+
+    pub fn read<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+        f(&self.data.read().unwrap())
+    }
+
+    /// changes the data and saves it, the lock is held
+    /// until the file is written so saves never interleave
+    pub fn update<R>(&self, f: impl FnOnce(&mut T) -> R) -> std::io::Result<R> {
+        let mut data = self.data.write().unwrap();
+        let result = f(&mut data);
+
+        if let Some(parent) = self.file_name.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let temp_file_name = self.file_name.with_extension("json.tmp");
+        std::fs::write(&temp_file_name, serde_json::to_string(&*data)?)?;
+        std::fs::rename(&temp_file_name, &self.file_name)?;
+
+        Ok(result)
+    }
+}
@@ -0,0 +1,119 @@
+use crate::folder::Folder;
+use std::collections::BTreeMap;
+use std::path::{Component, Path, PathBuf};
+
+/// The named roots exposed to the clients. URLs and JSON use
+/// `library/relative/path` instead of the path on the host,
+/// the folder rules keep using the host paths. Without any
+/// library the host paths are exposed as they are.
+#[derive(Clone, Debug, Default)]
+pub struct Libraries {
+    roots: Vec<(String, PathBuf)>,
+}
+
+impl Libraries {
+    pub fn new(libraries: Option<&BTreeMap<String, String>>) -> Self {
+        let roots = libraries
+            .map(|libraries| {
+                libraries
+                    .iter()
+                    .map(|(name, root)| (name.to_owned(), PathBuf::from(root)))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Self { roots }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.roots.is_empty()
+    }
+
+    pub fn roots(&self) -> impl Iterator<Item = (&str, &Path)> {
+        self.roots
+            .iter()
+            .map(|(name, root)| (name.as_str(), root.as_path()))
+    }
+
+    /// the folders holding the media: the libraries or,
+    /// without them, the outermost folders with a rule
+    pub fn content_roots(&self, folders: &[Folder]) -> Vec<PathBuf> {
+        if self.is_enabled() {
+            return self.roots.iter().map(|(_, root)| root.to_owned()).collect();
+        }
+
+        let mut paths = folders
+            .iter()
+            .map(|folder| PathBuf::from(&folder.path))
+            .collect::<Vec<_>>();
+        paths.sort();
+        let mut roots: Vec<PathBuf> = Vec::new();
+        paths.into_iter().for_each(|path| {
+            if !roots.iter().any(|root| path.starts_with(root)) {
+                roots.push(path);
+            }
+        });
+        roots
+    }
+
+    /// translates the path of a request into the
+    /// host one, None if the library does not exist
+    pub fn resolve(&self, public_path: &Path) -> Option<PathBuf> {
+        // the segments guard already refuses them,
+        // but we must never climb out of a root
+        if public_path
+            .components()
+            .any(|component| component == Component::ParentDir)
+        {
+            return None;
+        }
+
+        if !self.is_enabled() {
+            return Some(PathBuf::from("/").join(public_path));
+        }
+
+        let mut components = public_path
+            .components()
+            .filter_map(|component| match component {
+                Component::Normal(component) => component.to_str(),
+                _ => None,
+            });
+        let name = components.next()?;
+        let (_, root) = self.roots.iter().find(|(library, _)| library == name)?;
+
+        Some(components.fold(root.to_owned(), |path, component| path.join(component)))
+    }
+
+    /// translates a host path into the form the
+    /// clients see, None if it is outside every library
+    pub fn to_public(&self, host_path: &Path) -> Option<String> {
+        if !self.is_enabled() {
+            return host_path.to_str().map(|path| path.to_owned());
+        }
+
+        let (name, root) = self
+            .roots
+            .iter()
+            .filter(|(_, root)| host_path.starts_with(root))
+            .max_by_key(|(_, root)| root.components().count())?;
+
+        let relative = host_path.strip_prefix(root).ok()?.to_str()?;
+        if relative.is_empty() {
+            Some(name.to_owned())
+        } else {
+            Some(format!("{}/{}", name, relative))
+        }
+    }
+}
+
+/// the new public path of an item after `from` has been
+/// moved to `to`, None if the item was not under `from`
+pub fn rebase(public_path: &str, from: &str, to: &str) -> Option<String> {
+    if public_path == from {
+        return Some(to.to_owned());
+    }
+    public_path
+        .strip_prefix(from)
+        .filter(|rest| rest.starts_with('/'))
+        .map(|rest| format!("{}{}", to, rest))
+}
@@ -0,0 +1,468 @@
+use crate::annotations::Annotations;
+use crate::api::{json_response, status_response};
+use crate::encode_path;
+use crate::folder_metadata::{FolderMetadata, SortOrder};
+use crate::forwarded_identity::ForwardedIdentity;
+use crate::media_type::MediaKind;
+use crate::options::Options;
+use crate::permission::Permission;
+use crate::statistics::*;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use rocket::http::{ContentType, Status};
+use rocket::{Response, State};
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+
+/// past this size the metadata cache is emptied,
+/// it is cheaper than keeping track of the usage
+const METADATA_CACHE_MAX_ENTRIES: usize = 100_000;
+
+/// What exiftool tells us about a media file
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MediaMetadata {
+    pub taken: Option<NaiveDateTime>,
+    pub width: Option<u64>,
+    pub height: Option<u64>,
+    /// in seconds
+    pub duration: Option<f64>,
+    /// signed decimal degrees, only used by the map
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+}
+
+/// Keeps the metadata of the media files in memory, a file
+/// is read again only when its modification time changes
+#[derive(Debug, Default)]
+pub struct MetadataCache {
+    entries: RwLock<HashMap<PathBuf, (SystemTime, MediaMetadata)>>,
+}
+
+impl MetadataCache {
+    /// returns the metadata of the files, the ones not in the
+    /// cache are read with a single exiftool invocation
+    pub fn get_many(&self, files: &[(PathBuf, SystemTime)]) -> HashMap<PathBuf, MediaMetadata> {
+        let mut found = HashMap::new();
+        let mut missing = Vec::new();
+
+        {
+            let entries = self.entries.read().unwrap();
+            files
+                .iter()
+                .for_each(|(path, modified)| match entries.get(path) {
+                    Some((cached_modified, metadata)) if cached_modified == modified => {
+                        found.insert(path.to_owned(), metadata.clone());
+                    }
+                    _ => missing.push((path.to_owned(), *modified)),
+                });
+        }
+
+        if missing.is_empty() {
+            return found;
+        }
+
+        // a few hundred files at a time, to stay
+        // well below the command line length limit
+        let read = missing
+            .chunks(500)
+            .flat_map(|chunk| {
+                read_metadata(
+                    &chunk
+                        .iter()
+                        .map(|(path, _)| path.as_path())
+                        .collect::<Vec<_>>(),
+                )
+            })
+            .collect::<HashMap<_, _>>();
+
+        let mut entries = self.entries.write().unwrap();
+        if entries.len() + missing.len() > METADATA_CACHE_MAX_ENTRIES {
+            debug!("metadata cache full, clearing it");
+            entries.clear();
+        }
+        missing.into_iter().for_each(|(path, modified)| {
+            let metadata = read.get(&path).cloned().unwrap_or_default();
+            entries.insert(path.clone(), (modified, metadata.clone()));
+            found.insert(path, metadata);
+        });
+
+        found
+    }
+}
+
+pub(crate) fn read_metadata(paths: &[&Path]) -> HashMap<PathBuf, MediaMetadata> {
+    let mut cmd = Command::new("exiftool");
+    let cmd = cmd
+        .args(&[
+            "-json",
+            "-n",
+            "-fast",
+            "-DateTimeOriginal",
+            "-CreateDate",
+            "-ImageWidth",
+            "-ImageHeight",
+            "-Orientation",
+            "-Duration",
+            "-GPSLatitude",
+            "-GPSLongitude",
+        ])
+        .args(paths.iter().map(|path| path.to_str().unwrap()));
+    trace!("{:#?}", cmd);
+
+    let output = match cmd.output() {
+        Ok(output) => output,
+        Err(err) => {
+            warn!("cannot run exiftool: {}", err);
+            return HashMap::new();
+        }
+    };
+    trace!("{:?}", output.status);
+
+    // exiftool fails if any of the files cannot be read,
+    // but still reports the other ones
+    let parsed: Vec<serde_json::Value> = match serde_json::from_slice(&output.stdout) {
+        Ok(parsed) => parsed,
+        Err(err) => {
+            warn!("cannot parse the exiftool output: {}", err);
+            return HashMap::new();
+        }
+    };
+
+    parsed
+        .iter()
+        .filter_map(|tags| {
+            let path = PathBuf::from(tags.get("SourceFile")?.as_str()?);
+
+            let taken = ["DateTimeOriginal", "CreateDate"]
+                .iter()
+                .filter_map(|tag| tags.get(*tag)?.as_str())
+                .find_map(|date| NaiveDateTime::parse_from_str(date, "%Y:%m:%d %H:%M:%S").ok());
+            let width = tags.get("ImageWidth").and_then(|width| width.as_u64());
+            let height = tags.get("ImageHeight").and_then(|height| height.as_u64());
+            let duration = tags.get("Duration").and_then(|duration| duration.as_f64());
+            let latitude = tags
+                .get("GPSLatitude")
+                .and_then(|latitude| latitude.as_f64())
+                .filter(|latitude| (-90.0..=90.0).contains(latitude));
+            let longitude = tags
+                .get("GPSLongitude")
+                .and_then(|longitude| longitude.as_f64())
+                .filter(|longitude| (-180.0..=180.0).contains(longitude));
+
+            // the thumbnails are rotated, the sizes must be too
+            let rotated = tags
+                .get("Orientation")
+                .and_then(|orientation| orientation.as_u64())
+                .is_some_and(|orientation| (5..=8).contains(&orientation));
+            let (width, height) = if rotated {
+                (height, width)
+            } else {
+                (width, height)
+            };
+
+            Some((
+                path,
+                MediaMetadata {
+                    taken,
+                    width,
+                    height,
+                    duration,
+                    latitude,
+                    longitude,
+                },
+            ))
+        })
+        .collect()
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryKind {
+    Folder,
+    Image,
+    Video,
+    Audio,
+    Document,
+    Other,
+}
+
+impl From<MediaKind> for EntryKind {
+    fn from(kind: MediaKind) -> Self {
+        match kind {
+            MediaKind::Image => EntryKind::Image,
+            MediaKind::Video => EntryKind::Video,
+            MediaKind::Audio => EntryKind::Audio,
+            MediaKind::Document => EntryKind::Document,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ChildCounts {
+    pub folders: u64,
+    pub files: u64,
+}
+
+/// An element of the version 2 listing
+#[derive(Clone, Debug, Serialize)]
+pub struct Entry {
+    pub path: String,
+    pub name: String,
+    pub kind: EntryKind,
+    pub mime: Option<String>,
+    pub size: Option<u64>,
+    pub modified: Option<DateTime<Utc>>,
+    /// the capture date, in the local time of the camera
+    pub taken: Option<NaiveDateTime>,
+    pub width: Option<u64>,
+    pub height: Option<u64>,
+    pub duration: Option<f64>,
+    pub children: Option<ChildCounts>,
+    pub thumbnail: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tags: Option<BTreeSet<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub caption: Option<String>,
+}
+
+impl Entry {
+    fn new(options: &Options, path: &Path, kind: EntryKind) -> Self {
+        Self {
+            path: options.libraries.to_public(path).unwrap(),
+            name: path.file_name().unwrap().to_str().unwrap().to_owned(),
+            kind,
+            mime: None,
+            size: None,
+            modified: path
+                .metadata()
+                .and_then(|metadata| metadata.modified())
+                .ok()
+                .map(DateTime::<Utc>::from),
+            taken: None,
+            width: None,
+            height: None,
+            duration: None,
+            children: None,
+            thumbnail: None,
+            tags: None,
+            caption: None,
+        }
+    }
+
+    fn taken_or_modified(&self) -> Option<NaiveDateTime> {
+        self.taken
+            .or_else(|| self.modified.map(|modified| modified.naive_utc()))
+    }
+}
+
+fn thumbnail_url(options: &Options, path: &Path) -> Option<String> {
+    match encode_path(options, path) {
+        Some(encoded) => Some(format!("/thumb/{}/{}", options.api_thumb_size, encoded)),
+        None => {
+            warn!(
+                "no thumbnail for {:?}: it is outside of the libraries",
+                path
+            );
+            None
+        }
+    }
+}
+
+/// the ones the user can see among the
+/// visible subfolders of a visible folder
+pub fn visible_subfolders(
+    options: &Options,
+    folder: &PathBuf,
+    subfolders: Vec<PathBuf>,
+    email: &str,
+) -> Vec<PathBuf> {
+    let permissions = options.entries_permissions(folder, &subfolders, email);
+    subfolders
+        .into_iter()
+        .zip(permissions)
+        .filter(|(_, permission)| permission.is_some())
+        .map(|(subfolder, _)| subfolder)
+        .collect()
+}
+
+fn count_children(options: &Options, folder: &PathBuf, email: &str) -> ChildCounts {
+    let mut counts = ChildCounts::default();
+    if let Ok(read_dir) = folder.read_dir() {
+        let (folders, files): (Vec<_>, Vec<_>) = read_dir
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| !options.hidden_files.is_hidden_child(path))
+            .partition(|path| path.is_dir());
+        counts.folders = visible_subfolders(options, folder, folders, email).len() as u64;
+        counts.files = files
+            .iter()
+            .filter(|path| options.media_types.is_previewable(path))
+            .count() as u64;
+    }
+    counts
+}
+
+pub fn folder_entry(options: &Options, path: &PathBuf, email: &str) -> Entry {
+    let mut entry = Entry::new(options, path, EntryKind::Folder);
+    entry.children = Some(count_children(options, path, email));
+    entry.thumbnail = FolderMetadata::load(path)
+        .unwrap_or_default()
+        .cover_path(options, path)
+        .and_then(|cover| thumbnail_url(options, &cover));
+    entry
+}
+
+/// builds the entries of the files, reading the
+/// metadata of the media ones in one go
+pub fn file_entries(
+    options: &Options,
+    metadata_cache: &MetadataCache,
+    annotations: &Annotations,
+    files: &[PathBuf],
+) -> Vec<Entry> {
+    let media_files = files
+        .iter()
+        .filter(|path| options.media_types.lookup(path).is_some())
+        .filter_map(|path| {
+            let modified = path
+                .metadata()
+                .and_then(|metadata| metadata.modified())
+                .ok()?;
+            Some((path.to_owned(), modified))
+        })
+        .collect::<Vec<_>>();
+    let metadata = metadata_cache.get_many(&media_files);
+
+    files
+        .iter()
+        .map(|path| {
+            let media_type = options.media_types.lookup(path);
+            let mut entry = Entry::new(
+                options,
+                path,
+                media_type.map_or(EntryKind::Other, |media_type| media_type.kind.into()),
+            );
+            entry.size = path.metadata().map(|metadata| metadata.len()).ok();
+            entry.mime = match media_type {
+                Some(media_type) => Some(media_type.mime.to_owned()),
+                None => path
+                    .extension()
+                    .and_then(|extension| extension.to_str())
+                    .and_then(|extension| ContentType::from_extension(&extension.to_lowercase()))
+                    .map(|content_type| content_type.to_string()),
+            };
+            if media_type.is_some() {
+                entry.thumbnail = thumbnail_url(options, path);
+            }
+            if let Some(metadata) = metadata.get(path) {
+                entry.taken = metadata.taken;
+                entry.width = metadata.width;
+                entry.height = metadata.height;
+                entry.duration = metadata.duration;
+            }
+            if let Some(annotation) = annotations.get(options, path) {
+                entry.tags = Some(annotation.tags).filter(|tags| !tags.is_empty());
+                entry.caption = annotation.caption;
+            }
+            entry
+        })
+        .collect()
+}
+
+/// sorts the entries as the folder sidecar asks,
+/// by name if it does not say anything
+pub fn sort_entries(entries: &mut [Entry], sort_order: Option<SortOrder>) {
+    match sort_order.unwrap_or(SortOrder::Name) {
+        SortOrder::Name => entries.sort_by(|a, b| a.name.cmp(&b.name)),
+        SortOrder::NameDesc => entries.sort_by(|a, b| b.name.cmp(&a.name)),
+        SortOrder::Date => entries.sort_by(|a, b| {
+            (a.taken_or_modified(), &a.name).cmp(&(b.taken_or_modified(), &b.name))
+        }),
+        SortOrder::DateDesc => entries.sort_by(|a, b| {
+            (b.taken_or_modified(), &b.name).cmp(&(a.taken_or_modified(), &a.name))
+        }),
+    }
+}
+
+/// lists the content of a folder with everything the
+/// client needs to show it: the subfolders, the media files
+/// and, with the full permission level, the extra files
+#[get("/api/v2/list/<path..>")]
+pub(crate) fn list_v2<'r>(
+    options: State<'_, Options>,
+    statistics: State<'_, Arc<RwLock<Statistics>>>,
+    metadata_cache: State<'_, MetadataCache>,
+    annotations: State<'_, Annotations>,
+    forwarded_identity: ForwardedIdentity,
+    path: PathBuf,
+) -> Response<'r> {
+    let path = match options.libraries.resolve(&path) {
+        Some(path) => path,
+        None => return status_response(Status::NotFound),
+    };
+    trace!("Authenticated as {}", &forwarded_identity);
+    trace!("requested path == {:?}", &path);
+
+    let permission = options.folder_permission(&path, &forwarded_identity.email);
+    trace!("permission == {:?}", permission);
+
+    if permission.is_none() {
+        track_unauthorized_list_v2(&options, &statistics);
+        options.audit(
+            &forwarded_identity.email,
+            "listing",
+            path.to_str().unwrap(),
+            "list",
+            false,
+        );
+        return status_response(Status::Unauthorized);
+    }
+
+    if !path.is_dir() || options.hidden_files.is_hidden(&path) {
+        track_authorized_not_found(&options, &statistics);
+        return status_response(Status::NotFound);
+    }
+
+    track_authorized_list_v2(&options, &statistics);
+    options.audit(
+        &forwarded_identity.email,
+        "listing",
+        path.to_str().unwrap(),
+        "list",
+        true,
+    );
+
+    let include_extras = permission.is_some_and(|permission| permission >= Permission::Full);
+
+    let read_dir = match path.read_dir() {
+        Ok(read_dir) => read_dir,
+        Err(err) => {
+            error!("cannot read {:?}: {}", path, err);
+            return status_response(Status::InternalServerError);
+        }
+    };
+    let (subfolders, children): (Vec<_>, Vec<_>) = read_dir
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|child| !options.hidden_files.is_hidden_child(child))
+        .partition(|child| child.is_dir());
+    let mut folders = visible_subfolders(&options, &path, subfolders, &forwarded_identity.email)
+        .iter()
+        .map(|child| folder_entry(&options, child, &forwarded_identity.email))
+        .collect::<Vec<_>>();
+    let files = children
+        .into_iter()
+        .filter(|child| include_extras || options.media_types.is_previewable(child))
+        .collect::<Vec<_>>();
+
+    let sort_order = FolderMetadata::load(&path).and_then(|metadata| metadata.sort_order);
+    let mut files = file_entries(&options, &metadata_cache, &annotations, &files);
+    sort_entries(&mut folders, sort_order);
+    sort_entries(&mut files, sort_order);
+    folders.append(&mut files);
+
+    json_response(&options, Status::Ok, &folders)
+}
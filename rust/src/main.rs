@@ -4,35 +4,75 @@
 extern crate rocket;
 #[macro_use]
 extern crate log;
+use rocket::http::uri::Uri;
 use rocket::http::Status;
-use rocket::http::{ContentType, MediaType};
+use rocket::http::{ContentType, Header};
 use rocket::response::NamedFile;
 use rocket::{Response, State};
 use snafu::{Backtrace, ResultExt, Snafu};
-use std::collections::HashMap;
 use std::convert::TryInto;
 use std::io::Cursor;
 use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::sync::{Arc, RwLock};
 
+mod albums;
+mod annotations;
+mod api;
 mod audit;
+mod breadcrumbs;
+mod browse;
+mod byte_range;
+mod comments;
+mod document;
+mod duplicates;
+mod favorites;
 mod file_type;
 mod file_with_size;
+mod first_level_folders;
 mod folder;
+mod folder_metadata;
 mod forwarded_identity;
+mod hidden_files;
+mod hls;
+mod image_format;
+mod json_store;
+mod libraries;
+mod listing;
 mod logging;
+mod manage;
+mod map;
+mod media_type;
 mod options;
+mod permission;
+mod preview_clip;
 mod statistics;
+mod storyboard;
+mod upload;
+mod video_probe;
+use albums::Albums;
+use annotations::Annotations;
+use byte_range::{ByteRange, FileWindow};
+use comments::Comments;
+use duplicates::Duplicates;
+use favorites::Favorites;
 use file_type::FileType;
 use file_with_size::FileWithSize;
+use first_level_folders::FirstLevelFolders;
+use folder_metadata::FolderMetadata;
 use forwarded_identity::ForwardedIdentity;
+use image_format::{AcceptedImageFormats, ImageFormat};
+use json_store::unique_id;
+use listing::MetadataCache;
 use logging::setup_logger;
+use manage::Trash;
+use map::{LocationPolicy, Map, StrippedCopy};
+use media_type::{MediaKind, MediaTypes, Thumbnailer};
 use options::*;
+use permission::Permission;
+use preview_clip::{PreviewClipFormat, PreviewClips};
 use statistics::*;
-
-static IMAGE_EXTENSIONS: &[&str] = &["png", "bmp", "jpg", "gif"];
-static VIDEO_EXTENSIONS: &[&str] = &["mkv", "mp4", "avi", "mov", "webm"];
+use upload::Uploads;
 
 #[get("/metrics")]
 pub(crate) fn metrics<'r>(statistics: State<'_, Arc<RwLock<Statistics>>>) -> Response<'r> {
@@ -44,34 +84,35 @@ pub(crate) fn metrics<'r>(statistics: State<'_, Arc<RwLock<Statistics>>>) -> Res
     response
 }
 
-fn get_file<'r>(path: &Path) -> Result<Response<'r>, Box<dyn std::error::Error>> {
-    let file = std::fs::OpenOptions::new().read(true).open(&path)?;
-
-    let content_type = ContentType::parse_flexible(&path.extension().unwrap().to_str().unwrap())
-        .unwrap_or_else(|| {
-            let extension = path.extension().unwrap().to_str().unwrap().to_lowercase();
-            debug!("extension == {:?}", extension);
-
-            let media_type =
-                MediaType::from_extension(&extension).unwrap_or_else(|| match extension.as_ref() {
-                    "mkv" => MediaType::new("video", "mp4"),
-                    "mp4" => MediaType::new("video", "mp4"),
-                    "avi" => MediaType::new("video", "x-msvideo"),
-                    "webm" => MediaType::new("video", "webm"),
-                    "webp" => MediaType::new("image", "webp"),
-                    "ogv" => MediaType::new("video", "ogg"),
-                    "mpeg" => MediaType::new("video", "mpeg"),
-                    _ => {
-                        warn!(
-                            "unsuppored media type {}, returning application/octet-stream",
-                            extension
-                        );
-                        MediaType::new("application", "octet-stream")
-                    }
-                });
-            ContentType(media_type)
-        });
+fn content_type_of(path: &Path, media_types: &MediaTypes) -> ContentType {
+    let content_type = match media_types.lookup(path) {
+        Some(media_type) => ContentType::parse_flexible(&media_type.mime),
+        None => path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .and_then(|extension| {
+                ContentType::parse_flexible(extension)
+                    .or_else(|| ContentType::from_extension(&extension.to_lowercase()))
+            }),
+    }
+    .unwrap_or_else(|| {
+        warn!(
+            "unsuppored media type for {:?}, returning application/octet-stream",
+            path
+        );
+        ContentType::Binary
+    });
     debug!("content_type == {:?}", content_type);
+    content_type
+}
+
+fn get_file<'r>(
+    path: &Path,
+    media_types: &MediaTypes,
+) -> Result<Response<'r>, Box<dyn std::error::Error>> {
+    let content_type = content_type_of(path, media_types);
+
+    let file = std::fs::OpenOptions::new().read(true).open(&path)?;
 
     let mut response = Response::new();
     response.set_status(Status::Ok);
@@ -80,6 +121,48 @@ fn get_file<'r>(path: &Path) -> Result<Response<'r>, Box<dyn std::error::Error>>
     Ok(response)
 }
 
+/// like get_file but honors the Range header,
+/// so the browser can seek in audio and video files
+fn get_file_range<'r>(
+    path: &Path,
+    media_types: &MediaTypes,
+    byte_range: Option<ByteRange>,
+) -> Result<Response<'r>, Box<dyn std::error::Error>> {
+    let byte_range = match byte_range {
+        Some(byte_range) => byte_range,
+        None => {
+            let mut response = get_file(path, media_types)?;
+            response.set_raw_header("Accept-Ranges", "bytes");
+            return Ok(response);
+        }
+    };
+    trace!("byte_range == {:?}", byte_range);
+
+    let content_type = content_type_of(path, media_types);
+    let file = std::fs::OpenOptions::new().read(true).open(&path)?;
+    let file_length = file.metadata()?.len();
+
+    let mut response = Response::new();
+    response.set_raw_header("Accept-Ranges", "bytes");
+
+    match byte_range.resolve(file_length) {
+        Some((start, end)) => {
+            response.set_status(Status::PartialContent);
+            response.set_header(content_type);
+            response.set_raw_header(
+                "Content-Range",
+                format!("bytes {}-{}/{}", start, end, file_length),
+            );
+            response.set_sized_body(FileWindow::new(file, start, end - start + 1)?);
+        }
+        None => {
+            response.set_status(Status::RangeNotSatisfiable);
+            response.set_raw_header("Content-Range", format!("bytes */{}", file_length));
+        }
+    }
+    Ok(response)
+}
+
 #[get("/", rank = 1)]
 fn root<'a>(
     options: State<'_, Options>,
@@ -94,7 +177,7 @@ fn root<'a>(
     } else {
         track_authorized_static(&options, &statistics, "/");
         let path = Path::new(&options.static_site_path).join("index.html");
-        get_file(&path).unwrap()
+        get_file(&path, &options.media_types).unwrap()
     }
 }
 
@@ -115,7 +198,7 @@ fn site<'r>(
         trace!("requested: {:?}, mapped as {:?}", &file, &complete_path);
         if complete_path.exists() {
             track_authorized_static(&options, &statistics, complete_path.to_str().unwrap());
-            get_file(&complete_path).unwrap()
+            get_file(&complete_path, &options.media_types).unwrap()
         } else {
             // the file does not exists so let's call index.html and let
             // Angular sort out the path
@@ -131,7 +214,7 @@ fn site<'r>(
                 response
             } else {
                 track_authorized_dynamic(&options, &statistics);
-                get_file(&path).unwrap()
+                get_file(&path, &options.media_types).unwrap()
             }
         }
     }
@@ -142,12 +225,21 @@ fn path<'r>(
     options: State<'_, Options>,
     statistics: State<'_, Arc<RwLock<Statistics>>>,
     forwarded_identity: ForwardedIdentity,
+    byte_range: Option<ByteRange>,
     path: PathBuf,
 ) -> Response<'r> {
-    let path = PathBuf::from("/").join(path);
+    let path = match options.libraries.resolve(&path) {
+        Some(path) => path,
+        None => {
+            let mut response = Response::new();
+            response.set_status(Status::NotFound);
+            return response;
+        }
+    };
     trace!("requesting: {:?}", &path);
     trace!("Authenticated as {}", &forwarded_identity);
-    let is_folder_allowed = options.is_folder_allowed(&path, &forwarded_identity.email);
+    let is_folder_allowed =
+        options.is_folder_allowed_with(&path, &forwarded_identity.email, Permission::Original);
     trace!("is_folder_allowed == {}", is_folder_allowed);
 
     if !is_folder_allowed {
@@ -156,24 +248,12 @@ fn path<'r>(
         response.set_status(Status::Unauthorized);
         response
     } else {
-        trace!("extension == {:?}", path.as_path().extension());
-        let extension = match path.as_path().extension() {
-            Some(ext) => ext.to_str().unwrap().to_lowercase(),
-            None => {
-                let mut response = Response::new();
-                response.set_status(Status::NotFound);
-                return response;
-            }
-        };
-
-        if path.as_path().is_dir() {
+        if path.as_path().is_dir() || options.hidden_files.is_hidden(&path) {
             track_authorized_not_found(&options, &statistics);
             let mut response = Response::new();
             response.set_status(Status::NotFound);
             response
-        } else if IMAGE_EXTENSIONS.iter().any(|&ext| ext == extension)
-            || VIDEO_EXTENSIONS.iter().any(|&ext| ext == extension)
-        {
+        } else if options.media_types.is_previewable(&path) {
             track_authorized_dynamic(&options, &statistics);
             options.audit(
                 &forwarded_identity.email,
@@ -183,8 +263,35 @@ fn path<'r>(
                 true,
             );
 
+            // the pictures and the videos are sent without
+            // their location to the users who cannot see it
+            let has_location = options.media_types.lookup(&path).is_some_and(|media_type| {
+                [MediaKind::Image, MediaKind::Video].contains(&media_type.kind)
+            });
+            let path = if has_location
+                && options.location_policy == LocationPolicy::Strip
+                && !options.can_view_location(&forwarded_identity.email)
+            {
+                match map::without_location(&options, &path) {
+                    StrippedCopy::Ready(path) => path,
+                    StrippedCopy::Pending => {
+                        let mut response = Response::new();
+                        response.set_status(Status::Accepted);
+                        add_access_control_allow_origin_if_needed(&mut response, &options);
+                        return response;
+                    }
+                    StrippedCopy::Failed => {
+                        let mut response = Response::new();
+                        response.set_status(Status::InternalServerError);
+                        return response;
+                    }
+                }
+            } else {
+                path
+            };
+
             debug!("sending == {:?}", &path);
-            match get_file(&path) {
+            match get_file_range(&path, &options.media_types, byte_range) {
                 Ok(response) => response,
                 Err(_err) => {
                     let mut response = Response::new();
@@ -202,27 +309,322 @@ fn path<'r>(
 }
 
 fn generate_thumb_folder_path(options: &Options, size: u64, original_path: &PathBuf) -> PathBuf {
+    generate_cache_folder_path(options, &format!("{}x{}", size, size), original_path)
+}
+
+fn generate_cache_folder_path(
+    options: &Options,
+    cache_name: &str,
+    original_path: &PathBuf,
+) -> PathBuf {
     trace!("original_path == {:?}", &original_path);
-    let path = Path::new(&options.thumb_folder_path).join(format!("{}x{}", size, size));
-    trace!("generate_thumb_folder_path == {:?}", &path);
+    let path = Path::new(&options.thumb_folder_path).join(cache_name);
+    trace!("generate_cache_folder_path == {:?}", &path);
     let path = path.join(&original_path.parent().unwrap().to_str().unwrap()[1..]);
-    trace!("generate_thumb_folder_path == {:?}", &path);
+    trace!("generate_cache_folder_path == {:?}", &path);
 
     std::fs::create_dir_all(&path).unwrap();
 
     path
 }
 
+/// the folders under thumb_folder_path mirroring the libraries,
+/// one for each cache_name passed to generate_cache_folder_path:
+/// keep them in sync. thumb_folder_path can be shared, so
+/// only the names we generate are considered
+fn cache_folder_roots(options: &Options) -> Vec<PathBuf> {
+    let is_cache_name = |name: &str| {
+        let name = name.strip_suffix("-nogps").unwrap_or(name);
+        let dimension =
+            |value: &str| !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit());
+        let is_thumb = name
+            .split_once('x')
+            .is_some_and(|(width, height)| dimension(width) && dimension(height));
+        let is_render = name.strip_suffix('w').is_some_and(dimension);
+        is_thumb
+            || is_render
+            || ["preview", "cover", "page", "clip", "storyboard", "nogps"].contains(&name)
+    };
+
+    let children = |folder: &Path| {
+        folder
+            .read_dir()
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.is_dir())
+            .collect::<Vec<_>>()
+    };
+
+    let thumb_folder_path = Path::new(&options.thumb_folder_path);
+    let mut roots = children(thumb_folder_path)
+        .into_iter()
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(is_cache_name)
+        })
+        .collect::<Vec<_>>();
+    // one folder for each variant
+    roots.append(&mut children(&thumb_folder_path.join("hls")));
+    roots
+}
+
+/// the path as it appears in the URLs: the public
+/// form with every segment percent-encoded, or None
+/// when the path is outside of the libraries
+fn encode_path(options: &Options, path: &Path) -> Option<String> {
+    let public = options.libraries.to_public(path)?;
+    Some(
+        public
+            .split('/')
+            .filter(|component| !component.is_empty())
+            .map(|component| Uri::percent_encode(component).into_owned())
+            .collect::<Vec<_>>()
+            .join("/"),
+    )
+}
+
+/// the width of a picture, as ImageMagick reads it
+fn image_width(path: &Path) -> Option<u64> {
+    let mut cmd = Command::new("identify");
+    let cmd = cmd.args(&["-format", "%w", &format!("{}[0]", path.to_str()?)]);
+    trace!("{:#?}", cmd);
+    let output = cmd.output().ok()?;
+    trace!("{:?}", output);
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8_lossy(&output.stdout).trim().parse().ok()
+}
+
+/// RAW and HEIC files cannot be handled by the browser and
+/// ImageMagick is painfully slow with them. Almost all of them
+/// carry a JPEG preview so we extract and cache it instead.
+fn generate_embedded_preview(
+    options: &State<'_, Options>,
+    statistics: &State<'_, Arc<RwLock<Statistics>>>,
+    original_path: &PathBuf,
+) -> PathBuf {
+    let output_file_name = generate_cache_folder_path(
+        options,
+        &map::location_cache_name(options, "preview"),
+        &original_path,
+    )
+    .join(format!(
+        "{}.jpg",
+        original_path.file_name().unwrap().to_str().unwrap()
+    ));
+    trace!("output_file_name == {:#?}", output_file_name);
+    track_embedded_preview_access(options, statistics);
+
+    // if we already have a preview, do not extract it again
+    if !output_file_name.exists() {
+        track_embedded_preview_generation(options, statistics);
+
+        // the tags are sorted from the biggest to the smallest. A
+        // preview narrower than the smallest render is useless: most
+        // HEIC files only carry a 160px ThumbnailImage
+        let min_width = options.render_widths.first().copied().unwrap_or(0);
+        let candidate_file_name = output_file_name.with_file_name(format!(
+            "{}.{}.candidate.jpg",
+            original_path.file_name().unwrap().to_str().unwrap(),
+            unique_id()
+        ));
+        let has_preview = ["JpgFromRaw", "PreviewImage", "ThumbnailImage"]
+            .iter()
+            .any(|tag| {
+                let mut cmd = Command::new("exiftool");
+                let cmd = cmd.args(&["-b", &format!("-{}", tag), original_path.to_str().unwrap()]);
+                trace!("{:#?}", cmd);
+                let output = cmd.output().unwrap();
+                trace!("{:?}", output.status);
+                if !output.status.success() || output.stdout.is_empty() {
+                    return false;
+                }
+
+                std::fs::write(&candidate_file_name, output.stdout).unwrap();
+                let width = image_width(&candidate_file_name);
+                trace!("{} width == {:?}", tag, width);
+                width.is_some_and(|width| width >= min_width)
+            });
+
+        if has_preview {
+            // the embedded preview does not carry the
+            // orientation of the original
+            let mut cmd = Command::new("exiftool");
+            let cmd = cmd.args(&[
+                "-overwrite_original",
+                "-TagsFromFile",
+                original_path.to_str().unwrap(),
+                "-Orientation",
+                candidate_file_name.to_str().unwrap(),
+            ]);
+            trace!("{:#?}", cmd);
+            let output = cmd.output().unwrap();
+            trace!("{:?}", output);
+        } else {
+            debug!(
+                "no large enough embedded preview in {:?}, falling back to ImageMagick",
+                original_path
+            );
+            let mut cmd = Command::new("convert");
+            let cmd = cmd.args(&[
+                &format!("{}[0]", original_path.to_str().unwrap()),
+                candidate_file_name.to_str().unwrap(),
+            ]);
+            trace!("{:#?}", cmd);
+            let output = cmd.output().unwrap();
+            trace!("{:?}", output);
+        }
+
+        // the previews are shared by all the users: one that
+        // cannot be stripped is not cached, nor sent
+        let is_stripped = options.location_policy != LocationPolicy::Strip
+            || map::strip_location(&candidate_file_name);
+        if !is_stripped || std::fs::rename(&candidate_file_name, &output_file_name).is_err() {
+            let _ = std::fs::remove_file(&candidate_file_name);
+        }
+    }
+
+    output_file_name
+}
+
+/// extracts the picture attached to an audio file. If there
+/// is none, the cover of the folder is used instead
+fn generate_cover_art(
+    options: &State<'_, Options>,
+    statistics: &State<'_, Arc<RwLock<Statistics>>>,
+    original_path: &PathBuf,
+) -> Option<PathBuf> {
+    let output_file_name =
+        generate_cache_folder_path(options, "cover", &original_path).join(format!(
+            "{}.jpg",
+            original_path.file_name().unwrap().to_str().unwrap()
+        ));
+    trace!("output_file_name == {:#?}", output_file_name);
+    track_cover_art_access(options, statistics);
+
+    // an audio file without cover art is not read again
+    // until it changes, its folder might have one meanwhile
+    if !output_file_name.exists() && has_failed(&output_file_name, original_path) {
+        return find_folder_cover(options, original_path.parent()?);
+    }
+
+    // if we already have the cover, do not extract it again
+    if !output_file_name.exists() {
+        track_cover_art_generation(options, statistics);
+
+        let mut cmd = Command::new("ffmpeg");
+        let cmd = cmd.args(&[
+            "-i",
+            original_path.to_str().unwrap(),
+            "-an",
+            "-frames:v",
+            "1",
+            "-c:v",
+            "mjpeg",
+            "-f",
+            "image2",
+            output_file_name.to_str().unwrap(),
+            "-y",
+        ]);
+        trace!("{:#?}", cmd);
+        let output = cmd.output().unwrap();
+        trace!("{:?}", output);
+
+        if !output.status.success() {
+            debug!(
+                "no cover art in {:?}, looking for the cover of the folder",
+                original_path
+            );
+            let _ = std::fs::remove_file(&output_file_name);
+            if let Err(err) = std::fs::write(failure_marker(&output_file_name), b"") {
+                warn!(
+                    "cannot record the failure of {:?}: {}",
+                    output_file_name, err
+                );
+            }
+            return find_folder_cover(options, original_path.parent()?);
+        }
+        let _ = std::fs::remove_file(failure_marker(&output_file_name));
+    }
+
+    Some(output_file_name)
+}
+
+/// looks for one of cover_art_file_names in the
+/// folder, ignoring the case, in the configured order
+fn find_folder_cover(options: &Options, folder: &Path) -> Option<PathBuf> {
+    let file_names = std::fs::read_dir(folder)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .collect::<Vec<_>>();
+
+    options.cover_art_file_names.iter().find_map(|cover| {
+        file_names
+            .iter()
+            .find(|path| {
+                path.file_name()
+                    .and_then(|file_name| file_name.to_str())
+                    .is_some_and(|file_name| file_name.eq_ignore_ascii_case(cover))
+            })
+            .cloned()
+    })
+}
+
+/// rasterizes the first page of a document,
+/// flattened on white as PDFs are often transparent
+fn generate_first_page(
+    options: &State<'_, Options>,
+    statistics: &State<'_, Arc<RwLock<Statistics>>>,
+    original_path: &PathBuf,
+) -> PathBuf {
+    let output_file_name =
+        generate_cache_folder_path(options, "page", &original_path).join(format!(
+            "{}.jpg",
+            original_path.file_name().unwrap().to_str().unwrap()
+        ));
+    trace!("output_file_name == {:#?}", output_file_name);
+    track_first_page_access(options, statistics);
+
+    // if we already have the page, do not rasterize it again
+    if !output_file_name.exists() {
+        track_first_page_generation(options, statistics);
+
+        let mut cmd = Command::new("convert");
+        let cmd = cmd.args(&[
+            "-density",
+            "150",
+            &format!("{}[0]", original_path.to_str().unwrap()),
+            "-background",
+            "white",
+            "-flatten",
+            output_file_name.to_str().unwrap(),
+        ]);
+        trace!("{:#?}", cmd);
+        let output = cmd.output().unwrap();
+        trace!("{:?}", output);
+    }
+
+    output_file_name
+}
+
 fn generate_picture_thumb(
     options: &State<'_, Options>,
     statistics: &State<'_, Arc<RwLock<Statistics>>>,
     size: u64,
+    format: ImageFormat,
     original_path: &PathBuf,
     complete_path: &PathBuf,
 ) -> PathBuf {
     let output_file_name = generate_thumb_folder_path(options, size, &original_path).join(format!(
-        "{}.jpg",
-        original_path.file_name().unwrap().to_str().unwrap()
+        "{}.{}",
+        original_path.file_name().unwrap().to_str().unwrap(),
+        format.extension()
     ));
     trace!("output_file_name == {:#?}", output_file_name);
     track_picture_thumb_access(options, statistics);
@@ -243,6 +645,8 @@ fn generate_picture_thumb(
             "center",
             "-extent",
             &format!("{}x{}", size, size),
+            "-quality",
+            &options.thumb_quality(format).to_string(),
             output_file_name.to_str().unwrap(),
         ]);
         trace!("{:#?}", cmd);
@@ -257,13 +661,13 @@ fn generate_video_thumb(
     options: &State<'_, Options>,
     statistics: &State<'_, Arc<RwLock<Statistics>>>,
     size: u64,
+    format: ImageFormat,
     original_path: &PathBuf,
     complete_path: &PathBuf,
 ) -> PathBuf {
-    let output_file_name = generate_thumb_folder_path(options, size, &original_path).join(format!(
-        "{}.jpg",
-        original_path.file_name().unwrap().to_str().unwrap()
-    ));
+    let thumb_folder_path = generate_thumb_folder_path(options, size, &original_path);
+    let file_name = original_path.file_name().unwrap().to_str().unwrap();
+    let output_file_name = thumb_folder_path.join(format!("{}.{}", file_name, format.extension()));
     trace!("output_file_name == {:#?}", output_file_name);
     track_video_thumb_access(options, statistics);
 
@@ -271,13 +675,32 @@ fn generate_video_thumb(
     if !output_file_name.exists() {
         track_video_thumb_generation(options, statistics);
 
+        // ffmpeg cannot write every format we support so we grab
+        // the frame as JPEG first, the concurrent requests for
+        // the thumbnail do not share it
+        let frame_file_name =
+            thumb_folder_path.join(format!("{}.{}.frame.jpg", file_name, unique_id()));
+
+        // the first frames are often black so we skip a few
+        // seconds (without going past the first third of
+        // short videos) and let ffmpeg pick the most
+        // representative frame among the following ones
+        let seek = match video_probe::probe(complete_path) {
+            Some(info) => options.video_thumb_seek_seconds.min(info.duration / 3.0),
+            None => 0.0,
+        };
+
         let mut cmd = Command::new("ffmpeg");
         let cmd = cmd.args(&[
+            "-ss",
+            &format!("{:.3}", seek),
             "-i",
             complete_path.to_str().unwrap(),
-            "-vframes",
+            "-vf",
+            "thumbnail",
+            "-frames:v",
             "1",
-            output_file_name.to_str().unwrap(),
+            frame_file_name.to_str().unwrap(),
             "-y",
         ]);
         trace!("about to send == {:#?}", cmd);
@@ -286,7 +709,7 @@ fn generate_video_thumb(
 
         let mut cmd = Command::new("convert");
         let cmd = cmd.args(&[
-            output_file_name.to_str().unwrap(),
+            frame_file_name.to_str().unwrap(),
             "-thumbnail",
             &format!("{}x{}>", size, size),
             "-background",
@@ -301,6 +724,8 @@ fn generate_video_thumb(
         let output = cmd.output().unwrap();
         trace!("{:?}", output);
 
+        let _ = std::fs::remove_file(&frame_file_name);
+
         let mut cmd = Command::new("composite");
         let cmd = cmd.args(&[
             "-dissolve",
@@ -311,6 +736,8 @@ fn generate_video_thumb(
             output_file_name.to_str().unwrap(),
             "-alpha",
             "Set",
+            "-quality",
+            &options.thumb_quality(format).to_string(),
             output_file_name.to_str().unwrap(),
         ]);
         trace!("{:#?}", cmd);
@@ -321,18 +748,276 @@ fn generate_video_thumb(
     output_file_name
 }
 
+/// builds a short, muted, low resolution loop of the video.
+/// It runs in the background (see `PreviewClips`) so it takes
+/// plain references instead of the managed state
+fn generate_video_clip(options: &Options, original_path: &PathBuf) -> PathBuf {
+    let output_file_name = generate_clip_file_name(options, original_path);
+    trace!("output_file_name == {:#?}", output_file_name);
+
+    if !output_file_name.exists() {
+        let seek = match video_probe::probe(original_path) {
+            Some(info) => options
+                .video_thumb_seek_seconds
+                .min((info.duration - options.preview_clip_seconds as f64).max(0.0)),
+            None => 0.0,
+        };
+        let partial_file_name = output_file_name.with_extension(format!(
+            "{}.partial",
+            options.preview_clip_format.extension()
+        ));
+
+        let mut cmd = Command::new("ffmpeg");
+        let mut cmd = cmd.args(&[
+            "-ss",
+            &format!("{:.3}", seek),
+            "-i",
+            original_path.to_str().unwrap(),
+            "-t",
+            &options.preview_clip_seconds.to_string(),
+            "-an",
+        ]);
+        cmd = match options.preview_clip_format {
+            PreviewClipFormat::Mp4 => cmd.args(&[
+                "-vf",
+                &format!("scale={}:-2", options.preview_clip_width),
+                "-c:v",
+                "libx264",
+                "-preset",
+                "veryfast",
+                "-crf",
+                "28",
+                "-pix_fmt",
+                "yuv420p",
+                "-movflags",
+                "+faststart",
+                "-f",
+                "mp4",
+            ]),
+            PreviewClipFormat::Webp => cmd.args(&[
+                "-vf",
+                &format!("fps=10,scale={}:-2", options.preview_clip_width),
+                "-c:v",
+                "libwebp",
+                "-q:v",
+                "50",
+                "-loop",
+                "0",
+                "-f",
+                "webp",
+            ]),
+        };
+        let cmd = cmd.args(&["-y", partial_file_name.to_str().unwrap()]);
+        trace!("about to send == {:#?}", cmd);
+        let output = cmd.output().unwrap();
+        trace!("{:?}", output);
+
+        if output.status.success() {
+            std::fs::rename(&partial_file_name, &output_file_name).unwrap();
+            let _ = std::fs::remove_file(failure_marker(&output_file_name));
+        } else {
+            error!(
+                "ffmpeg failed to generate the preview clip of {:?}: {}",
+                original_path,
+                String::from_utf8_lossy(&output.stderr)
+            );
+            let _ = std::fs::remove_file(&partial_file_name);
+            if let Err(err) = std::fs::write(failure_marker(&output_file_name), b"") {
+                warn!(
+                    "cannot record the failure of {:?}: {}",
+                    output_file_name, err
+                );
+            }
+        }
+    }
+
+    output_file_name
+}
+
+fn generate_clip_file_name(options: &Options, original_path: &PathBuf) -> PathBuf {
+    generate_cache_folder_path(options, "clip", original_path).join(format!(
+        "{}.{}",
+        original_path.file_name().unwrap().to_str().unwrap(),
+        options.preview_clip_format.extension()
+    ))
+}
+
+/// the empty file recording that a generation failed
+fn failure_marker(output_file_name: &Path) -> PathBuf {
+    let mut file_name = output_file_name.file_name().unwrap().to_owned();
+    file_name.push(".failed");
+    output_file_name.with_file_name(file_name)
+}
+
+/// a failed generation is not attempted again
+/// until the original is replaced
+fn has_failed(output_file_name: &Path, original_path: &Path) -> bool {
+    let modified = |path: &Path| {
+        path.metadata()
+            .and_then(|metadata| metadata.modified())
+            .ok()
+    };
+    let failed_at = modified(&failure_marker(output_file_name));
+    failed_at.is_some() && failed_at >= modified(original_path)
+}
+
+fn generate_render(
+    options: &State<'_, Options>,
+    statistics: &State<'_, Arc<RwLock<Statistics>>>,
+    width: u64,
+    original_path: &PathBuf,
+    complete_path: &PathBuf,
+) -> PathBuf {
+    // the extension tells the format, a change of the
+    // quality must not serve the renders made before
+    let output_file_name = generate_cache_folder_path(
+        options,
+        &map::location_cache_name(options, &format!("{}w", width)),
+        &original_path,
+    )
+    .join(format!(
+        "{}.q{}.{}",
+        original_path.file_name().unwrap().to_str().unwrap(),
+        options.render_quality,
+        options.render_format.extension()
+    ));
+    trace!("output_file_name == {:#?}", output_file_name);
+    track_render_access(options, statistics);
+
+    // if we already have a render, do not regenerate it
+    if !output_file_name.exists() {
+        track_render_generation(options, statistics);
+
+        // written aside then renamed, the concurrent requests
+        // never see a render that is not stripped yet
+        let partial_file_name = output_file_name.with_file_name(format!(
+            "{}.{}.partial.{}",
+            original_path.file_name().unwrap().to_str().unwrap(),
+            unique_id(),
+            options.render_format.extension()
+        ));
+        let mut cmd = Command::new("convert");
+        let cmd = cmd.args(&[
+            complete_path.to_str().unwrap(),
+            "-auto-orient",
+            "-resize",
+            &format!("{}x>", width),
+            "-quality",
+            &options.render_quality.to_string(),
+            partial_file_name.to_str().unwrap(),
+        ]);
+        trace!("{:#?}", cmd);
+        let output = cmd.output().unwrap();
+        trace!("{:?}", output);
+
+        // the renders are shared by all the users: one that
+        // cannot be stripped is not cached, nor sent
+        let is_stripped = options.location_policy != LocationPolicy::Strip
+            || map::strip_location(&partial_file_name);
+        if !is_stripped || std::fs::rename(&partial_file_name, &output_file_name).is_err() {
+            let _ = std::fs::remove_file(&partial_file_name);
+        }
+    }
+
+    output_file_name
+}
+
+#[get("/render/<width>/<path..>")]
+fn render(
+    options: State<'_, Options>,
+    statistics: State<'_, Arc<RwLock<Statistics>>>,
+    forwarded_identity: ForwardedIdentity,
+    width: u64,
+    path: PathBuf,
+) -> Option<NamedFile> {
+    let path = options.libraries.resolve(&path)?;
+    trace!("requesting: {:?}", &path);
+    trace!("Authenticated as {}", &forwarded_identity);
+    let is_folder_allowed =
+        options.is_folder_allowed_with(&path, &forwarded_identity.email, Permission::Original);
+    trace!("is_folder_allowed == {}", is_folder_allowed);
+
+    if !is_folder_allowed {
+        track_unauthorized_dynamic(&options, &statistics);
+        return None;
+    }
+
+    let media_type = match options.media_types.lookup(&path) {
+        Some(media_type) if !path.is_dir() && !options.hidden_files.is_hidden(&path) => media_type,
+        _ => {
+            track_authorized_not_found(&options, &statistics);
+            return None;
+        }
+    };
+    let width = match options.render_width(width) {
+        Some(width) => width,
+        None => {
+            track_authorized_not_found(&options, &statistics);
+            return None;
+        }
+    };
+    trace!("media_type == {:?}, width == {}", media_type, width);
+
+    let complete_path = match media_type.thumbnailer() {
+        Thumbnailer::Picture => path.clone(),
+        Thumbnailer::EmbeddedPreview => generate_embedded_preview(&options, &statistics, &path),
+        Thumbnailer::Video | Thumbnailer::CoverArt | Thumbnailer::FirstPage => {
+            track_authorized_not_found(&options, &statistics);
+            return None;
+        }
+    };
+
+    track_authorized_dynamic(&options, &statistics);
+    options.audit(
+        &forwarded_identity.email,
+        "render",
+        path.to_str().unwrap(),
+        "get",
+        true,
+    );
+
+    NamedFile::open(generate_render(
+        &options,
+        &statistics,
+        width,
+        &path,
+        &complete_path,
+    ))
+    .ok()
+}
+
+/// The thumbnail format depends on the Accept header
+/// so the caches must keep the variants apart
+#[derive(Responder)]
+struct VaryByAccept {
+    inner: NamedFile,
+    vary: Header<'static>,
+}
+
+impl VaryByAccept {
+    fn new(inner: NamedFile) -> Self {
+        Self {
+            inner,
+            vary: Header::new("Vary", "Accept"),
+        }
+    }
+}
+
 #[get("/thumb/<max_size>/<path..>")]
 fn thumb(
     options: State<'_, Options>,
     statistics: State<'_, Arc<RwLock<Statistics>>>,
     forwarded_identity: ForwardedIdentity,
+    accepted_formats: AcceptedImageFormats,
     max_size: u64,
     path: PathBuf,
-) -> Option<NamedFile> {
-    let path = PathBuf::from("/").join(path);
+) -> Option<VaryByAccept> {
+    let path = options.libraries.resolve(&path)?;
     trace!("requesting: {:?}", &path);
     trace!("Authenticated as {}", &forwarded_identity);
-    let is_folder_allowed = options.is_folder_allowed(&path, &forwarded_identity.email);
+    let permission = options.media_permission(&path);
+    let is_folder_allowed =
+        options.is_folder_allowed_with(&path, &forwarded_identity.email, permission);
     trace!("is_folder_allowed == {}", is_folder_allowed);
 
     if !is_folder_allowed {
@@ -340,65 +1025,135 @@ fn thumb(
         None
     } else {
         trace!("{:?}", path);
+        send_thumb(&options, &statistics, &accepted_formats, max_size, &path)
+    }
+}
 
-        if path.as_path().is_dir() {
-            None
-        } else {
-            track_authorized_thumb(&options, &statistics);
-            trace!("extension == {:?}", path.as_path().extension());
-            let extension = match path.as_path().extension() {
-                Some(ext) => ext.to_str().unwrap().to_lowercase(),
-                None => return None,
-            };
+/// generates the thumbnail if needed, the
+/// caller has already checked the permissions
+fn send_thumb(
+    options: &State<'_, Options>,
+    statistics: &State<'_, Arc<RwLock<Statistics>>>,
+    accepted_formats: &AcceptedImageFormats,
+    max_size: u64,
+    path: &PathBuf,
+) -> Option<VaryByAccept> {
+    if path.as_path().is_dir() || options.hidden_files.is_hidden(path) {
+        return None;
+    }
 
-            if IMAGE_EXTENSIONS.iter().any(|&ext| ext == extension) {
-                NamedFile::open(generate_picture_thumb(
-                    &options,
-                    &statistics,
-                    max_size,
-                    &path,
-                    &path,
-                ))
-                .ok()
-            } else if VIDEO_EXTENSIONS.iter().any(|&ext| ext == extension) {
-                NamedFile::open(generate_video_thumb(
-                    &options,
-                    &statistics,
-                    max_size,
-                    &path,
-                    &path,
-                ))
-                .ok()
-            } else {
-                None
-            }
+    track_authorized_thumb(options, statistics);
+    let media_type = options.media_types.lookup(path)?;
+    let format = options.thumb_format(accepted_formats);
+    trace!("media_type == {:?}, format == {:?}", media_type, format);
+
+    let thumb_path = match media_type.thumbnailer() {
+        Thumbnailer::Picture => {
+            generate_picture_thumb(options, statistics, max_size, format, path, path)
         }
-    }
+        Thumbnailer::EmbeddedPreview => generate_picture_thumb(
+            options,
+            statistics,
+            max_size,
+            format,
+            path,
+            &generate_embedded_preview(options, statistics, path),
+        ),
+        Thumbnailer::Video => {
+            generate_video_thumb(options, statistics, max_size, format, path, path)
+        }
+        Thumbnailer::CoverArt => generate_picture_thumb(
+            options,
+            statistics,
+            max_size,
+            format,
+            path,
+            &generate_cover_art(options, statistics, path)?,
+        ),
+        Thumbnailer::FirstPage => generate_picture_thumb(
+            options,
+            statistics,
+            max_size,
+            format,
+            path,
+            &generate_first_page(options, statistics, path),
+        ),
+    };
+
+    NamedFile::open(thumb_path).ok().map(VaryByAccept::new)
 }
 
-fn is_previewable_file(file: &PathBuf) -> bool {
-    let extension = match file.as_path().extension() {
-        Some(ext) => ext.to_str().unwrap().to_lowercase(),
-        None => return false,
-    };
+#[get("/jpeg/<path..>")]
+fn jpeg(
+    options: State<'_, Options>,
+    statistics: State<'_, Arc<RwLock<Statistics>>>,
+    forwarded_identity: ForwardedIdentity,
+    path: PathBuf,
+) -> Option<NamedFile> {
+    let path = options.libraries.resolve(&path)?;
+    trace!("requesting: {:?}", &path);
+    trace!("Authenticated as {}", &forwarded_identity);
+    let is_folder_allowed =
+        options.is_folder_allowed_with(&path, &forwarded_identity.email, Permission::Original);
+    trace!("is_folder_allowed == {}", is_folder_allowed);
 
-    IMAGE_EXTENSIONS.iter().any(|&ext| ext == extension)
-        || VIDEO_EXTENSIONS.iter().any(|&ext| ext == extension)
+    if !is_folder_allowed {
+        track_unauthorized_dynamic(&options, &statistics);
+        return None;
+    }
+
+    match options.media_types.lookup(&path) {
+        Some(media_type)
+            if !path.is_dir()
+                && !options.hidden_files.is_hidden(&path)
+                && media_type.thumbnailer() == Thumbnailer::EmbeddedPreview =>
+        {
+            track_authorized_dynamic(&options, &statistics);
+            options.audit(
+                &forwarded_identity.email,
+                "jpeg",
+                path.to_str().unwrap(),
+                "get",
+                true,
+            );
+
+            NamedFile::open(generate_embedded_preview(&options, &statistics, &path)).ok()
+        }
+        _ => {
+            track_authorized_not_found(&options, &statistics);
+            None
+        }
+    }
 }
 
 #[get("/list/<file_type>/<path..>")]
 fn list_files<'a>(
     options: State<'a, Options>,
     statistics: State<'a, Arc<RwLock<Statistics>>>,
+    annotations: State<'a, Annotations>,
     forwarded_identity: ForwardedIdentity,
     file_type: FileType,
     path: PathBuf,
 ) -> Response<'a> {
-    let path = PathBuf::from("/").join(path);
+    let path = match options.libraries.resolve(&path) {
+        Some(path) => path,
+        None => {
+            let mut response = Response::new();
+            response.set_status(Status::NotFound);
+            return response;
+        }
+    };
     trace!("Authenticated as {}", &forwarded_identity);
     trace!("requested path == {:?}", &path);
 
-    let is_folder_allowed = options.is_folder_allowed(&path, &forwarded_identity.email);
+    // extra files require the full permission level, everything
+    // else can be listed with just the preview one
+    let required_permission = match file_type {
+        FileType::Extra => Permission::Full,
+        FileType::Preview | FileType::Folder => Permission::Preview,
+    };
+    let is_folder_allowed =
+        options.is_folder_allowed_with(&path, &forwarded_identity.email, required_permission);
     trace!("is_folder_allowed == {}", is_folder_allowed);
 
     if !is_folder_allowed {
@@ -408,6 +1163,13 @@ fn list_files<'a>(
         return response;
     }
 
+    if options.hidden_files.is_hidden(&path) {
+        track_authorized_not_found(&options, &statistics);
+        let mut response = Response::new();
+        response.set_status(Status::NotFound);
+        return response;
+    }
+
     track_authorized_list_files(&options, &statistics, file_type);
 
     let items = match file_type {
@@ -416,13 +1178,15 @@ fn list_files<'a>(
                 .read_dir()
                 .unwrap()
                 .map(|res| res.unwrap().path())
+                .filter(|res| !options.hidden_files.is_hidden_child(res))
                 .filter(|res| res.is_file())
-                .filter(|res| is_previewable_file(&res))
+                .filter(|res| options.media_types.is_previewable(res))
                 .map(|res| {
                     FileWithSize::with_size(
-                        res.to_str().unwrap().to_owned(),
+                        options.libraries.to_public(&res).unwrap(),
                         res.metadata().unwrap().len(),
                     )
+                    .with_annotation(annotations.get(&options, &res))
                 })
                 .collect::<Vec<_>>();
 
@@ -441,13 +1205,15 @@ fn list_files<'a>(
                 .read_dir()
                 .unwrap()
                 .map(|res| res.unwrap().path())
+                .filter(|res| !options.hidden_files.is_hidden_child(res))
                 .filter(|res| res.is_file())
-                .filter(|res| !is_previewable_file(&res))
+                .filter(|res| !options.media_types.is_previewable(res))
                 .map(|res| {
                     FileWithSize::with_size(
-                        res.to_str().unwrap().to_owned(),
+                        options.libraries.to_public(&res).unwrap(),
                         res.metadata().unwrap().len(),
                     )
+                    .with_annotation(annotations.get(&options, &res))
                 })
                 .collect::<Vec<_>>();
 
@@ -466,9 +1232,17 @@ fn list_files<'a>(
                 .read_dir()
                 .unwrap()
                 .map(|res| res.unwrap().path())
+                .filter(|res| !options.hidden_files.is_hidden_child(res))
                 .filter(|res| res.is_dir())
                 .filter(|res| options.is_folder_allowed(&res, &forwarded_identity.email))
-                .map(|res| FileWithSize::without_size(res.to_str().unwrap().to_owned()))
+                .map(|res| {
+                    let metadata = FolderMetadata::load(&res).unwrap_or_default();
+                    let cover = metadata
+                        .cover_path(&options, &res)
+                        .and_then(|cover| options.libraries.to_public(&cover));
+                    FileWithSize::without_size(options.libraries.to_public(&res).unwrap())
+                        .with_folder_metadata(metadata, cover)
+                })
                 .collect::<Vec<_>>();
 
             options.audit(
@@ -501,7 +1275,14 @@ fn is_folder_allowed(
         &forwarded_identity,
         &path
     );
-    let path = PathBuf::from("/").join(path);
+    let path = match options.libraries.resolve(&path) {
+        Some(path) => path,
+        None => {
+            let mut response = Response::new();
+            response.set_status(Status::NotFound);
+            return response;
+        }
+    };
 
     let mut response = Response::new();
     response.set_status(Status::Ok);
@@ -513,13 +1294,49 @@ fn is_folder_allowed(
     response
 }
 
+#[get("/permission/<path..>")]
+fn folder_permission(
+    options: State<'_, Options>,
+    forwarded_identity: ForwardedIdentity,
+    path: PathBuf,
+) -> Response<'_> {
+    trace!(
+        "folder_permission(forwared_identity = {:?}, path == {:?}",
+        &forwarded_identity,
+        &path
+    );
+    let path = match options.libraries.resolve(&path) {
+        Some(path) => path,
+        None => {
+            let mut response = Response::new();
+            response.set_status(Status::NotFound);
+            return response;
+        }
+    };
+
+    let mut response = Response::new();
+    response.set_status(Status::Ok);
+    response.set_sized_body(Cursor::new(
+        serde_json::to_string(&options.folder_permission(&path, &forwarded_identity.email))
+            .unwrap(),
+    ));
+    add_access_control_allow_origin_if_needed(&mut response, &options);
+    response
+}
+
 #[get("/firstlevel")]
 fn get_first_level_folders<'r>(
     options: State<'r, Options>,
     statistics: State<'_, Arc<RwLock<Statistics>>>,
-    first_folder_by_email: State<'r, HashMap<String, Vec<String>>>,
+    first_folder_by_email: State<'r, RwLock<FirstLevelFolders>>,
     forwarded_identity: ForwardedIdentity,
 ) -> Response<'r> {
+    // a time limited rule might have started or
+    // expired since the last calculation
+    if first_folder_by_email.read().unwrap().is_stale(&options) {
+        debug!("ACL changed, recalculating the first level folders");
+        *first_folder_by_email.write().unwrap() = FirstLevelFolders::calculate(&options);
+    }
     trace!("{:#?}", first_folder_by_email);
 
     options.audit(
@@ -541,9 +1358,14 @@ fn get_first_level_folders<'r>(
         add_access_control_allow_origin_if_needed(&mut response, &options);
         response.set_sized_body(Cursor::new(
             serde_json::to_string(
-                first_folder_by_email
-                    .get(&forwarded_identity.email)
-                    .unwrap(),
+                &options.public_first_level_folders(
+                    &forwarded_identity.email,
+                    first_folder_by_email
+                        .read()
+                        .unwrap()
+                        .get(&forwarded_identity.email)
+                        .unwrap(),
+                ),
             )
             .unwrap(),
         ));
@@ -602,10 +1424,38 @@ fn main() {
 
     setup_logger(&options).unwrap();
 
-    let first_folders_by_email = options.calculate_first_level_folders_for_every_user();
-    debug!("first_folders_by_email == {:#?}", first_folders_by_email);
+    options
+        .lint()
+        .iter()
+        .for_each(|warning| warn!("configuration: {}", warning));
+
+    // nas_gallery config.toml duplicates email: prints the
+    // duplicates the user can see and exits
+    if std::env::args().nth(2).as_deref() == Some("duplicates") {
+        let email = std::env::args()
+            .nth(3)
+            .expect("please pass the email of the user after duplicates");
+        let duplicates = Duplicates::open(&options);
+        duplicates.scan_now(&options);
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&duplicates.report(&options, &email)).unwrap()
+        );
+        return;
+    }
+
+    let first_folders_by_email = RwLock::new(FirstLevelFolders::calculate(&options));
 
     let statistics = Arc::new(RwLock::new(Statistics::default()));
+    let preview_clips = PreviewClips::new(&options, statistics.clone());
+    let favorites = Favorites::open(&options.data_folder_path, "favorites");
+    let albums = Albums::open(&options.data_folder_path, "albums");
+    let annotations = Annotations::open(&options.data_folder_path);
+    let comments = Comments::open(&options.data_folder_path, "comments");
+    let uploads = Uploads::new(&options.data_folder_path);
+    let trash = Trash::new(&options);
+    let duplicates = Duplicates::new(&options);
+    let map = Map::new(&options);
 
     if options.prometheus_metrics_enabled {
         let statistics = statistics.clone();
@@ -628,14 +1478,66 @@ fn main() {
             routes![
                 path,
                 thumb,
+                jpeg,
+                render,
+                hls::master_playlist,
+                hls::variant_playlist,
+                hls::segment,
+                storyboard::storyboard,
+                storyboard::storyboard_image,
+                preview_clip::clip,
+                document::text,
+                document::download,
+                listing::list_v2,
+                browse::browse,
+                breadcrumbs::breadcrumbs,
+                favorites::add_favorite,
+                favorites::remove_favorite,
+                favorites::list_favorites,
+                albums::list_albums,
+                albums::create_album,
+                albums::album,
+                albums::update_album,
+                albums::delete_album,
+                albums::album_thumb,
+                annotations::annotation,
+                annotations::annotate,
+                annotations::search,
+                comments::comments,
+                comments::add_comment,
+                comments::delete_comment,
+                comments::latest_comments,
+                upload::start_upload,
+                upload::upload_status,
+                upload::upload_chunk,
+                upload::cancel_upload,
+                manage::rename,
+                manage::move_to,
+                manage::delete,
+                manage::trash_items,
+                manage::restore,
+                manage::purge,
+                duplicates::duplicates,
+                map::map,
                 list_files,
                 get_first_level_folders,
                 is_folder_allowed,
+                folder_permission,
                 site,
                 root,
             ],
         )
         .manage(first_folders_by_email)
+        .manage(preview_clips)
+        .manage(MetadataCache::default())
+        .manage(favorites)
+        .manage(albums)
+        .manage(annotations)
+        .manage(comments)
+        .manage(uploads)
+        .manage(trash)
+        .manage(duplicates)
+        .manage(map)
         .manage(options)
         .manage(statistics)
         .launch();
@@ -0,0 +1,753 @@
+use crate::albums::{move_album_items, Albums};
+use crate::annotations::Annotations;
+use crate::api::{json_response, status_response};
+use crate::comments::Comments;
+use crate::duplicates::scan_roots;
+use crate::favorites::{move_favorites, Favorites};
+use crate::forwarded_identity::ForwardedIdentity;
+use crate::hidden_files::TRASH_FOLDER_NAME;
+use crate::json_store::{unique_id, JsonStore};
+use crate::options::Options;
+use crate::permission::Capability;
+use crate::statistics::*;
+use crate::upload::{is_valid_file_name, move_file};
+use crate::{add_access_control_allow_origin_if_needed, cache_folder_roots};
+use chrono::{DateTime, Duration, Utc};
+use rocket::http::Status;
+use rocket::{Response, State};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::thread;
+
+/// how often the expired items are removed from the trash
+const TRASH_PURGE_INTERVAL_SECONDS: u64 = 60 * 60;
+
+/// A deleted file or folder, kept in the trash
+/// folder until it is restored or purged
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TrashEntry {
+    /// the host path it was deleted from
+    pub original: String,
+    pub deleted_by: String,
+    pub deleted: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+struct TrashItem {
+    id: String,
+    path: String,
+    deleted_by: String,
+    deleted: DateTime<Utc>,
+    is_dir: bool,
+}
+
+/// Where the deleted items go: trash_folder_path if configured,
+/// otherwise a hidden folder at the root of the library of the
+/// item, so the items are renamed and never copied
+#[derive(Clone, Debug)]
+struct TrashFolders {
+    configured: Option<PathBuf>,
+    roots: Vec<PathBuf>,
+}
+
+impl TrashFolders {
+    fn new(options: &Options) -> Self {
+        Self {
+            configured: options.trash_folder_path.as_ref().map(PathBuf::from),
+            roots: scan_roots(options),
+        }
+    }
+
+    fn folder_for(&self, original: &Path) -> Option<PathBuf> {
+        match &self.configured {
+            Some(configured) => Some(configured.to_owned()),
+            None => self
+                .roots
+                .iter()
+                .find(|root| original.starts_with(root))
+                .map(|root| root.join(TRASH_FOLDER_NAME)),
+        }
+    }
+
+    /// the folder holding the item, trash_folder/<id>
+    fn entry_folder(&self, id: &str, entry: &TrashEntry) -> Option<PathBuf> {
+        Some(self.folder_for(Path::new(&entry.original))?.join(id))
+    }
+}
+
+/// The soft deleted items: each one is moved to
+/// <trash folder>/<id>/<name>. The items older than
+/// trash_retention_days are purged in the background.
+#[derive(Debug)]
+pub struct Trash {
+    entries: Arc<JsonStore<BTreeMap<String, TrashEntry>>>,
+    folders: TrashFolders,
+}
+
+impl Trash {
+    pub fn new(options: &Options) -> Self {
+        let entries = Arc::new(JsonStore::open(&options.data_folder_path, "trash"));
+        let folders = TrashFolders::new(options);
+
+        // 0 keeps the items until they are purged by hand
+        if options.trash_retention_days > 0 {
+            let retention_days = options.trash_retention_days;
+            let entries = entries.clone();
+            let folders = folders.clone();
+            thread::spawn(move || loop {
+                let oldest = Utc::now() - Duration::days(retention_days as i64);
+                purge_older_than(&entries, &folders, &oldest);
+                thread::sleep(std::time::Duration::from_secs(TRASH_PURGE_INTERVAL_SECONDS));
+            });
+        }
+
+        Self { entries, folders }
+    }
+
+    fn item_path(&self, id: &str, entry: &TrashEntry) -> Option<PathBuf> {
+        let name = Path::new(&entry.original).file_name()?;
+        Some(self.folders.entry_folder(id, entry)?.join(name))
+    }
+
+    fn get(&self, id: &str) -> Option<TrashEntry> {
+        self.entries.read(|entries| entries.get(id).cloned())
+    }
+
+    fn put(&self, path: &Path, deleted_by: &str) -> std::io::Result<()> {
+        let id = unique_id();
+        let entry = TrashEntry {
+            original: path.to_str().unwrap().to_owned(),
+            deleted_by: deleted_by.to_owned(),
+            deleted: Utc::now(),
+        };
+
+        let item_path = self.item_path(&id, &entry).ok_or_else(no_trash_folder)?;
+        std::fs::create_dir_all(item_path.parent().unwrap())?;
+        move_item(path, &item_path)?;
+        self.entries.update(|entries| {
+            entries.insert(id, entry);
+        })
+    }
+
+    fn take(&self, id: &str, entry: &TrashEntry) -> std::io::Result<()> {
+        let item_path = self.item_path(id, entry).ok_or_else(no_trash_folder)?;
+        move_item(&item_path, Path::new(&entry.original))?;
+        self.forget(id, entry)
+    }
+
+    fn purge(&self, id: &str, entry: &TrashEntry) -> std::io::Result<()> {
+        let entry_folder = self
+            .folders
+            .entry_folder(id, entry)
+            .ok_or_else(no_trash_folder)?;
+        remove_item(&entry_folder)?;
+        self.forget(id, entry)
+    }
+
+    fn forget(&self, id: &str, entry: &TrashEntry) -> std::io::Result<()> {
+        if let Some(entry_folder) = self.folders.entry_folder(id, entry) {
+            let _ = std::fs::remove_dir(entry_folder);
+        }
+        self.entries.update(|entries| {
+            entries.remove(id);
+        })
+    }
+}
+
+fn no_trash_folder() -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::NotFound,
+        "the item is outside every library",
+    )
+}
+
+fn purge_older_than(
+    entries: &JsonStore<BTreeMap<String, TrashEntry>>,
+    folders: &TrashFolders,
+    oldest: &DateTime<Utc>,
+) {
+    let expired = entries.read(|entries| {
+        entries
+            .iter()
+            .filter(|(_, entry)| entry.deleted < *oldest)
+            .map(|(id, entry)| (id.to_owned(), entry.clone()))
+            .collect::<Vec<_>>()
+    });
+
+    expired.iter().for_each(|(id, entry)| {
+        debug!("purging {} from the trash", id);
+        let removed = folders
+            .entry_folder(id, entry)
+            .ok_or_else(no_trash_folder)
+            .and_then(|entry_folder| remove_item(&entry_folder));
+        if let Err(err) = removed {
+            error!("cannot purge {} from the trash: {}", id, err);
+            return;
+        }
+        if let Err(err) = entries.update(|entries| entries.remove(id)) {
+            error!("cannot save the trash: {}", err);
+        }
+    });
+}
+
+fn remove_item(path: &Path) -> std::io::Result<()> {
+    if path.is_dir() {
+        std::fs::remove_dir_all(path)
+    } else if path.exists() {
+        std::fs::remove_file(path)
+    } else {
+        Ok(())
+    }
+}
+
+/// folders can only be renamed, a configured trash_folder_path
+/// must be on the same file system as the libraries
+fn move_item(from: &Path, to: &Path) -> std::io::Result<()> {
+    if from.is_dir() {
+        std::fs::rename(from, to)
+    } else {
+        move_file(from, to)
+    }
+}
+
+/// the cache of host_path inside one of the cache roots
+fn mirrored(cache_root: &Path, host_path: &Path) -> PathBuf {
+    cache_root.join(&host_path.to_str().unwrap()[1..])
+}
+
+/// the cached files of a file are named after it, with a suffix
+fn cached_files(cache_folder: &Path, file_name: &str) -> Vec<(PathBuf, String)> {
+    let prefix = format!("{}.", file_name);
+    cache_folder
+        .read_dir()
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            let suffix = name.strip_prefix(&prefix)?.to_owned();
+            Some((entry.path(), suffix))
+        })
+        .collect()
+}
+
+/// moves the cached thumbnails and renders along with the item.
+/// What cannot be moved is dropped, it will be generated again
+fn move_cache(options: &Options, from: &Path, to: &Path, is_dir: bool) {
+    cache_folder_roots(options).iter().for_each(|cache_root| {
+        let moved = if is_dir {
+            let from_cache = mirrored(cache_root, from);
+            if !from_cache.is_dir() {
+                return;
+            }
+            let to_cache = mirrored(cache_root, to);
+            std::fs::create_dir_all(to_cache.parent().unwrap())
+                .and_then(|_| std::fs::rename(&from_cache, &to_cache))
+        } else {
+            let from_name = from.file_name().unwrap().to_str().unwrap();
+            let to_name = to.file_name().unwrap().to_str().unwrap();
+            let to_cache = mirrored(cache_root, to.parent().unwrap());
+            cached_files(&mirrored(cache_root, from.parent().unwrap()), from_name)
+                .into_iter()
+                .try_for_each(|(cached, suffix)| {
+                    std::fs::create_dir_all(&to_cache)?;
+                    std::fs::rename(cached, to_cache.join(format!("{}.{}", to_name, suffix)))
+                })
+        };
+
+        if let Err(err) = moved {
+            warn!(
+                "cannot move the cache of {:?} in {:?}, dropping it: {}",
+                from, cache_root, err
+            );
+            invalidate_cache_in(cache_root, from, is_dir);
+        }
+    });
+}
+
+fn invalidate_cache(options: &Options, path: &Path, is_dir: bool) {
+    cache_folder_roots(options)
+        .iter()
+        .for_each(|cache_root| invalidate_cache_in(cache_root, path, is_dir));
+}
+
+fn invalidate_cache_in(cache_root: &Path, path: &Path, is_dir: bool) {
+    let removed = if is_dir {
+        remove_item(&mirrored(cache_root, path))
+    } else {
+        let name = path.file_name().unwrap().to_str().unwrap();
+        cached_files(&mirrored(cache_root, path.parent().unwrap()), name)
+            .into_iter()
+            .try_for_each(|(cached, _)| remove_item(&cached))
+    };
+    if let Err(err) = removed {
+        warn!("cannot invalidate the cache of {:?}: {}", path, err);
+    }
+}
+
+/// The stores keyed by public path, they follow the moved items
+struct UserData<'a> {
+    annotations: &'a Annotations,
+    comments: &'a Comments,
+    favorites: &'a Favorites,
+    albums: &'a Albums,
+}
+
+/// keeps the tags, captions, comments, favorites and
+/// album items with the item
+fn move_user_data(options: &Options, user_data: &UserData<'_>, from: &Path, to: &Path) {
+    let (from, to) = match (
+        options.libraries.to_public(from),
+        options.libraries.to_public(to),
+    ) {
+        (Some(from), Some(to)) => (from, to),
+        _ => return,
+    };
+
+    if let Err(err) = user_data.annotations.move_paths(&from, &to) {
+        error!("cannot save the annotations: {}", err);
+    }
+    if let Err(err) = user_data
+        .comments
+        .update(|comments| comments.move_paths(&from, &to))
+    {
+        error!("cannot save the comments: {}", err);
+    }
+    if let Err(err) = move_favorites(user_data.favorites, &from, &to) {
+        error!("cannot save the favorites: {}", err);
+    }
+    if let Err(err) = move_album_items(user_data.albums, &from, &to) {
+        error!("cannot save the albums: {}", err);
+    }
+}
+
+/// the XMP sidecar written next to a file follows it
+fn move_sidecar(from: &Path, to: &Path) {
+    let sidecar = |path: &Path| {
+        let mut file_name = path.file_name().unwrap().to_owned();
+        file_name.push(".xmp");
+        path.with_file_name(file_name)
+    };
+
+    let from = sidecar(from);
+    let to = sidecar(to);
+    if from.is_file() && !to.exists() {
+        if let Err(err) = move_file(&from, &to) {
+            warn!("cannot move the sidecar {:?}: {}", from, err);
+        }
+    }
+}
+
+fn done_response<'r>(options: &Options, status: Status) -> Response<'r> {
+    let mut response = status_response(status);
+    add_access_control_allow_origin_if_needed(&mut response, options);
+    response
+}
+
+fn object_name(path: &Path) -> &'static str {
+    if path.is_dir() {
+        "folder"
+    } else {
+        "file"
+    }
+}
+
+/// the item and the folder containing it must be managed by the user.
+/// The library roots and the paths outside them cannot be touched
+fn authorize_manage(
+    options: &State<'_, Options>,
+    statistics: &State<'_, Arc<RwLock<Statistics>>>,
+    forwarded_identity: &ForwardedIdentity,
+    path: &Path,
+) -> Result<PathBuf, Status> {
+    let path = options.libraries.resolve(path).ok_or(Status::NotFound)?;
+    trace!("requesting: {:?}", &path);
+    trace!("Authenticated as {}", &forwarded_identity);
+    let email = &forwarded_identity.email;
+    let parent = path.parent().ok_or(Status::Forbidden)?.to_path_buf();
+
+    let is_folder_allowed = options.has_capability(&path, email, Capability::Manage)
+        && options.has_capability(&parent, email, Capability::Manage);
+    trace!("is_folder_allowed == {}", is_folder_allowed);
+
+    if !is_folder_allowed {
+        track_unauthorized_dynamic(options, statistics);
+        return Err(Status::Unauthorized);
+    }
+
+    if !path.exists() || options.hidden_files.is_hidden(&path) {
+        track_authorized_not_found(options, statistics);
+        return Err(Status::NotFound);
+    }
+
+    // without [libraries] the roots are the folders of the rules
+    let is_root = options.libraries.roots().any(|(_, root)| root == path)
+        || options
+            .folders
+            .iter()
+            .any(|folder| Path::new(&folder.path) == path)
+        || options.libraries.to_public(&parent).is_none();
+    if is_root {
+        return Err(Status::Forbidden);
+    }
+
+    Ok(path)
+}
+
+/// the folder rules are keyed by host path: moving or deleting a
+/// folder holding rules would drop them or put its content under
+/// the rules of the destination, so it must be done by hand
+fn holds_rules(options: &Options, path: &Path) -> bool {
+    options
+        .folders
+        .iter()
+        .any(|folder| Path::new(&folder.path).starts_with(path))
+}
+
+/// renames a file or a folder, it stays in the same folder
+#[post("/rename/<path..>?<name>")]
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn rename<'r>(
+    options: State<'_, Options>,
+    statistics: State<'_, Arc<RwLock<Statistics>>>,
+    annotations: State<'_, Annotations>,
+    comments: State<'_, Comments>,
+    favorites: State<'_, Favorites>,
+    albums: State<'_, Albums>,
+    forwarded_identity: ForwardedIdentity,
+    path: PathBuf,
+    name: String,
+) -> Response<'r> {
+    let path = match authorize_manage(&options, &statistics, &forwarded_identity, &path) {
+        Ok(path) => path,
+        Err(status) => return status_response(status),
+    };
+
+    if holds_rules(&options, &path) {
+        options.audit(
+            &forwarded_identity.email,
+            "folder",
+            path.to_str().unwrap(),
+            "rename",
+            false,
+        );
+        return status_response(Status::Forbidden);
+    }
+
+    let to = path.with_file_name(&name);
+    if !is_valid_file_name(&name) || options.hidden_files.is_hidden(&to) {
+        return status_response(Status::BadRequest);
+    }
+    if to.exists() {
+        return status_response(Status::Conflict);
+    }
+
+    track_manage(&options, &statistics);
+    let object = object_name(&path);
+    let user_data = UserData {
+        annotations: &annotations,
+        comments: &comments,
+        favorites: &favorites,
+        albums: &albums,
+    };
+    relocate(
+        &options,
+        &user_data,
+        &forwarded_identity,
+        object,
+        "rename",
+        &path,
+        &to,
+    )
+}
+
+/// moves a file or a folder into another folder, the user must
+/// manage the destination too
+#[post("/move/<path..>?<to>")]
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn move_to<'r>(
+    options: State<'_, Options>,
+    statistics: State<'_, Arc<RwLock<Statistics>>>,
+    annotations: State<'_, Annotations>,
+    comments: State<'_, Comments>,
+    favorites: State<'_, Favorites>,
+    albums: State<'_, Albums>,
+    forwarded_identity: ForwardedIdentity,
+    path: PathBuf,
+    to: String,
+) -> Response<'r> {
+    let path = match authorize_manage(&options, &statistics, &forwarded_identity, &path) {
+        Ok(path) => path,
+        Err(status) => return status_response(status),
+    };
+
+    if holds_rules(&options, &path) {
+        options.audit(
+            &forwarded_identity.email,
+            "folder",
+            path.to_str().unwrap(),
+            "move",
+            false,
+        );
+        return status_response(Status::Forbidden);
+    }
+
+    let destination = match options.libraries.resolve(Path::new(&to)) {
+        Some(destination) => destination,
+        None => return status_response(Status::NotFound),
+    };
+    let email = &forwarded_identity.email;
+    if !options.has_capability(&destination, email, Capability::Manage) {
+        track_unauthorized_dynamic(&options, &statistics);
+        return status_response(Status::Unauthorized);
+    }
+    if !destination.is_dir() || options.hidden_files.is_hidden(&destination) {
+        track_authorized_not_found(&options, &statistics);
+        return status_response(Status::NotFound);
+    }
+    // a folder cannot go inside itself
+    if destination.starts_with(&path) {
+        return status_response(Status::BadRequest);
+    }
+
+    let to = destination.join(path.file_name().unwrap());
+    if to.exists() {
+        return status_response(Status::Conflict);
+    }
+
+    track_manage(&options, &statistics);
+    let object = object_name(&path);
+    let user_data = UserData {
+        annotations: &annotations,
+        comments: &comments,
+        favorites: &favorites,
+        albums: &albums,
+    };
+    relocate(
+        &options,
+        &user_data,
+        &forwarded_identity,
+        object,
+        "move",
+        &path,
+        &to,
+    )
+}
+
+fn relocate<'r>(
+    options: &Options,
+    user_data: &UserData<'_>,
+    forwarded_identity: &ForwardedIdentity,
+    object: &str,
+    operation: &str,
+    from: &Path,
+    to: &Path,
+) -> Response<'r> {
+    let is_dir = from.is_dir();
+    if let Err(err) = move_item(from, to) {
+        error!("cannot {} {:?} to {:?}: {}", operation, from, to, err);
+        return status_response(Status::InternalServerError);
+    }
+
+    options.audit(
+        &forwarded_identity.email,
+        object,
+        &format!("{} -> {}", from.to_str().unwrap(), to.to_str().unwrap()),
+        operation,
+        true,
+    );
+
+    if !is_dir {
+        move_sidecar(from, to);
+    }
+    move_cache(options, from, to, is_dir);
+    move_user_data(options, user_data, from, to);
+
+    json_response(
+        options,
+        Status::Ok,
+        &serde_json::json!({ "path": options.libraries.to_public(to) }),
+    )
+}
+
+/// moves a file or a folder to the trash, it can be restored
+/// until it is purged
+#[delete("/files/<path..>")]
+pub(crate) fn delete<'r>(
+    options: State<'_, Options>,
+    statistics: State<'_, Arc<RwLock<Statistics>>>,
+    trash: State<'_, Trash>,
+    forwarded_identity: ForwardedIdentity,
+    path: PathBuf,
+) -> Response<'r> {
+    let path = match authorize_manage(&options, &statistics, &forwarded_identity, &path) {
+        Ok(path) => path,
+        Err(status) => return status_response(status),
+    };
+
+    // the rules would point into the trash, and a restore would
+    // put the content back under rules that may have changed
+    if holds_rules(&options, &path) {
+        options.audit(
+            &forwarded_identity.email,
+            "folder",
+            path.to_str().unwrap(),
+            "delete",
+            false,
+        );
+        return status_response(Status::Forbidden);
+    }
+
+    track_manage(&options, &statistics);
+    let object = object_name(&path);
+    let is_dir = path.is_dir();
+    if let Err(err) = trash.put(&path, &forwarded_identity.email) {
+        error!("cannot move {:?} to the trash: {}", path, err);
+        return status_response(Status::InternalServerError);
+    }
+
+    options.audit(
+        &forwarded_identity.email,
+        object,
+        path.to_str().unwrap(),
+        "delete",
+        true,
+    );
+    invalidate_cache(&options, &path, is_dir);
+
+    done_response(&options, Status::NoContent)
+}
+
+/// the deleted items the user could restore
+#[get("/trash")]
+pub(crate) fn trash_items<'r>(
+    options: State<'_, Options>,
+    statistics: State<'_, Arc<RwLock<Statistics>>>,
+    trash: State<'_, Trash>,
+    forwarded_identity: ForwardedIdentity,
+) -> Response<'r> {
+    if !options.identity_allowed(&forwarded_identity) {
+        return status_response(Status::Unauthorized);
+    }
+    track_manage(&options, &statistics);
+
+    let email = &forwarded_identity.email;
+    let entries = trash.entries.read(|entries| entries.clone());
+    let items = entries
+        .into_iter()
+        .filter_map(|(id, entry)| {
+            let original = PathBuf::from(&entry.original);
+            let can_manage = options
+                .folder_capabilities(&original.parent()?.to_path_buf(), email)
+                .contains(&Capability::Manage);
+            if !can_manage {
+                return None;
+            }
+            Some(TrashItem {
+                path: options.libraries.to_public(&original)?,
+                is_dir: trash.item_path(&id, &entry)?.is_dir(),
+                id,
+                deleted_by: entry.deleted_by,
+                deleted: entry.deleted,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    options.audit(email, "trash", "", "list", true);
+
+    json_response(&options, Status::Ok, &items)
+}
+
+/// finds the trash entry, the user must manage the folder it was deleted from
+fn authorize_trash_entry(
+    options: &State<'_, Options>,
+    statistics: &State<'_, Arc<RwLock<Statistics>>>,
+    trash: &Trash,
+    forwarded_identity: &ForwardedIdentity,
+    id: &str,
+) -> Result<TrashEntry, Status> {
+    let entry = trash.get(id).ok_or(Status::NotFound)?;
+    let parent = Path::new(&entry.original).parent().unwrap().to_path_buf();
+
+    if !options.has_capability(&parent, &forwarded_identity.email, Capability::Manage) {
+        track_unauthorized_dynamic(options, statistics);
+        return Err(Status::Unauthorized);
+    }
+
+    Ok(entry)
+}
+
+#[post("/trash/<id>/restore")]
+pub(crate) fn restore<'r>(
+    options: State<'_, Options>,
+    statistics: State<'_, Arc<RwLock<Statistics>>>,
+    trash: State<'_, Trash>,
+    forwarded_identity: ForwardedIdentity,
+    id: String,
+) -> Response<'r> {
+    let entry = match authorize_trash_entry(&options, &statistics, &trash, &forwarded_identity, &id)
+    {
+        Ok(entry) => entry,
+        Err(status) => return status_response(status),
+    };
+
+    // the folder must still be there and the name free
+    let original = Path::new(&entry.original);
+    if !original.parent().unwrap().is_dir() || original.exists() {
+        return status_response(Status::Conflict);
+    }
+
+    track_manage(&options, &statistics);
+    let object = trash
+        .item_path(&id, &entry)
+        .map_or("file", |item_path| object_name(&item_path));
+    if let Err(err) = trash.take(&id, &entry) {
+        error!("cannot restore {:?}: {}", original, err);
+        return status_response(Status::InternalServerError);
+    }
+
+    options.audit(
+        &forwarded_identity.email,
+        object,
+        &entry.original,
+        "restore",
+        true,
+    );
+    done_response(&options, Status::NoContent)
+}
+
+/// removes the item for good, without waiting for the retention
+#[delete("/trash/<id>")]
+pub(crate) fn purge<'r>(
+    options: State<'_, Options>,
+    statistics: State<'_, Arc<RwLock<Statistics>>>,
+    trash: State<'_, Trash>,
+    forwarded_identity: ForwardedIdentity,
+    id: String,
+) -> Response<'r> {
+    let entry = match authorize_trash_entry(&options, &statistics, &trash, &forwarded_identity, &id)
+    {
+        Ok(entry) => entry,
+        Err(status) => return status_response(status),
+    };
+
+    track_manage(&options, &statistics);
+    let object = trash
+        .item_path(&id, &entry)
+        .map_or("file", |item_path| object_name(&item_path));
+    if let Err(err) = trash.purge(&id, &entry) {
+        error!("cannot purge {:?}: {}", entry.original, err);
+        return status_response(Status::InternalServerError);
+    }
+
+    options.audit(
+        &forwarded_identity.email,
+        object,
+        &entry.original,
+        "purge",
+        true,
+    );
+    done_response(&options, Status::NoContent)
+}
@@ -0,0 +1,531 @@
+use crate::api::{json_response, status_response};
+use crate::duplicates::{previewable_files, scan_roots};
+use crate::forwarded_identity::ForwardedIdentity;
+use crate::json_store::{unique_id, JsonStore};
+use crate::listing::read_metadata;
+use crate::media_type::MediaKind;
+use crate::options::Options;
+use crate::statistics::*;
+use crate::{failure_marker, has_failed};
+use rocket::http::{ContentType, Status};
+use rocket::{Response, State};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::UNIX_EPOCH;
+
+/// the cells of the clustering grid are a quarter of a 256
+/// pixels tile, past this zoom every media is its own point
+const CLUSTER_CELLS_PER_TILE: f64 = 4.0;
+const MAX_CLUSTER_ZOOM: u8 = 18;
+/// the exiftool arguments removing the EXIF and the XMP location
+const STRIP_LOCATION_ARGS: [&str; 2] = ["-gps:all=", "-xmp-exif:gps*="];
+/// the videos being stripped in the background
+static STRIPPING: Mutex<BTreeSet<PathBuf>> = Mutex::new(BTreeSet::new());
+
+/// Who sees the location of the media
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LocationPolicy {
+    /// everyone sees the location of the media they can open
+    #[default]
+    Show,
+    /// only the users with the view_location permission get the map
+    Hide,
+    /// like hide, and the pictures and the videos are sent to the
+    /// other users without the location tags
+    Strip,
+}
+
+/// Where a media file was taken, None if it is not geotagged
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Geotag {
+    /// seconds since the epoch, a changed file is read again
+    pub modified: u64,
+    /// longitude and latitude, in the GeoJSON order
+    pub location: Option<(f64, f64)>,
+}
+
+/// The geotags of the pictures and videos, by host path
+pub type Geotags = JsonStore<BTreeMap<String, Geotag>>;
+
+/// The background scan: every map_scan_interval_hours the
+/// libraries are walked and the new or changed files are read
+/// with exiftool. The map only reads the stored geotags.
+#[derive(Debug, Clone)]
+pub struct Map {
+    geotags: Arc<Geotags>,
+}
+
+impl Map {
+    pub fn new(options: &Options) -> Self {
+        let geotags = Arc::new(Geotags::open(&options.data_folder_path, "geotags"));
+
+        if options.map_enabled {
+            let options = options.clone();
+            let geotags = geotags.clone();
+            thread::spawn(move || loop {
+                scan(&options, &geotags);
+                thread::sleep(std::time::Duration::from_secs(
+                    options.map_scan_interval_hours * 60 * 60,
+                ));
+            });
+        }
+
+        Self { geotags }
+    }
+}
+
+fn modified_secs(path: &Path) -> Option<u64> {
+    Some(
+        path.metadata()
+            .ok()?
+            .modified()
+            .ok()?
+            .duration_since(UNIX_EPOCH)
+            .ok()?
+            .as_secs(),
+    )
+}
+
+fn scan(options: &Options, geotags: &Geotags) {
+    info!("scanning for geotags");
+    let mut files = Vec::new();
+    scan_roots(options)
+        .iter()
+        .for_each(|root| previewable_files(options, root, &mut files));
+    files.retain(|path| {
+        options.media_types.lookup(path).is_some_and(|media_type| {
+            matches!(media_type.kind, MediaKind::Image | MediaKind::Video)
+        })
+    });
+
+    // forget the files that do not exist anymore
+    let existing = files
+        .iter()
+        .filter_map(|path| path.to_str())
+        .collect::<HashSet<_>>();
+    if let Err(err) =
+        geotags.update(|geotags| geotags.retain(|path, _| existing.contains(path.as_str())))
+    {
+        error!("cannot save the geotags: {}", err);
+        return;
+    }
+
+    let changed = geotags.read(|geotags| {
+        files
+            .iter()
+            .filter_map(|path| Some((path.to_str()?, modified_secs(path)?)))
+            .filter(|(path, modified)| {
+                geotags
+                    .get(*path)
+                    .is_none_or(|geotag| geotag.modified != *modified)
+            })
+            .map(|(path, modified)| (PathBuf::from(path), modified))
+            .collect::<Vec<_>>()
+    });
+
+    // a few hundred files at a time, to stay
+    // well below the command line length limit
+    changed.chunks(500).for_each(|chunk| {
+        let metadata = read_metadata(
+            &chunk
+                .iter()
+                .map(|(path, _)| path.as_path())
+                .collect::<Vec<_>>(),
+        );
+        let read = chunk
+            .iter()
+            .map(|(path, modified)| {
+                let location = metadata
+                    .get(path)
+                    .and_then(|metadata| Some((metadata.longitude?, metadata.latitude?)));
+                (
+                    path.to_str().unwrap().to_owned(),
+                    Geotag {
+                        modified: *modified,
+                        location,
+                    },
+                )
+            })
+            .collect::<Vec<_>>();
+        if let Err(err) = geotags.update(|geotags| geotags.extend(read)) {
+            error!("cannot save the geotags: {}", err);
+        }
+    });
+    info!("geotag scan completed, {} files", files.len());
+}
+
+/// west, south, east, north. West greater than
+/// east crosses the antimeridian
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct BoundingBox {
+    west: f64,
+    south: f64,
+    east: f64,
+    north: f64,
+}
+
+impl BoundingBox {
+    const WORLD: BoundingBox = BoundingBox {
+        west: -180.0,
+        south: -90.0,
+        east: 180.0,
+        north: 90.0,
+    };
+
+    fn parse(bbox: &str) -> Option<Self> {
+        let values = bbox
+            .split(',')
+            .map(|value| value.trim().parse::<f64>().ok())
+            .collect::<Option<Vec<_>>>()?;
+        match values[..] {
+            [west, south, east, north]
+                if (-180.0..=180.0).contains(&west)
+                    && (-180.0..=180.0).contains(&east)
+                    && (-90.0..=90.0).contains(&south)
+                    && (-90.0..=90.0).contains(&north)
+                    && south <= north =>
+            {
+                Some(Self {
+                    west,
+                    south,
+                    east,
+                    north,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    fn contains(&self, (longitude, latitude): (f64, f64)) -> bool {
+        let longitude_inside = if self.west <= self.east {
+            self.west <= longitude && longitude <= self.east
+        } else {
+            self.west <= longitude || longitude <= self.east
+        };
+        longitude_inside && self.south <= latitude && latitude <= self.north
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Point {
+    public_path: String,
+    kind: MediaKind,
+    location: (f64, f64),
+}
+
+/// the media grouped by grid cell: a cell holding more than one
+/// becomes a cluster with their number and their bounding box
+fn features(mut points: Vec<Point>, zoom: u8) -> Vec<serde_json::Value> {
+    points.sort_by(|a, b| a.public_path.cmp(&b.public_path));
+
+    let point_feature = |point: &Point| {
+        serde_json::json!({
+            "type": "Feature",
+            "geometry": { "type": "Point", "coordinates": [point.location.0, point.location.1] },
+            "properties": {
+                "path": point.public_path,
+                "kind": point.kind,
+            },
+        })
+    };
+
+    if zoom > MAX_CLUSTER_ZOOM {
+        return points.iter().map(point_feature).collect();
+    }
+
+    let cell_degrees = 360.0 / (2f64.powi(zoom.into()) * CLUSTER_CELLS_PER_TILE);
+    let mut cells: BTreeMap<(i64, i64), Vec<Point>> = BTreeMap::new();
+    points.into_iter().for_each(|point| {
+        let (longitude, latitude) = point.location;
+        let cell = (
+            ((longitude + 180.0) / cell_degrees).floor() as i64,
+            ((latitude + 90.0) / cell_degrees).floor() as i64,
+        );
+        cells.entry(cell).or_default().push(point);
+    });
+
+    cells
+        .values()
+        .map(|cell| {
+            if cell.len() == 1 {
+                return point_feature(&cell[0]);
+            }
+
+            let count = cell.len() as f64;
+            let longitudes = cell.iter().map(|point| point.location.0);
+            let latitudes = cell.iter().map(|point| point.location.1);
+            let bbox = [
+                longitudes.clone().fold(f64::INFINITY, f64::min),
+                latitudes.clone().fold(f64::INFINITY, f64::min),
+                longitudes.clone().fold(f64::NEG_INFINITY, f64::max),
+                latitudes.clone().fold(f64::NEG_INFINITY, f64::max),
+            ];
+            serde_json::json!({
+                "type": "Feature",
+                "geometry": {
+                    "type": "Point",
+                    "coordinates": [
+                        longitudes.sum::<f64>() / count,
+                        latitudes.sum::<f64>() / count,
+                    ],
+                },
+                "bbox": bbox,
+                "properties": {
+                    "cluster": true,
+                    "count": cell.len(),
+                    // the first media, to show a thumbnail
+                    "path": cell[0].public_path,
+                },
+            })
+        })
+        .collect()
+}
+
+/// the geotagged media the user can open as GeoJSON, grouped
+/// in clusters for the zoom level (0 is the whole world)
+#[get("/map?<bbox>&<zoom>")]
+pub(crate) fn map<'r>(
+    options: State<'_, Options>,
+    statistics: State<'_, Arc<RwLock<Statistics>>>,
+    map: State<'_, Map>,
+    forwarded_identity: ForwardedIdentity,
+    bbox: Option<String>,
+    zoom: Option<u8>,
+) -> Response<'r> {
+    let email = &forwarded_identity.email;
+    if !options.map_enabled {
+        return status_response(Status::NotFound);
+    }
+
+    if !options.identity_allowed(&forwarded_identity) || !options.can_view_location(email) {
+        track_unauthorized_dynamic(&options, &statistics);
+        options.audit(email, "map", "", "get", false);
+        return status_response(Status::Unauthorized);
+    }
+
+    let bbox = match bbox.as_deref().map(BoundingBox::parse) {
+        None => BoundingBox::WORLD,
+        Some(Some(bbox)) => bbox,
+        Some(None) => return status_response(Status::BadRequest),
+    };
+
+    track_map(&options, &statistics);
+    let points = map.geotags.read(|geotags| {
+        geotags
+            .iter()
+            .filter_map(|(path, geotag)| {
+                let location = geotag
+                    .location
+                    .filter(|location| bbox.contains(*location))?;
+                let path = PathBuf::from(path);
+                if !path.is_file()
+                    || options.hidden_files.is_hidden(&path)
+                    || options.folder_permission(&path, email).is_none()
+                {
+                    return None;
+                }
+                Some(Point {
+                    public_path: options.libraries.to_public(&path)?,
+                    kind: options.media_types.lookup(&path)?.kind,
+                    location,
+                })
+            })
+            .collect::<Vec<_>>()
+    });
+
+    options.audit(email, "map", "", "get", true);
+    let collection = serde_json::json!({
+        "type": "FeatureCollection",
+        "features": features(points, zoom.unwrap_or(0)),
+    });
+
+    let mut response = json_response(&options, Status::Ok, &collection);
+    response.set_header(ContentType::new("application", "geo+json"));
+    response
+}
+
+/// removes the location tags from a generated file, in
+/// place. False if they might still be there
+pub(crate) fn strip_location(path: &Path) -> bool {
+    let mut cmd = Command::new("exiftool");
+    let cmd = cmd
+        .arg("-overwrite_original")
+        .args(STRIP_LOCATION_ARGS)
+        .arg(path);
+    trace!("{:#?}", cmd);
+    match cmd.output() {
+        Ok(output) => {
+            trace!("{:?}", output);
+            if !output.status.success() {
+                warn!(
+                    "cannot strip the location of {:?}: {}",
+                    path,
+                    String::from_utf8_lossy(&output.stderr)
+                );
+            }
+            output.status.success()
+        }
+        Err(err) => {
+            warn!("cannot strip the location of {:?}: {}", path, err);
+            false
+        }
+    }
+}
+
+/// the name of the cache folder of the generated files shared
+/// by all the users: they are stripped with the strip policy,
+/// the ones cached before it was enabled must not be reused
+pub(crate) fn location_cache_name(options: &Options, name: &str) -> String {
+    if options.location_policy == LocationPolicy::Strip {
+        format!("{}-nogps", name)
+    } else {
+        name.to_string()
+    }
+}
+
+/// The copy without the location tags sent to the
+/// users without the view_location permission
+pub(crate) enum StrippedCopy {
+    Ready(PathBuf),
+    /// the video is being remuxed in the background
+    Pending,
+    /// the original must not be sent in its place
+    Failed,
+}
+
+fn stripped_file_name(options: &Options, original_path: &PathBuf) -> Option<PathBuf> {
+    let file_name = original_path.file_name()?.to_str()?;
+    let extension = original_path.extension()?.to_str()?;
+    Some(
+        crate::generate_cache_folder_path(options, "nogps", original_path)
+            .join(format!("{}.nogps.{}", file_name, extension)),
+    )
+}
+
+/// if we already have an up to date copy, do not strip it again
+fn is_up_to_date(output_file_name: &Path, original_path: &Path) -> bool {
+    let modified = |path: &Path| {
+        path.metadata()
+            .and_then(|metadata| metadata.modified())
+            .ok()
+    };
+    modified(output_file_name).is_some() && modified(output_file_name) >= modified(original_path)
+}
+
+/// the pictures are stripped on the spot. The videos are remuxed
+/// whole, which takes minutes for the big ones: like the preview
+/// clips it is done in the background, once per video, and the
+/// client is expected to retry
+pub(crate) fn without_location(options: &Options, original_path: &PathBuf) -> StrippedCopy {
+    let output_file_name = match stripped_file_name(options, original_path) {
+        Some(output_file_name) => output_file_name,
+        None => return StrippedCopy::Failed,
+    };
+    if is_up_to_date(&output_file_name, original_path) {
+        return StrippedCopy::Ready(output_file_name);
+    }
+
+    let is_video = options
+        .media_types
+        .lookup(original_path)
+        .is_some_and(|media_type| media_type.kind == MediaKind::Video);
+    if !is_video {
+        return match generate_without_location(options, original_path) {
+            Some(path) => StrippedCopy::Ready(path),
+            None => StrippedCopy::Failed,
+        };
+    }
+
+    if has_failed(&output_file_name, original_path) {
+        return StrippedCopy::Failed;
+    }
+    if STRIPPING.lock().unwrap().insert(original_path.to_owned()) {
+        let options = options.clone();
+        let original_path = original_path.to_owned();
+        thread::spawn(move || {
+            if generate_without_location(&options, &original_path).is_some() {
+                let _ = std::fs::remove_file(failure_marker(&output_file_name));
+            } else if let Err(err) = std::fs::write(failure_marker(&output_file_name), b"") {
+                warn!(
+                    "cannot record the failure of {:?}: {}",
+                    output_file_name, err
+                );
+            }
+            STRIPPING.lock().unwrap().remove(&original_path);
+        });
+    }
+    StrippedCopy::Pending
+}
+
+/// a copy of the picture or of the video without the location
+/// tags. None if it cannot be made
+fn generate_without_location(options: &Options, original_path: &PathBuf) -> Option<PathBuf> {
+    let kind = options.media_types.lookup(original_path)?.kind;
+    let file_name = original_path.file_name()?.to_str()?;
+    let extension = original_path.extension()?.to_str()?;
+    let output_file_name = stripped_file_name(options, original_path)?;
+    trace!("output_file_name == {:#?}", output_file_name);
+
+    if is_up_to_date(&output_file_name, original_path) {
+        return Some(output_file_name);
+    }
+
+    // written aside then renamed, the concurrent
+    // requests never see a partial copy
+    let partial_file_name = output_file_name.with_file_name(format!(
+        "{}.{}.partial.{}",
+        file_name,
+        unique_id(),
+        extension
+    ));
+    let mut cmd = match kind {
+        MediaKind::Image => {
+            let mut cmd = Command::new("exiftool");
+            cmd.arg("-o")
+                .arg(&partial_file_name)
+                .args(STRIP_LOCATION_ARGS)
+                .arg(original_path);
+            cmd
+        }
+        // the location of the videos is in the container
+        // metadata, the streams are copied as they are
+        MediaKind::Video => {
+            let mut cmd = Command::new("ffmpeg");
+            cmd.args(&["-v", "error", "-i"])
+                .arg(original_path)
+                .args(&[
+                    "-map",
+                    "0:v",
+                    "-map",
+                    "0:a?",
+                    "-c",
+                    "copy",
+                    "-map_metadata",
+                    "-1",
+                    "-y",
+                ])
+                .arg(&partial_file_name);
+            cmd
+        }
+        _ => return Some(original_path.clone()),
+    };
+    trace!("{:#?}", cmd);
+    let output = cmd.output().ok()?;
+    trace!("{:?}", output);
+    if !output.status.success() || std::fs::rename(&partial_file_name, &output_file_name).is_err() {
+        warn!(
+            "cannot strip the location of {:?}: {}",
+            original_path,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        let _ = std::fs::remove_file(&partial_file_name);
+        return None;
+    }
+
+    Some(output_file_name)
+}
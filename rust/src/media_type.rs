@@ -0,0 +1,255 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::io::Read;
+use std::path::Path;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MediaKind {
+    Image,
+    Video,
+    Audio,
+    /// documents only get a thumbnail, they
+    /// are still listed with the extra files
+    Document,
+}
+
+/// The program used to generate the thumbnail
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Thumbnailer {
+    /// ImageMagick on the file itself
+    Picture,
+    /// ffmpeg frame grab
+    Video,
+    /// ImageMagick on the JPEG preview embedded
+    /// in RAW and HEIC files
+    EmbeddedPreview,
+    /// the picture attached to an audio file or,
+    /// if missing, the cover of its folder
+    CoverArt,
+    /// ImageMagick on the first page of the document
+    FirstPage,
+}
+
+impl MediaKind {
+    pub fn default_thumbnailer(self) -> Thumbnailer {
+        match self {
+            MediaKind::Image => Thumbnailer::Picture,
+            MediaKind::Video => Thumbnailer::Video,
+            MediaKind::Audio => Thumbnailer::CoverArt,
+            MediaKind::Document => Thumbnailer::FirstPage,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MediaType {
+    pub extension: String,
+    pub kind: MediaKind,
+    pub mime: String,
+    pub thumbnailer: Option<Thumbnailer>,
+}
+
+impl MediaType {
+    fn new(extension: &str, kind: MediaKind, mime: &str) -> Self {
+        Self {
+            extension: extension.to_owned(),
+            kind,
+            mime: mime.to_owned(),
+            thumbnailer: None,
+        }
+    }
+
+    fn with_thumbnailer(mut self, thumbnailer: Thumbnailer) -> Self {
+        self.thumbnailer = Some(thumbnailer);
+        self
+    }
+
+    pub fn thumbnailer(&self) -> Thumbnailer {
+        self.thumbnailer
+            .unwrap_or_else(|| self.kind.default_thumbnailer())
+    }
+}
+
+fn default_media_types() -> Vec<MediaType> {
+    vec![
+        MediaType::new("png", MediaKind::Image, "image/png"),
+        MediaType::new("bmp", MediaKind::Image, "image/bmp"),
+        MediaType::new("jpg", MediaKind::Image, "image/jpeg"),
+        MediaType::new("jpeg", MediaKind::Image, "image/jpeg"),
+        MediaType::new("gif", MediaKind::Image, "image/gif"),
+        MediaType::new("webp", MediaKind::Image, "image/webp"),
+        MediaType::new("tif", MediaKind::Image, "image/tiff"),
+        MediaType::new("tiff", MediaKind::Image, "image/tiff"),
+        MediaType::new("heic", MediaKind::Image, "image/heic")
+            .with_thumbnailer(Thumbnailer::EmbeddedPreview),
+        MediaType::new("heif", MediaKind::Image, "image/heif")
+            .with_thumbnailer(Thumbnailer::EmbeddedPreview),
+        MediaType::new("cr2", MediaKind::Image, "image/x-canon-cr2")
+            .with_thumbnailer(Thumbnailer::EmbeddedPreview),
+        MediaType::new("nef", MediaKind::Image, "image/x-nikon-nef")
+            .with_thumbnailer(Thumbnailer::EmbeddedPreview),
+        MediaType::new("arw", MediaKind::Image, "image/x-sony-arw")
+            .with_thumbnailer(Thumbnailer::EmbeddedPreview),
+        MediaType::new("dng", MediaKind::Image, "image/x-adobe-dng")
+            .with_thumbnailer(Thumbnailer::EmbeddedPreview),
+        // most browsers refuse video/x-matroska but will
+        // happily play a matroska with a compatible codec
+        MediaType::new("mkv", MediaKind::Video, "video/mp4"),
+        MediaType::new("mp4", MediaKind::Video, "video/mp4"),
+        MediaType::new("m4v", MediaKind::Video, "video/x-m4v"),
+        MediaType::new("avi", MediaKind::Video, "video/x-msvideo"),
+        MediaType::new("mov", MediaKind::Video, "video/quicktime"),
+        MediaType::new("webm", MediaKind::Video, "video/webm"),
+        MediaType::new("ogv", MediaKind::Video, "video/ogg"),
+        MediaType::new("mpeg", MediaKind::Video, "video/mpeg"),
+        MediaType::new("mpg", MediaKind::Video, "video/mpeg"),
+        MediaType::new("3gp", MediaKind::Video, "video/3gpp"),
+        MediaType::new("mp3", MediaKind::Audio, "audio/mpeg"),
+        MediaType::new("flac", MediaKind::Audio, "audio/flac"),
+        MediaType::new("m4a", MediaKind::Audio, "audio/mp4"),
+        MediaType::new("aac", MediaKind::Audio, "audio/aac"),
+        MediaType::new("ogg", MediaKind::Audio, "audio/ogg"),
+        MediaType::new("oga", MediaKind::Audio, "audio/ogg"),
+        MediaType::new("opus", MediaKind::Audio, "audio/ogg"),
+        MediaType::new("wav", MediaKind::Audio, "audio/wav"),
+        MediaType::new("pdf", MediaKind::Document, "application/pdf"),
+    ]
+}
+
+/// Maps the file extensions to the media types. The built in
+/// types can be extended or overridden in the configuration file.
+/// Files with an unknown extension are identified by their
+/// magic bytes.
+#[derive(Clone, Debug, Default)]
+pub struct MediaTypes {
+    by_extension: HashMap<String, MediaType>,
+}
+
+impl MediaTypes {
+    pub fn new(custom_media_types: Option<&[MediaType]>) -> Self {
+        let mut by_extension = HashMap::new();
+
+        default_media_types()
+            .into_iter()
+            .chain(custom_media_types.unwrap_or(&[]).iter().cloned())
+            .for_each(|media_type| {
+                by_extension.insert(media_type.extension.to_lowercase(), media_type);
+            });
+
+        Self { by_extension }
+    }
+
+    pub fn by_extension(&self, extension: &str) -> Option<&MediaType> {
+        self.by_extension.get(&extension.to_lowercase())
+    }
+
+    pub fn lookup(&self, path: &Path) -> Option<&MediaType> {
+        let by_extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .and_then(|extension| self.by_extension(extension));
+
+        // an unknown extension is not a media, only the files
+        // without one are worth opening to read their header
+        match by_extension {
+            Some(media_type) => Some(media_type),
+            None if path.extension().is_none() => {
+                sniff(path).and_then(|extension| self.by_extension(extension))
+            }
+            None => None,
+        }
+    }
+
+    pub fn is_previewable(&self, path: &Path) -> bool {
+        self.lookup(path)
+            .is_some_and(|media_type| media_type.kind != MediaKind::Document)
+    }
+
+    /// false if the first bytes say the file is of another kind
+    /// than its extension. Unknown contents are given the benefit
+    /// of the doubt, the raw formats cannot be sniffed
+    pub fn content_matches_extension(&self, path: &Path) -> bool {
+        let by_extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .and_then(|extension| self.by_extension(extension));
+        let by_content = sniff(path).and_then(|extension| self.by_extension(extension));
+
+        match (by_extension, by_content) {
+            (Some(by_extension), Some(by_content)) => by_extension.kind == by_content.kind,
+            (Some(_), None) => true,
+            (None, _) => false,
+        }
+    }
+}
+
+/// returns the canonical extension of the file
+/// by looking at its first bytes
+fn sniff(path: &Path) -> Option<&'static str> {
+    if !path.is_file() {
+        return None;
+    }
+
+    let mut header = [0u8; 16];
+    let (read, length) = std::fs::File::open(path)
+        .and_then(|mut file| Ok((file.read(&mut header)?, file.metadata()?.len())))
+        .ok()?;
+    let header = &header[..read];
+    trace!("sniffing {:?}, header == {:?}", path, header);
+
+    if header.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some("jpg")
+    } else if header.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("png")
+    } else if header.starts_with(b"GIF87a") || header.starts_with(b"GIF89a") {
+        Some("gif")
+    } else if is_bmp(header, length) {
+        Some("bmp")
+    } else if header.starts_with(b"II*\0") || header.starts_with(b"MM\0*") {
+        Some("tiff")
+    } else if header.starts_with(b"RIFF") && header.get(8..12) == Some(&b"WEBP"[..]) {
+        Some("webp")
+    } else if header.starts_with(b"RIFF") && header.get(8..12) == Some(&b"AVI "[..]) {
+        Some("avi")
+    } else if header.starts_with(b"%PDF-") {
+        Some("pdf")
+    } else if header.starts_with(b"RIFF") && header.get(8..12) == Some(&b"WAVE"[..]) {
+        Some("wav")
+    } else if header.starts_with(b"ID3") || header.starts_with(&[0xFF, 0xFB]) {
+        Some("mp3")
+    } else if header.starts_with(b"fLaC") {
+        Some("flac")
+    } else if header.starts_with(b"OggS") {
+        Some("ogg")
+    } else if header.starts_with(&[0x1A, 0x45, 0xDF, 0xA3]) {
+        Some("mkv")
+    } else if header.get(4..8) == Some(&b"ftyp"[..]) {
+        match header.get(8..11) {
+            Some(b"qt ") => Some("mov"),
+            Some(b"3gp") => Some("3gp"),
+            Some(b"hei") | Some(b"mif") => Some("heic"),
+            Some(b"M4A") => Some("m4a"),
+            _ => Some("mp4"),
+        }
+    } else {
+        None
+    }
+}
+
+/// "BM" alone is too common a start: the BMP header also holds
+/// the size of the file, two reserved zeros and the offset of the
+/// pixels, past the 26 bytes of the smallest headers
+fn is_bmp(header: &[u8], length: u64) -> bool {
+    let le_u32 = |range: std::ops::Range<usize>| {
+        header
+            .get(range)
+            .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()) as u64)
+    };
+    header.starts_with(b"BM")
+        && le_u32(2..6) == Some(length)
+        && le_u32(6..10) == Some(0)
+        && le_u32(10..14).is_some_and(|offset| (26..length).contains(&offset))
+}
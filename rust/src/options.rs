@@ -1,15 +1,85 @@
 use crate::audit::Audit;
-use crate::folder::Folder;
+use crate::folder::{is_expired_at, is_valid_at, Folder};
 use crate::forwarded_identity::ForwardedIdentity;
+use crate::hidden_files::HiddenFiles;
+use crate::hls::HlsMode;
+use crate::image_format::{AcceptedImageFormats, ImageFormat};
+use crate::libraries::Libraries;
+use crate::map::LocationPolicy;
+use crate::media_type::{MediaKind, MediaType, MediaTypes};
+use crate::permission::{Capability, Permission};
+use crate::preview_clip::PreviewClipFormat;
+use crate::upload::ConflictPolicy;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::convert::TryFrom;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 #[derive(Clone, Debug, Serialize, Default, Deserialize)]
 pub struct Group {
     pub name: String,
+    #[serde(default)]
     pub members_email: Vec<String>,
+    pub memberships: Option<Vec<Membership>>,
+}
+
+/// A group membership limited in time
+#[derive(Clone, Debug, Serialize, Default, Deserialize)]
+pub struct Membership {
+    pub email: String,
+    pub valid_from: Option<DateTime<Utc>>,
+    pub valid_until: Option<DateTime<Utc>>,
+}
+
+impl Membership {
+    pub fn is_valid_at(&self, instant: &DateTime<Utc>) -> bool {
+        is_valid_at(&self.valid_from, &self.valid_until, instant)
+    }
+
+    pub fn is_expired_at(&self, instant: &DateTime<Utc>) -> bool {
+        is_expired_at(&self.valid_until, instant)
+    }
+}
+
+impl Group {
+    /// emails of the members whose membership is valid
+    /// at the given instant
+    pub fn members_at<'a>(&'a self, instant: &'a DateTime<Utc>) -> impl Iterator<Item = &'a str> {
+        self.members_email.iter().map(|email| email as &str).chain(
+            self.memberships
+                .iter()
+                .flatten()
+                .filter(move |membership| membership.is_valid_at(instant))
+                .map(|membership| &membership.email as &str),
+        )
+    }
+
+    pub fn has_expired_membership(&self, email: &str, instant: &DateTime<Utc>) -> bool {
+        self.memberships
+            .iter()
+            .flatten()
+            .any(|membership| membership.email == email && membership.is_expired_at(instant))
+    }
+}
+
+/// What a rule grants to one of its allowed users
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Grant {
+    permission: Permission,
+    capabilities: BTreeSet<Capability>,
+}
+
+/// The outcome of the rule walk for a single path
+#[derive(Debug, Clone, Default)]
+pub struct PermissionCheck {
+    pub permission: Option<Permission>,
+    /// always empty without a permission level
+    pub capabilities: BTreeSet<Capability>,
+    /// true if the user would have been granted
+    /// access by a rule or group membership
+    /// that has expired
+    pub expired_grant: bool,
 }
 
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
@@ -23,6 +93,51 @@ pub struct OptionsInternal {
     pub groups: Vec<Group>,
     pub folders: Vec<Folder>,
     pub prometheus_metrics_enabled: Option<bool>,
+    pub hidden_patterns: Option<Vec<String>>,
+    pub hide_markers: Option<Vec<String>>,
+    pub media_types: Option<Vec<MediaType>>,
+    pub render_widths: Option<Vec<u64>>,
+    pub render_format: Option<ImageFormat>,
+    pub render_quality: Option<u8>,
+    pub thumb_jpeg_quality: Option<u8>,
+    pub thumb_webp_enabled: Option<bool>,
+    pub thumb_webp_quality: Option<u8>,
+    pub thumb_avif_enabled: Option<bool>,
+    pub thumb_avif_quality: Option<u8>,
+    pub hls_mode: Option<HlsMode>,
+    pub hls_ladder: Option<Vec<u64>>,
+    pub hls_segment_seconds: Option<u64>,
+    pub hls_cache_max_bytes: Option<u64>,
+    pub video_thumb_seek_seconds: Option<f64>,
+    pub storyboard_tile_width: Option<u64>,
+    pub storyboard_columns: Option<u64>,
+    pub storyboard_max_tiles: Option<u64>,
+    pub preview_clips_enabled: Option<bool>,
+    pub preview_clip_seconds: Option<u64>,
+    pub preview_clip_width: Option<u64>,
+    pub preview_clip_format: Option<PreviewClipFormat>,
+    pub preview_clip_workers: Option<usize>,
+    pub cover_art_file_names: Option<Vec<String>>,
+    pub text_preview_extensions: Option<Vec<String>>,
+    pub text_preview_max_bytes: Option<u64>,
+    pub api_thumb_size: Option<u64>,
+    pub libraries: Option<BTreeMap<String, String>>,
+    pub data_folder_path: Option<String>,
+    pub xmp_sidecars_enabled: Option<bool>,
+    pub admins: Option<Vec<String>>,
+    pub upload_max_bytes: Option<u64>,
+    pub upload_chunk_max_bytes: Option<u64>,
+    pub upload_conflict_policy: Option<ConflictPolicy>,
+    pub upload_thumb_sizes: Option<Vec<u64>>,
+    pub trash_folder_path: Option<String>,
+    pub trash_retention_days: Option<u64>,
+    pub duplicate_scan_enabled: Option<bool>,
+    pub duplicate_scan_interval_hours: Option<u64>,
+    pub near_duplicate_distance: Option<u32>,
+    pub map_enabled: Option<bool>,
+    pub map_scan_interval_hours: Option<u64>,
+    pub view_location: Option<Vec<String>>,
+    pub location_policy: Option<LocationPolicy>,
 }
 
 #[derive(Clone, Debug)]
@@ -37,9 +152,143 @@ pub struct Options {
     pub groups: Vec<Group>,
     pub folders: Vec<Folder>,
     pub prometheus_metrics_enabled: bool,
+    pub hidden_files: HiddenFiles,
+    pub media_types: MediaTypes,
+    pub render_widths: Vec<u64>,
+    pub render_format: ImageFormat,
+    pub render_quality: u8,
+    pub thumb_jpeg_quality: u8,
+    pub thumb_webp_enabled: bool,
+    pub thumb_webp_quality: u8,
+    pub thumb_avif_enabled: bool,
+    pub thumb_avif_quality: u8,
+    pub hls_mode: HlsMode,
+    pub hls_ladder: Vec<u64>,
+    pub hls_segment_seconds: u64,
+    pub hls_cache_max_bytes: u64,
+    pub video_thumb_seek_seconds: f64,
+    pub storyboard_tile_width: u64,
+    pub storyboard_columns: u64,
+    pub storyboard_max_tiles: u64,
+    pub preview_clips_enabled: bool,
+    pub preview_clip_seconds: u64,
+    pub preview_clip_width: u64,
+    pub preview_clip_format: PreviewClipFormat,
+    pub preview_clip_workers: usize,
+    pub cover_art_file_names: Vec<String>,
+    pub text_preview_extensions: Vec<String>,
+    pub text_preview_max_bytes: u64,
+    pub api_thumb_size: u64,
+    pub libraries: Libraries,
+    pub data_folder_path: String,
+    pub xmp_sidecars_enabled: bool,
+    /// emails and #groups that can moderate
+    pub admins: Vec<String>,
+    pub upload_max_bytes: u64,
+    pub upload_chunk_max_bytes: u64,
+    pub upload_conflict_policy: ConflictPolicy,
+    pub upload_thumb_sizes: Vec<u64>,
+    /// None keeps the trash in each library, see manage
+    pub trash_folder_path: Option<String>,
+    pub trash_retention_days: u64,
+    pub duplicate_scan_enabled: bool,
+    pub duplicate_scan_interval_hours: u64,
+    /// the most bits two perceptual hashes can differ
+    /// by for the pictures to be near duplicates
+    pub near_duplicate_distance: u32,
+    pub map_enabled: bool,
+    pub map_scan_interval_hours: u64,
+    /// emails and #groups with the view_location permission
+    pub view_location: Vec<String>,
+    pub location_policy: LocationPolicy,
     all_emails: HashSet<String>,
 }
 
+/// The resultant set of permissions while walking
+/// the rules of a path from the root down
+#[derive(Clone)]
+struct RuleWalk<'a> {
+    expired_allowed: HashSet<String>,
+    current_allowed: HashMap<String, Grant>,
+    current_denied: HashSet<String>,
+    current_path: &'a str,
+    current_inheritable: bool,
+}
+
+impl<'a> RuleWalk<'a> {
+    fn new() -> Self {
+        Self {
+            expired_allowed: HashSet::new(),
+            current_allowed: HashMap::new(),
+            current_denied: HashSet::new(),
+            current_path: "/",
+            current_inheritable: false,
+        }
+    }
+
+    fn apply(&mut self, subpath: &'a Folder, now: &DateTime<Utc>) {
+        debug!("processing path {:?}", subpath);
+
+        // a rule outside its validity window grants nothing, but
+        // still restricts: expiring a grant must not open the folder
+        // to the audience of the parents
+        let is_valid = subpath.is_valid_at(now);
+        if !is_valid {
+            debug!("ignoring the grants of a rule not valid at {}", now);
+            if subpath.is_expired_at(now) {
+                if let Some(allowed) = &subpath.allowed {
+                    allowed.iter().for_each(|allowed| {
+                        self.expired_allowed.insert(allowed.to_owned());
+                    });
+                }
+            }
+        }
+
+        // if the path breaks inheritance reset the permissions!
+        if let Some(breaks_inheritance) = subpath.breaks_inheritance {
+            if breaks_inheritance {
+                self.current_allowed = HashMap::new();
+                self.current_denied = HashSet::new();
+            }
+        }
+
+        // if it's inheritable save the info
+        self.current_inheritable = if let Some(inheritable) = subpath.inheritable {
+            inheritable
+        } else {
+            false
+        };
+
+        // let's add the relevant items. A deeper rule
+        // overrides the level and the capabilities
+        // granted by its parents
+        // TODO: Remove the unnecessary string
+        // allocations here
+        if let Some(allowed) = subpath.allowed.as_ref().filter(|_| is_valid) {
+            let grant = Grant {
+                permission: subpath.permission.unwrap_or_default(),
+                capabilities: subpath.capabilities.iter().flatten().copied().collect(),
+            };
+            allowed.iter().for_each(|allowed| {
+                self.current_allowed
+                    .insert(allowed.to_owned(), grant.clone());
+            });
+        }
+        if let Some(denied) = &subpath.denied {
+            denied.iter().for_each(|denied| {
+                self.current_denied.insert(denied.to_owned());
+            });
+        }
+
+        self.current_path = &subpath.path;
+
+        debug!("current_path == {:#?}", self.current_path);
+        debug!("current_allowed == {:#?}", self.current_allowed);
+        debug!("current_denied == {:#?}", self.current_denied);
+        debug!("current_inheritable == {:?}", self.current_inheritable);
+    }
+}
+
 impl TryFrom<&str> for Options {
     type Error = toml::de::Error;
 
@@ -53,8 +302,43 @@ impl TryFrom<&str> for Options {
         options.groups.iter().for_each(|group| {
             group.members_email.iter().for_each(|email| {
                 all_emails.insert(email.to_owned());
-            })
+            });
+            group.memberships.iter().flatten().for_each(|membership| {
+                all_emails.insert(membership.email.to_owned());
+            });
         });
+        options
+            .admins
+            .iter()
+            .flatten()
+            .chain(options.view_location.iter().flatten())
+            .filter(|admin| !admin.starts_with('#'))
+            .for_each(|email| {
+                all_emails.insert(email.to_owned());
+            });
+
+        // the transcoded variants are the heights of the ladder
+        if options
+            .hls_ladder
+            .as_ref()
+            .is_some_and(|hls_ladder| !hls_ladder.iter().any(|height| *height > 0))
+        {
+            return Err(serde::de::Error::custom(
+                "hls_ladder must hold at least one height",
+            ));
+        }
+
+        let libraries = Libraries::new(options.libraries.as_ref());
+        let hidden_files = HiddenFiles::new(
+            options.hidden_patterns.as_deref(),
+            options.hide_markers.as_deref(),
+            &options.folders,
+            libraries.content_roots(&options.folders),
+        );
+        let api_thumb_size = options.api_thumb_size.unwrap_or(256);
+        let data_folder_path = options
+            .data_folder_path
+            .unwrap_or_else(|| "/var/lib/nas_gallery".to_owned());
 
         Ok(Options {
             log_level: match options.log_level {
@@ -81,6 +365,91 @@ impl TryFrom<&str> for Options {
             groups: options.groups,
             folders: options.folders,
             prometheus_metrics_enabled: options.prometheus_metrics_enabled.unwrap_or(false),
+            hidden_files,
+            media_types: MediaTypes::new(options.media_types.as_deref()),
+            render_widths: {
+                let mut render_widths = options.render_widths.unwrap_or_else(|| vec![1080, 2160]);
+                render_widths.sort_unstable();
+                render_widths.dedup();
+                render_widths
+            },
+            render_format: options.render_format.unwrap_or_default(),
+            render_quality: options.render_quality.unwrap_or(85),
+            thumb_jpeg_quality: options.thumb_jpeg_quality.unwrap_or(85),
+            thumb_webp_enabled: options.thumb_webp_enabled.unwrap_or(true),
+            thumb_webp_quality: options.thumb_webp_quality.unwrap_or(80),
+            thumb_avif_enabled: options.thumb_avif_enabled.unwrap_or(false),
+            thumb_avif_quality: options.thumb_avif_quality.unwrap_or(50),
+            hls_mode: options.hls_mode.unwrap_or_default(),
+            hls_ladder: options
+                .hls_ladder
+                .map(|hls_ladder| {
+                    hls_ladder
+                        .into_iter()
+                        .filter(|height| *height > 0)
+                        .collect()
+                })
+                .unwrap_or_else(|| vec![1080, 720, 480]),
+            hls_segment_seconds: options.hls_segment_seconds.unwrap_or(6).max(1),
+            hls_cache_max_bytes: options
+                .hls_cache_max_bytes
+                .unwrap_or(10 * 1024 * 1024 * 1024),
+            video_thumb_seek_seconds: options.video_thumb_seek_seconds.unwrap_or(3.0).max(0.0),
+            storyboard_tile_width: options.storyboard_tile_width.unwrap_or(160).max(2),
+            storyboard_columns: options.storyboard_columns.unwrap_or(10).max(1),
+            storyboard_max_tiles: options.storyboard_max_tiles.unwrap_or(100).max(1),
+            preview_clips_enabled: options.preview_clips_enabled.unwrap_or(false),
+            preview_clip_seconds: options.preview_clip_seconds.unwrap_or(3).max(1),
+            preview_clip_width: options.preview_clip_width.unwrap_or(320).max(2) / 2 * 2,
+            preview_clip_format: options.preview_clip_format.unwrap_or_default(),
+            preview_clip_workers: options.preview_clip_workers.unwrap_or(2).max(1),
+            cover_art_file_names: options.cover_art_file_names.unwrap_or_else(|| {
+                [
+                    "cover.jpg",
+                    "folder.jpg",
+                    "front.jpg",
+                    "cover.png",
+                    "folder.png",
+                ]
+                .iter()
+                .map(|file_name| (*file_name).to_owned())
+                .collect()
+            }),
+            text_preview_extensions: options
+                .text_preview_extensions
+                .unwrap_or_else(|| {
+                    ["txt", "md", "markdown", "log", "csv", "nfo", "srt", "vtt"]
+                        .iter()
+                        .map(|extension| (*extension).to_owned())
+                        .collect()
+                })
+                .iter()
+                .map(|extension| extension.to_lowercase())
+                .collect(),
+            text_preview_max_bytes: options.text_preview_max_bytes.unwrap_or(1024 * 1024),
+            api_thumb_size,
+            libraries,
+            trash_folder_path: options.trash_folder_path,
+            data_folder_path,
+            xmp_sidecars_enabled: options.xmp_sidecars_enabled.unwrap_or(false),
+            admins: options.admins.unwrap_or_default(),
+            upload_max_bytes: options.upload_max_bytes.unwrap_or(2 * 1024 * 1024 * 1024),
+            upload_chunk_max_bytes: options.upload_chunk_max_bytes.unwrap_or(8 * 1024 * 1024),
+            upload_conflict_policy: options.upload_conflict_policy.unwrap_or_default(),
+            upload_thumb_sizes: options
+                .upload_thumb_sizes
+                .unwrap_or_else(|| vec![512, api_thumb_size]),
+            trash_retention_days: options.trash_retention_days.unwrap_or(30),
+            duplicate_scan_enabled: options.duplicate_scan_enabled.unwrap_or(false),
+            duplicate_scan_interval_hours: options
+                .duplicate_scan_interval_hours
+                .unwrap_or(24)
+                .max(1),
+            near_duplicate_distance: options.near_duplicate_distance.unwrap_or(4),
+            map_enabled: options.map_enabled.unwrap_or(false),
+            map_scan_interval_hours: options.map_scan_interval_hours.unwrap_or(6).max(1),
+            view_location: options.view_location.unwrap_or_default(),
+            location_policy: options.location_policy.unwrap_or_default(),
             all_emails,
         })
     }
@@ -121,6 +490,137 @@ impl Options {
         forwared_identity.forced() || self.all_emails.contains(&forwared_identity.email)
     }
 
+    pub fn is_admin(&self, user: &str) -> bool {
+        self.is_shared_with(&self.admins, user)
+    }
+
+    /// with the show policy everyone sees the location
+    /// of the media they can open
+    pub fn can_view_location(&self, user: &str) -> bool {
+        self.location_policy == LocationPolicy::Show
+            || self.is_shared_with(&self.view_location, user)
+    }
+
+    /// returns the human readable warnings about the configuration
+    pub fn lint(&self) -> Vec<String> {
+        let now = Utc::now();
+        let mut warnings = Vec::new();
+
+        self.folders.iter().for_each(|folder| {
+            if folder.is_expired_at(&now) {
+                warnings.push(format!(
+                    "the rule on folder {} expired on {}",
+                    folder.path,
+                    folder.valid_until.unwrap()
+                ));
+            }
+            if let (Some(valid_from), Some(valid_until)) = (folder.valid_from, folder.valid_until) {
+                if valid_until <= valid_from {
+                    warnings.push(format!(
+                        "the rule on folder {} is never valid ({} >= {})",
+                        folder.path, valid_from, valid_until
+                    ));
+                }
+            }
+        });
+
+        self.hidden_files
+            .invalid_patterns()
+            .iter()
+            .for_each(|pattern| warnings.push(format!("invalid hidden pattern {}", pattern)));
+
+        self.libraries
+            .roots()
+            .filter(|(_, root)| !root.is_dir())
+            .for_each(|(name, root)| {
+                warnings.push(format!(
+                    "the root of library {} ({:?}) is not a directory",
+                    name, root
+                ))
+            });
+
+        self.groups.iter().for_each(|group| {
+            group
+                .memberships
+                .iter()
+                .flatten()
+                .filter(|membership| membership.is_expired_at(&now))
+                .for_each(|membership| {
+                    warnings.push(format!(
+                        "the membership of {} in group {} expired on {}",
+                        membership.email,
+                        group.name,
+                        membership.valid_until.unwrap()
+                    ))
+                });
+        });
+
+        if !self.view_location.is_empty() && self.location_policy == LocationPolicy::Show {
+            warnings.push(
+                "view_location has no effect, everyone sees the locations with location_policy = \"show\""
+                    .to_owned(),
+            );
+        }
+
+        warnings
+    }
+
+    /// returns true if any rule or group membership
+    /// became valid or expired in the interval (from, to]
+    pub fn acl_changed_between(&self, from: &DateTime<Utc>, to: &DateTime<Utc>) -> bool {
+        let in_interval = |instant: &Option<DateTime<Utc>>| {
+            instant.is_some_and(|instant| *from < instant && instant <= *to)
+        };
+
+        self.folders
+            .iter()
+            .any(|folder| in_interval(&folder.valid_from) || in_interval(&folder.valid_until))
+            || self.groups.iter().any(|group| {
+                group.memberships.iter().flatten().any(|membership| {
+                    in_interval(&membership.valid_from) || in_interval(&membership.valid_until)
+                })
+            })
+    }
+
+    /// the level needed to see a file: the documents are listed
+    /// with the extra files, so they need the same level
+    pub fn media_permission(&self, path: &Path) -> Permission {
+        match self.media_types.lookup(path) {
+            Some(media_type) if media_type.kind == MediaKind::Document => Permission::Full,
+            _ => Permission::Preview,
+        }
+    }
+
+    /// returns the smallest allowed render width that is
+    /// at least the requested one. This way we only cache
+    /// a handful of renditions per picture
+    pub fn render_width(&self, requested_width: u64) -> Option<u64> {
+        self.render_widths
+            .iter()
+            .find(|width| **width >= requested_width)
+            .or_else(|| self.render_widths.last())
+            .copied()
+    }
+
+    /// picks the best enabled thumbnail format the browser supports
+    pub fn thumb_format(&self, accepted_formats: &AcceptedImageFormats) -> ImageFormat {
+        if self.thumb_avif_enabled && accepted_formats.avif {
+            ImageFormat::Avif
+        } else if self.thumb_webp_enabled && accepted_formats.webp {
+            ImageFormat::Webp
+        } else {
+            ImageFormat::Jpeg
+        }
+    }
+
+    pub fn thumb_quality(&self, format: ImageFormat) -> u8 {
+        match format {
+            ImageFormat::Jpeg => self.thumb_jpeg_quality,
+            ImageFormat::Webp => self.thumb_webp_quality,
+            ImageFormat::Avif => self.thumb_avif_quality,
+        }
+    }
+
     pub fn calculate_ancestors(&self) -> Vec<(&Folder, &Folder)> {
         let mut ancestors = Vec::new();
         // for each folder, find the topmost one
@@ -188,7 +688,10 @@ impl Options {
         for anc in ancestors {
             debug!("{} --> {}", anc.0.path, anc.1.path);
             let simplified_path = self.simplify_path(user, anc.1, anc.0);
-            for sim in simplified_path {
+            for sim in simplified_path
+                .into_iter()
+                .filter(|sim| !self.hidden_files.is_hidden(&PathBuf::from(&sim.path)))
+            {
                 hs.insert(&sim.path as &str);
                 debug!("\t{:?}", sim);
             }
@@ -198,6 +701,37 @@ impl Options {
         hs
     }
 
+    /// translates the first level folders of the user into their
+    /// public form. A folder above the libraries is replaced by
+    /// the library roots it contains the user can open
+    pub fn public_first_level_folders(
+        &self,
+        user: &str,
+        first_level_folders: &[String],
+    ) -> Vec<String> {
+        let mut public_folders = first_level_folders
+            .iter()
+            .flat_map(|first_level_folder| {
+                let first_level_folder = Path::new(first_level_folder);
+                match self.libraries.to_public(first_level_folder) {
+                    Some(public_folder) => vec![public_folder],
+                    None => self
+                        .libraries
+                        .roots()
+                        .filter(|(_, root)| root.starts_with(first_level_folder))
+                        .filter(|(_, root)| {
+                            self.folder_permission(&root.to_path_buf(), user).is_some()
+                        })
+                        .map(|(name, _)| name.to_owned())
+                        .collect(),
+                }
+            })
+            .collect::<Vec<_>>();
+        public_folders.sort();
+        public_folders.dedup();
+        public_folders
+    }
+
     pub fn calculate_first_level_folders_for_every_user(&self) -> HashMap<String, Vec<String>> {
         // now call  first_level_allowed_folders for every user and store it
         let mut hm = HashMap::with_capacity(self.all_emails.len());
@@ -216,6 +750,116 @@ impl Options {
     }
 
     pub fn is_folder_allowed(&self, path_to_check: &PathBuf, user_to_check: &str) -> bool {
+        self.is_folder_allowed_with(path_to_check, user_to_check, Permission::Preview)
+    }
+
+    pub fn is_folder_allowed_with(
+        &self,
+        path_to_check: &PathBuf,
+        user_to_check: &str,
+        required_permission: Permission,
+    ) -> bool {
+        let permission_check = self.check_permission(path_to_check, user_to_check);
+        let is_allowed = match permission_check.permission {
+            Some(permission) => permission >= required_permission,
+            None => false,
+        };
+        debug!(
+            "required_permission == {}, is_allowed == {}",
+            required_permission, is_allowed
+        );
+
+        self.audit_check(
+            path_to_check,
+            user_to_check,
+            match path_to_check.is_dir() {
+                true => "directory",
+                false => "file",
+            },
+            &permission_check,
+            is_allowed,
+        );
+
+        is_allowed
+    }
+
+    /// tells if the user can change the path, whatever
+    /// their permission level is
+    pub fn has_capability(
+        &self,
+        path_to_check: &PathBuf,
+        user_to_check: &str,
+        capability: Capability,
+    ) -> bool {
+        let permission_check = self.check_permission(path_to_check, user_to_check);
+        let is_allowed = permission_check.capabilities.contains(&capability);
+        debug!("capability == {}, is_allowed == {}", capability, is_allowed);
+
+        self.audit_check(
+            path_to_check,
+            user_to_check,
+            capability.as_str(),
+            &permission_check,
+            is_allowed,
+        );
+
+        is_allowed
+    }
+
+    fn audit_check(
+        &self,
+        path_to_check: &PathBuf,
+        user_to_check: &str,
+        obj: &str,
+        permission_check: &PermissionCheck,
+        is_allowed: bool,
+    ) {
+        self.audit(
+            user_to_check,
+            obj,
+            path_to_check.to_str().unwrap(),
+            "check",
+            is_allowed,
+        );
+
+        if !is_allowed && permission_check.expired_grant {
+            self.audit(
+                user_to_check,
+                "grant",
+                path_to_check.to_str().unwrap(),
+                "expired",
+                false,
+            );
+        }
+    }
+
+    /// returns the permission level the user has on the path,
+    /// or None if the user cannot access it at all
+    pub fn folder_permission(
+        &self,
+        path_to_check: &PathBuf,
+        user_to_check: &str,
+    ) -> Option<Permission> {
+        self.check_permission(path_to_check, user_to_check)
+            .permission
+    }
+
+    /// returns what the user can change on the path,
+    /// without auditing the check
+    pub fn folder_capabilities(
+        &self,
+        path_to_check: &PathBuf,
+        user_to_check: &str,
+    ) -> BTreeSet<Capability> {
+        self.check_permission(path_to_check, user_to_check)
+            .capabilities
+    }
+
+    pub fn check_permission(
+        &self,
+        path_to_check: &PathBuf,
+        user_to_check: &str,
+    ) -> PermissionCheck {
         // we need to traverse the path from root to here and collect the
         // resultant permissions
         debug!(
@@ -224,15 +868,110 @@ impl Options {
             path_to_check.is_dir()
         );
 
-        let mut current_allowed: HashSet<String> = HashSet::new();
-        let mut current_denied: HashSet<String> = HashSet::new();
-        let mut current_path = "/";
-        let mut current_inheritable = false;
+        let now = Utc::now();
+        let mut walk = RuleWalk::new();
+
+        // since rules are sorted by construction we can enumerate one by one and
+        // calculate the resultant set of permissions
+        self.rules_for(path_to_check)
+            .into_iter()
+            .for_each(|subpath| walk.apply(subpath, &now));
+
+        self.resolve(&walk, path_to_check.to_str().unwrap(), user_to_check, &now)
+    }
+
+    /// the permission of the user on each entry of the folder, the
+    /// rules of the folder and its ancestors are walked only once
+    pub fn entries_permissions(
+        &self,
+        folder: &PathBuf,
+        entries: &[PathBuf],
+        user_to_check: &str,
+    ) -> Vec<Option<Permission>> {
+        let now = Utc::now();
+        let mut walk = RuleWalk::new();
+        let folder_rules = self.rules_for(folder);
+        folder_rules
+            .iter()
+            .for_each(|subpath| walk.apply(subpath, &now));
+
+        // only the rules below the folder can tell the entries apart
+        let folder_str = folder.to_str().unwrap();
+        let deeper_rules = self
+            .folders
+            .iter()
+            .filter(|subpath| {
+                subpath.path.starts_with(folder_str)
+                    && !folder_rules
+                        .iter()
+                        .any(|rule| std::ptr::eq(*rule, *subpath))
+            })
+            .collect::<Vec<_>>();
+
+        // every entry without a rule of its own resolves the same
+        let mut inherited = None;
+        entries
+            .iter()
+            .map(|entry| {
+                let entry_str = entry.to_str().unwrap();
+                let entry_rules = deeper_rules
+                    .iter()
+                    .filter(|subpath| entry_str.starts_with(&subpath.path))
+                    .collect::<Vec<_>>();
+                if entry_rules.is_empty() {
+                    return *inherited.get_or_insert_with(|| {
+                        self.resolve(&walk, entry_str, user_to_check, &now)
+                            .permission
+                    });
+                }
+                let mut entry_walk = walk.clone();
+                entry_rules
+                    .into_iter()
+                    .for_each(|subpath| entry_walk.apply(subpath, &now));
+                self.resolve(&entry_walk, entry_str, user_to_check, &now)
+                    .permission
+            })
+            .collect()
+    }
+
+    /// checks the path and every one of its ancestors, from the
+    /// root down, with a single walk through the rules
+    pub fn check_ancestors(
+        &self,
+        path_to_check: &PathBuf,
+        user_to_check: &str,
+    ) -> Vec<(PathBuf, PermissionCheck)> {
+        let now = Utc::now();
+        let mut walk = RuleWalk::new();
+        let mut rules = self.rules_for(path_to_check).into_iter().peekable();
+
+        let mut ancestors = path_to_check.ancestors().collect::<Vec<_>>();
+        ancestors.reverse();
+
+        ancestors
+            .into_iter()
+            .map(|ancestor| {
+                let ancestor_str = ancestor.to_str().unwrap();
+                // the rules are prefixes of the same path, the
+                // ones of an ancestor come before the others
+                while let Some(subpath) =
+                    rules.next_if(|subpath| ancestor_str.starts_with(&subpath.path))
+                {
+                    walk.apply(subpath, &now);
+                }
+                (
+                    ancestor.to_path_buf(),
+                    self.resolve(&walk, ancestor_str, user_to_check, &now),
+                )
+            })
+            .collect()
+    }
 
-        // first find all the paths (from "root" path)
-        // in securities composing the given path
-        // that is, every folder that is superfolder
-        // of directory_to_check
+    /// finds all the paths (from "root" path)
+    /// in securities composing the given path
+    /// that is, every folder that is superfolder
+    /// of directory_to_check
+    fn rules_for(&self, path_to_check: &PathBuf) -> Vec<&Folder> {
         let subpaths: Vec<&Folder> = self
             .folders
             .iter()
@@ -248,55 +987,36 @@ impl Options {
             .collect::<_>();
 
         debug!("subpaths == {:?}", subpaths);
+        subpaths
+    }
 
-        // since subpaths are sorted by construction we can enumerate one by one and
-        // calculate the resultant set of permissions
-        for subpath in subpaths {
-            debug!("processing path {:?}", subpath);
-
-            // if the path breaks inheritance reset the permissions!
-            #[allow(unused_assignments)]
-            if let Some(breaks_inheritance) = subpath.breaks_inheritance {
-                if breaks_inheritance {
-                    current_allowed = HashSet::new();
-                    current_denied = HashSet::new();
-                    current_inheritable = false;
-                }
-            }
-
-            // if it's inheritable save the info
-            current_inheritable = if let Some(inheritable) = subpath.inheritable {
-                inheritable
-            } else {
-                false
-            };
-
-            // let's add the relevant items
-            // TODO: Remove the unnecessary string
-            // allocations here
-            if let Some(allowed) = &subpath.allowed {
-                allowed.iter().for_each(|allowed| {
-                    current_allowed.insert(allowed.to_owned());
-                });
-            }
-            if let Some(denied) = &subpath.denied {
-                denied.iter().for_each(|denied| {
-                    current_denied.insert(denied.to_owned());
-                });
-            }
-
-            current_path = &subpath.path;
-
-            debug!("current_path == {:#?}", current_path);
-            debug!("current_allowed == {:#?}", current_allowed);
-            debug!("current_denied == {:#?}", current_denied);
-            debug!("current_inheritable == {:?}", current_inheritable);
-        }
+    /// turns the state of the walk into the
+    /// permission of the user on the path
+    fn resolve(
+        &self,
+        walk: &RuleWalk<'_>,
+        path_to_check: &str,
+        user_to_check: &str,
+        now: &DateTime<Utc>,
+    ) -> PermissionCheck {
+        // keep track of the expired grants so we can
+        // explain a denial
+        let expired_grant = self
+            .explode_group_ignoring_validity(walk.expired_allowed.clone())
+            .contains(user_to_check)
+            || walk.current_allowed.keys().any(|item| {
+                item.starts_with('#')
+                    && self
+                        .groups
+                        .iter()
+                        .filter(|group| group.name == item[1..])
+                        .any(|group| group.has_expired_membership(user_to_check, now))
+            });
 
         // now we have the resultant policy, let's check it!
         // first let's explode the groups
-        let current_allowed = self.explode_group(current_allowed);
-        let current_denied = self.explode_group(current_denied);
+        let current_allowed = self.explode_group_permissions(walk.current_allowed.clone(), now);
+        let current_denied = self.explode_group(walk.current_denied.clone(), now);
         debug!(
             "after group explosion current_allowed == {:#?}",
             current_allowed
@@ -308,42 +1028,83 @@ impl Options {
 
         // If the directory to check is not the same as the
         // last checked path and inheritance is disabled
-        // we return false
-        let is_allowed = if path_to_check.to_str().unwrap() != current_path && !current_inheritable
-        {
-            false
+        // we return None
+        let grant = if path_to_check != walk.current_path && !walk.current_inheritable {
+            None
+        } else if current_denied.iter().any(|user| user == user_to_check) {
+            // the denied list always wins
+            None
         } else {
-            // authorize the user if it's not in the denied list
-            // and is in the authorized one
-            current_denied
-                .iter()
-                .find(|user| user == &user_to_check)
-                .is_none()
-                && current_allowed.iter().any(|user| user == user_to_check)
+            current_allowed.get(user_to_check).cloned()
         };
 
-        self.audit(
-            user_to_check,
-            match path_to_check.is_dir() {
-                true => "directory",
-                false => "file",
-            },
-            path_to_check.to_str().unwrap(),
-            "check",
-            is_allowed,
-        );
+        PermissionCheck {
+            permission: grant.as_ref().map(|grant| grant.permission),
+            capabilities: grant.map(|grant| grant.capabilities).unwrap_or_default(),
+            expired_grant,
+        }
+    }
 
-        is_allowed
+    /// tells if the user is one of the emails or a current
+    /// member of one of the #groups in the list
+    pub fn is_shared_with(&self, shared_with: &[String], user: &str) -> bool {
+        let shared_with = shared_with.iter().cloned().collect::<HashSet<_>>();
+        self.explode_group(shared_with, &Utc::now()).contains(user)
+    }
+
+    fn explode_group_permissions(
+        &self,
+        hm: HashMap<String, Grant>,
+        instant: &DateTime<Utc>,
+    ) -> HashMap<String, Grant> {
+        let mut tmp: HashMap<String, Grant> = HashMap::new();
+        hm.into_iter().for_each(|(item, grant)| {
+            let mut single = HashSet::new();
+            single.insert(item);
+            // if a user is granted more than once the highest
+            // level wins and the capabilities add up
+            self.explode_group(single, instant)
+                .into_iter()
+                .for_each(|email| {
+                    let entry = tmp.entry(email).or_insert_with(|| grant.clone());
+                    entry.permission = entry.permission.max(grant.permission);
+                    entry
+                        .capabilities
+                        .extend(grant.capabilities.iter().copied());
+                });
+        });
+
+        tmp
+    }
+
+    fn explode_group_ignoring_validity(&self, hs: HashSet<String>) -> HashSet<String> {
+        let mut tmp = HashSet::new();
+        hs.into_iter().for_each(|item| {
+            if item.starts_with('#') {
+                if let Some(group) = self.groups.iter().find(|group| group.name == item[1..]) {
+                    group.members_email.iter().for_each(|email| {
+                        tmp.insert(email.to_owned());
+                    });
+                    group.memberships.iter().flatten().for_each(|membership| {
+                        tmp.insert(membership.email.to_owned());
+                    });
+                }
+            } else {
+                tmp.insert(item);
+            }
+        });
+
+        tmp
     }
 
-    fn explode_group(&self, hs: HashSet<String>) -> HashSet<String> {
+    fn explode_group(&self, hs: HashSet<String>, instant: &DateTime<Utc>) -> HashSet<String> {
         let mut tmp = HashSet::new();
         hs.iter().for_each(|item| {
             if item.starts_with('#') {
                 // find the corresponding group
                 if let Some(group) = self.groups.iter().find(|group| group.name == item[1..]) {
                     // if found, let's add it!
-                    group.members_email.iter().for_each(|email| {
+                    group.members_at(instant).for_each(|email| {
                         tmp.insert(email.to_owned());
                     });
                 } else {
@@ -361,3 +1122,518 @@ impl Options {
         tmp
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: &str = r#"
+        log_file = "/tmp/nas_gallery.log"
+        static_site_path = "/tmp/site"
+        thumb_folder_path = "/tmp/thumbs"
+
+        [[groups]]
+        name = "Family"
+        members_email = ["mom@foo.bar", "dad@foo.bar"]
+    "#;
+
+    fn options(folders: &str) -> Options {
+        Options::try_from(&format!("{}\n{}", BASE, folders) as &str).unwrap()
+    }
+
+    fn permission(options: &Options, path: &str, user: &str) -> Option<Permission> {
+        options.folder_permission(&PathBuf::from(path), user)
+    }
+
+    #[test]
+    fn inheritable_rule_grants_its_level_to_the_subfolders() {
+        let options = options(
+            r##"
+            [[folders]]
+            path = "/nas"
+            inheritable = true
+            allowed = ["#Family"]
+            permission = "original"
+
+            [[folders]]
+            path = "/nas/private"
+            allowed = ["mom@foo.bar"]
+            "##,
+        );
+
+        assert_eq!(
+            permission(&options, "/nas/trip/day1/a.jpg", "dad@foo.bar"),
+            Some(Permission::Original)
+        );
+        // without inheritable the rule only covers its folder
+        assert_eq!(
+            permission(&options, "/nas/private", "mom@foo.bar"),
+            Some(Permission::Full)
+        );
+        assert_eq!(
+            permission(&options, "/nas/private/a.jpg", "mom@foo.bar"),
+            None
+        );
+        assert_eq!(permission(&options, "/other/a.jpg", "dad@foo.bar"), None);
+    }
+
+    #[test]
+    fn deeper_rule_can_downgrade_the_level() {
+        let options = options(
+            r##"
+            [[folders]]
+            path = "/nas"
+            inheritable = true
+            allowed = ["#Family"]
+
+            [[folders]]
+            path = "/nas/scans"
+            inheritable = true
+            allowed = ["#Family"]
+            permission = "preview"
+            "##,
+        );
+
+        assert_eq!(
+            permission(&options, "/nas/a.jpg", "mom@foo.bar"),
+            Some(Permission::Full)
+        );
+        assert_eq!(
+            permission(&options, "/nas/scans/a.jpg", "mom@foo.bar"),
+            Some(Permission::Preview)
+        );
+        assert!(options.is_folder_allowed_with(
+            &PathBuf::from("/nas/scans/a.jpg"),
+            "mom@foo.bar",
+            Permission::Preview
+        ));
+        assert!(!options.is_folder_allowed_with(
+            &PathBuf::from("/nas/scans/a.jpg"),
+            "mom@foo.bar",
+            Permission::Original
+        ));
+    }
+
+    #[test]
+    fn highest_of_group_and_individual_levels_wins() {
+        let options = options(
+            r##"
+            [[folders]]
+            path = "/nas"
+            inheritable = true
+            allowed = ["#Family", "dad@foo.bar"]
+            permission = "preview"
+
+            [[folders]]
+            path = "/nas/party"
+            inheritable = true
+            allowed = ["#Family"]
+            permission = "original"
+
+            [[folders]]
+            path = "/nas/party/raw"
+            inheritable = true
+            allowed = ["#Family"]
+            permission = "preview"
+            "##,
+        );
+
+        assert_eq!(
+            permission(&options, "/nas/a.jpg", "dad@foo.bar"),
+            Some(Permission::Preview)
+        );
+        // the group grant is higher than the individual one
+        assert_eq!(
+            permission(&options, "/nas/party/a.jpg", "dad@foo.bar"),
+            Some(Permission::Original)
+        );
+        // the individual grant of the parent is kept
+        // by the rule granting only the group
+        assert_eq!(
+            permission(&options, "/nas/party/raw/a.jpg", "dad@foo.bar"),
+            Some(Permission::Preview)
+        );
+    }
+
+    #[test]
+    fn denied_wins_over_allowed() {
+        let options = options(
+            r##"
+            [[folders]]
+            path = "/nas"
+            inheritable = true
+            allowed = ["#Family"]
+
+            [[folders]]
+            path = "/nas/gifts"
+            inheritable = true
+            allowed = ["mom@foo.bar"]
+            denied = ["dad@foo.bar"]
+
+            [[folders]]
+            path = "/nas/gifts/opened"
+            inheritable = true
+            allowed = ["dad@foo.bar"]
+            "##,
+        );
+
+        assert_eq!(
+            permission(&options, "/nas/gifts/a.jpg", "dad@foo.bar"),
+            None
+        );
+        assert_eq!(
+            permission(&options, "/nas/gifts/a.jpg", "mom@foo.bar"),
+            Some(Permission::Full)
+        );
+        // the denial is inherited along with the grants
+        assert_eq!(
+            permission(&options, "/nas/gifts/opened/a.jpg", "dad@foo.bar"),
+            None
+        );
+    }
+
+    #[test]
+    fn breaking_inheritance_drops_the_parent_grants() {
+        let options = options(
+            r##"
+            [[folders]]
+            path = "/nas"
+            inheritable = true
+            allowed = ["#Family"]
+            denied = ["friend@foo.bar"]
+
+            [[folders]]
+            path = "/nas/kids"
+            inheritable = true
+            breaks_inheritance = true
+            allowed = ["mom@foo.bar", "friend@foo.bar"]
+            permission = "original"
+            "##,
+        );
+
+        assert_eq!(permission(&options, "/nas/kids/a.jpg", "dad@foo.bar"), None);
+        assert_eq!(
+            permission(&options, "/nas/kids/a.jpg", "mom@foo.bar"),
+            Some(Permission::Original)
+        );
+        // the denials of the parents are dropped too
+        assert_eq!(
+            permission(&options, "/nas/kids/a.jpg", "friend@foo.bar"),
+            Some(Permission::Original)
+        );
+    }
+
+    #[test]
+    fn expired_rule_keeps_breaking_inheritance() {
+        let options = options(
+            r##"
+            [[folders]]
+            path = "/nas"
+            inheritable = true
+            allowed = ["#Family"]
+
+            [[folders]]
+            path = "/nas/party"
+            inheritable = true
+            breaks_inheritance = true
+            allowed = ["friend@foo.bar"]
+            valid_until = "2000-01-01T00:00:00Z"
+            "##,
+        );
+
+        assert_eq!(permission(&options, "/nas/party", "friend@foo.bar"), None);
+        assert_eq!(
+            permission(&options, "/nas/party/a.jpg", "mom@foo.bar"),
+            None
+        );
+        assert_eq!(
+            permission(&options, "/nas/other/a.jpg", "mom@foo.bar"),
+            Some(Permission::Full)
+        );
+        assert!(
+            options
+                .check_permission(&PathBuf::from("/nas/party"), "friend@foo.bar")
+                .expired_grant
+        );
+    }
+
+    #[test]
+    fn future_rule_keeps_breaking_inheritance() {
+        let options = options(
+            r##"
+            [[folders]]
+            path = "/nas"
+            inheritable = true
+            allowed = ["#Family"]
+
+            [[folders]]
+            path = "/nas/party"
+            inheritable = true
+            breaks_inheritance = true
+            allowed = ["friend@foo.bar"]
+            valid_from = "2999-01-01T00:00:00Z"
+            "##,
+        );
+
+        assert_eq!(permission(&options, "/nas/party", "friend@foo.bar"), None);
+        assert_eq!(
+            permission(&options, "/nas/party/a.jpg", "dad@foo.bar"),
+            None
+        );
+        assert!(
+            !options
+                .check_permission(&PathBuf::from("/nas/party"), "friend@foo.bar")
+                .expired_grant
+        );
+    }
+
+    #[test]
+    fn expired_rule_keeps_denying() {
+        let options = options(
+            r##"
+            [[folders]]
+            path = "/nas"
+            inheritable = true
+            allowed = ["#Family"]
+
+            [[folders]]
+            path = "/nas/gifts"
+            inheritable = true
+            allowed = ["friend@foo.bar"]
+            denied = ["dad@foo.bar"]
+            valid_until = "2000-01-01T00:00:00Z"
+            "##,
+        );
+
+        assert_eq!(
+            permission(&options, "/nas/gifts/a.jpg", "dad@foo.bar"),
+            None
+        );
+        assert_eq!(
+            permission(&options, "/nas/gifts/a.jpg", "friend@foo.bar"),
+            None
+        );
+        assert_eq!(
+            permission(&options, "/nas/gifts/a.jpg", "mom@foo.bar"),
+            Some(Permission::Full)
+        );
+    }
+
+    #[test]
+    fn expired_rule_is_not_inheritable_by_default() {
+        let options = options(
+            r##"
+            [[folders]]
+            path = "/nas"
+            inheritable = true
+            allowed = ["#Family"]
+
+            [[folders]]
+            path = "/nas/party"
+            allowed = ["friend@foo.bar"]
+            valid_until = "2000-01-01T00:00:00Z"
+            "##,
+        );
+
+        // the rule still sets the inheritance of its folder
+        assert_eq!(
+            permission(&options, "/nas/party", "mom@foo.bar"),
+            Some(Permission::Full)
+        );
+        assert_eq!(
+            permission(&options, "/nas/party/a.jpg", "mom@foo.bar"),
+            None
+        );
+    }
+
+    #[test]
+    fn capabilities_do_not_depend_on_the_level() {
+        let options = options(
+            r##"
+            [[folders]]
+            path = "/nas"
+            inheritable = true
+            allowed = ["#Family"]
+            capabilities = ["annotate"]
+
+            [[folders]]
+            path = "/nas/drop"
+            inheritable = true
+            allowed = ["friend@foo.bar", "#Family"]
+            permission = "preview"
+            capabilities = ["upload"]
+
+            [[folders]]
+            path = "/nas/drop/mom"
+            inheritable = true
+            allowed = ["#Family", "mom@foo.bar"]
+            permission = "original"
+            capabilities = ["manage"]
+            "##,
+        );
+        let capabilities = |path: &str, user: &str| {
+            options
+                .folder_capabilities(&PathBuf::from(path), user)
+                .into_iter()
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            capabilities("/nas/a.jpg", "dad@foo.bar"),
+            vec![Capability::Annotate]
+        );
+        // a deeper rule replaces the capabilities of its parents
+        assert_eq!(
+            permission(&options, "/nas/drop/a.jpg", "friend@foo.bar"),
+            Some(Permission::Preview)
+        );
+        assert_eq!(
+            capabilities("/nas/drop/a.jpg", "friend@foo.bar"),
+            vec![Capability::Upload]
+        );
+        assert_eq!(
+            capabilities("/nas/drop/a.jpg", "dad@foo.bar"),
+            vec![Capability::Upload]
+        );
+        assert_eq!(
+            capabilities("/nas/drop/mom/a.jpg", "mom@foo.bar"),
+            vec![Capability::Manage]
+        );
+        assert!(capabilities("/nas/other/a.jpg", "friend@foo.bar").is_empty());
+    }
+
+    #[test]
+    fn expired_membership_grants_nothing() {
+        let options = options(
+            r##"
+            [[groups]]
+            name = "Friends"
+            members_email = ["friend@foo.bar"]
+
+            [[groups.memberships]]
+            email = "guest@foo.bar"
+            valid_until = "2000-01-01T00:00:00Z"
+
+            [[groups.memberships]]
+            email = "visitor@foo.bar"
+            valid_from = "2000-01-01T00:00:00Z"
+
+            [[folders]]
+            path = "/nas/party"
+            inheritable = true
+            allowed = ["#Friends"]
+            "##,
+        );
+
+        assert_eq!(
+            permission(&options, "/nas/party/a.jpg", "friend@foo.bar"),
+            Some(Permission::Full)
+        );
+        assert_eq!(
+            permission(&options, "/nas/party/a.jpg", "visitor@foo.bar"),
+            Some(Permission::Full)
+        );
+        assert_eq!(
+            permission(&options, "/nas/party/a.jpg", "guest@foo.bar"),
+            None
+        );
+        assert!(
+            options
+                .check_permission(&PathBuf::from("/nas/party/a.jpg"), "guest@foo.bar")
+                .expired_grant
+        );
+        assert!(
+            !options
+                .check_permission(&PathBuf::from("/nas/party/a.jpg"), "stranger@foo.bar")
+                .expired_grant
+        );
+    }
+
+    #[test]
+    fn acl_changes_when_a_validity_bound_is_crossed() {
+        let options = options(
+            r##"
+            [[groups]]
+            name = "Friends"
+
+            [[groups.memberships]]
+            email = "guest@foo.bar"
+            valid_until = "2020-06-01T00:00:00Z"
+
+            [[folders]]
+            path = "/nas/party"
+            allowed = ["#Friends"]
+            valid_from = "2020-01-01T00:00:00Z"
+            "##,
+        );
+        let instant = |instant: &str| instant.parse::<DateTime<Utc>>().unwrap();
+
+        assert!(options.acl_changed_between(
+            &instant("2019-12-31T00:00:00Z"),
+            &instant("2020-01-01T00:00:00Z")
+        ));
+        assert!(options.acl_changed_between(
+            &instant("2020-05-31T00:00:00Z"),
+            &instant("2020-06-02T00:00:00Z")
+        ));
+        // the interval is open at its start
+        assert!(!options.acl_changed_between(
+            &instant("2020-01-01T00:00:00Z"),
+            &instant("2020-05-31T00:00:00Z")
+        ));
+        assert!(!options.acl_changed_between(
+            &instant("2020-06-02T00:00:00Z"),
+            &instant("2030-01-01T00:00:00Z")
+        ));
+    }
+
+    #[test]
+    fn empty_hls_ladder_is_refused() {
+        let config = format!("hls_ladder = []\nfolders = []\n{}", BASE);
+        let err = Options::try_from(&config as &str).unwrap_err();
+        assert!(err.to_string().contains("hls_ladder"));
+        let config = format!("hls_ladder = [0, 720]\nfolders = []\n{}", BASE);
+        assert_eq!(
+            Options::try_from(&config as &str).unwrap().hls_ladder,
+            vec![720]
+        );
+    }
+
+    #[test]
+    fn entries_permissions_match_the_single_checks() {
+        let options = options(
+            r##"
+            [[folders]]
+            path = "/nas"
+            inheritable = true
+            allowed = ["#Family"]
+
+            [[folders]]
+            path = "/nas/private"
+            allowed = ["mom@foo.bar"]
+
+            [[folders]]
+            path = "/nas/scans"
+            inheritable = true
+            allowed = ["#Family"]
+            permission = "preview"
+            "##,
+        );
+        let entries = ["/nas/private", "/nas/privateer", "/nas/scans", "/nas/trip"]
+            .iter()
+            .map(PathBuf::from)
+            .collect::<Vec<_>>();
+
+        ["mom@foo.bar", "dad@foo.bar", "other@foo.bar"]
+            .iter()
+            .for_each(|user| {
+                assert_eq!(
+                    options.entries_permissions(&PathBuf::from("/nas"), &entries, user),
+                    entries
+                        .iter()
+                        .map(|entry| options.folder_permission(entry, user))
+                        .collect::<Vec<_>>()
+                );
+            });
+    }
+}
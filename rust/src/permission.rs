@@ -0,0 +1,67 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+
+/// The access level a folder rule grants to its allowed users.
+/// Levels are cumulative: each one includes everything the
+/// previous ones allow.
+#[derive(
+    Debug, Default, Copy, Clone, PartialEq, PartialOrd, Eq, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    /// thumbnails and folder listings
+    Preview,
+    /// streaming and downloading the originals
+    Original,
+    /// the extra files and the documents. There is no archive
+    /// download to gate yet. Rules written before the
+    /// permission levels were introduced granted everything
+    #[default]
+    Full,
+}
+
+impl Display for Permission {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        write!(fmt, "{}", self.as_str())
+    }
+}
+
+impl Permission {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Permission::Preview => "preview",
+            Permission::Original => "original",
+            Permission::Full => "full",
+        }
+    }
+}
+
+/// What a folder rule allows its users to change. The
+/// capabilities are granted independently of the level:
+/// an uploader does not need to see the extra files
+#[derive(Debug, Copy, Clone, PartialEq, PartialOrd, Eq, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    /// editing the tags and the captions
+    Annotate,
+    /// adding files to the folder
+    Upload,
+    /// renaming, moving and deleting
+    Manage,
+}
+
+impl Display for Capability {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        write!(fmt, "{}", self.as_str())
+    }
+}
+
+impl Capability {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Capability::Annotate => "annotate",
+            Capability::Upload => "upload",
+            Capability::Manage => "manage",
+        }
+    }
+}
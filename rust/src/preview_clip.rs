@@ -0,0 +1,183 @@
+use crate::api::{authorize_video, status_response};
+use crate::forwarded_identity::ForwardedIdentity;
+use crate::options::Options;
+use crate::permission::Permission;
+use crate::statistics::*;
+use crate::{
+    add_access_control_allow_origin_if_needed, generate_clip_file_name, generate_video_clip,
+    has_failed,
+};
+use rocket::http::{ContentType, Status};
+use rocket::{Response, State};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+
+/// The format of the animated preview clips
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PreviewClipFormat {
+    #[default]
+    Mp4,
+    Webp,
+}
+
+impl PreviewClipFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            PreviewClipFormat::Mp4 => "mp4",
+            PreviewClipFormat::Webp => "webp",
+        }
+    }
+
+    fn content_type(self) -> ContentType {
+        match self {
+            PreviewClipFormat::Mp4 => ContentType::new("video", "mp4"),
+            PreviewClipFormat::Webp => ContentType::new("image", "webp"),
+        }
+    }
+}
+
+/// Generates the preview clips in the background: a fixed
+/// number of workers share the queue so we never run more than
+/// preview_clip_workers ffmpeg at the same time. A video already
+/// in the queue is not enqueued twice.
+#[derive(Debug, Clone)]
+pub struct PreviewClips {
+    send_channel: Option<Arc<Mutex<Sender<PathBuf>>>>,
+    pending: Arc<Mutex<HashSet<PathBuf>>>,
+}
+
+impl PreviewClips {
+    pub fn new(options: &Options, statistics: Arc<RwLock<Statistics>>) -> Self {
+        let pending = Arc::new(Mutex::new(HashSet::new()));
+
+        if !options.preview_clips_enabled {
+            return Self {
+                send_channel: None,
+                pending,
+            };
+        }
+
+        let (tx, rx) = channel::<PathBuf>();
+        let rx = Arc::new(Mutex::new(rx));
+
+        (0..options.preview_clip_workers).for_each(|_| {
+            let rx = rx.clone();
+            let pending = pending.clone();
+            let options = options.clone();
+            let statistics = statistics.clone();
+            thread::spawn(move || {
+                while let Some(original_path) = next_job(&rx) {
+                    track_clip_generation(&options, &statistics);
+                    generate_video_clip(&options, &original_path);
+                    pending.lock().unwrap().remove(&original_path);
+                }
+            });
+        });
+
+        Self {
+            send_channel: Some(Arc::new(Mutex::new(tx))),
+            pending,
+        }
+    }
+
+    /// returns false if the clips are disabled
+    pub fn enqueue(&self, original_path: &PathBuf) -> bool {
+        let send_channel = match &self.send_channel {
+            Some(send_channel) => send_channel,
+            None => return false,
+        };
+
+        if self
+            .pending
+            .lock()
+            .unwrap()
+            .insert(original_path.to_owned())
+        {
+            trace!("enqueuing preview clip of {:?}", original_path);
+            send_channel
+                .lock()
+                .unwrap()
+                .send(original_path.to_owned())
+                .unwrap();
+        }
+        true
+    }
+}
+
+fn next_job(rx: &Mutex<Receiver<PathBuf>>) -> Option<PathBuf> {
+    // the lock is released as soon as we get the job,
+    // so the other workers can pick the next one
+    rx.lock().unwrap().recv().ok()
+}
+
+/// serves the preview clip of the video if we already have it,
+/// otherwise schedules its generation and answers 202 Accepted:
+/// the client is expected to keep showing the thumbnail and retry.
+/// A video ffmpeg failed with has no clip, 404 Not Found
+#[get("/clip/<path..>")]
+pub(crate) fn clip<'r>(
+    options: State<'_, Options>,
+    statistics: State<'_, Arc<RwLock<Statistics>>>,
+    preview_clips: State<'_, PreviewClips>,
+    forwarded_identity: ForwardedIdentity,
+    path: PathBuf,
+) -> Response<'r> {
+    if !options.preview_clips_enabled {
+        return status_response(Status::NotFound);
+    }
+
+    let (path, _) = match authorize_video(
+        &options,
+        &statistics,
+        &forwarded_identity,
+        path,
+        Permission::Preview,
+    ) {
+        Ok(authorized) => authorized,
+        Err(status) => return status_response(status),
+    };
+
+    let mut response = Response::new();
+
+    track_clip_access(&options, &statistics);
+
+    let output_file_name = generate_clip_file_name(&options, &path);
+    if !output_file_name.exists() && has_failed(&output_file_name, &path) {
+        response.set_status(Status::NotFound);
+        add_access_control_allow_origin_if_needed(&mut response, &options);
+        return response;
+    }
+    if !output_file_name.exists() {
+        preview_clips.enqueue(&path);
+        response.set_status(Status::Accepted);
+        add_access_control_allow_origin_if_needed(&mut response, &options);
+        return response;
+    }
+
+    match std::fs::File::open(&output_file_name) {
+        Ok(file) => {
+            options.audit(
+                &forwarded_identity.email,
+                "video",
+                path.to_str().unwrap(),
+                "clip",
+                true,
+            );
+            response.set_status(Status::Ok);
+            response.set_header(options.preview_clip_format.content_type());
+            add_access_control_allow_origin_if_needed(&mut response, &options);
+            response.set_sized_body(file);
+            response
+        }
+        Err(err) => {
+            error!("cannot open preview clip {:?}: {}", output_file_name, err);
+            response.set_status(Status::InternalServerError);
+            response
+        }
+    }
+}
@@ -137,271 +137,1074 @@ pub(crate) fn track_video_thumb_generation(
 }
 
 #[inline]
-pub(crate) fn track_unauthorized_static(
+pub(crate) fn track_embedded_preview_access(
     options: &State<'_, Options>,
     statistics: &State<'_, Arc<RwLock<Statistics>>>,
-    path: &str,
 ) {
-    // only keep track of the accesses if the
-    // prometheus exporting has been
-    // enabled!
     if options.prometheus_metrics_enabled {
-        statistics.write().unwrap().inc_unathorized_static(path);
+        statistics.write().unwrap().embedded_preview_access += 1;
     }
 }
 
 #[inline]
-pub(crate) fn track_authorized_not_found(
+pub(crate) fn track_embedded_preview_generation(
     options: &State<'_, Options>,
     statistics: &State<'_, Arc<RwLock<Statistics>>>,
 ) {
-    // only keep track of the accesses if the
-    // prometheus exporting has been
-    // enabled!
     if options.prometheus_metrics_enabled {
-        statistics.write().unwrap().authorized_not_found += 1;
+        statistics.write().unwrap().embedded_preview_generation += 1;
     }
 }
 
 #[inline]
-pub(crate) fn track_unauthorized_thumb(
+pub(crate) fn track_render_access(
     options: &State<'_, Options>,
     statistics: &State<'_, Arc<RwLock<Statistics>>>,
 ) {
-    // only keep track of the accesses if the
-    // prometheus exporting has been
-    // enabled!
     if options.prometheus_metrics_enabled {
-        statistics.write().unwrap().unauthorized_thumb += 1;
+        statistics.write().unwrap().render_access += 1;
     }
 }
 
 #[inline]
-pub(crate) fn track_authorized_thumb(
+pub(crate) fn track_render_generation(
     options: &State<'_, Options>,
     statistics: &State<'_, Arc<RwLock<Statistics>>>,
 ) {
-    // only keep track of the accesses if the
-    // prometheus exporting has been
-    // enabled!
     if options.prometheus_metrics_enabled {
-        statistics.write().unwrap().authorized_thumb += 1;
+        statistics.write().unwrap().render_generation += 1;
     }
 }
 
-#[derive(Debug)]
-pub struct Statistics {
-    pub authorized_static: HashMap<String, u64>,
-    pub unathorized_static: HashMap<String, u64>,
-    pub unauthorized_dynamic: u64,
-    pub authorized_dynamic: u64,
-    pub authorized_not_found: u64,
-    pub authorized_thumb: u64,
-    pub unauthorized_thumb: u64,
-    pub picture_thumb_access: u64,
-    pub picture_thumb_generation: u64,
-    pub video_thumb_access: u64,
-    pub video_thumb_generation: u64,
-    pub authorized_list_files: HashMap<FileType, u64>,
-    pub unauthorized_list_files: HashMap<FileType, u64>,
-    pub authorized_first_level_folders: u64,
-    pub unauthorized_first_level_folders: u64,
+#[inline]
+pub(crate) fn track_hls_segment_access(
+    options: &State<'_, Options>,
+    statistics: &State<'_, Arc<RwLock<Statistics>>>,
+) {
+    if options.prometheus_metrics_enabled {
+        statistics.write().unwrap().hls_segment_access += 1;
+    }
+}
+
+#[inline]
+pub(crate) fn track_hls_segment_generation(
+    options: &State<'_, Options>,
+    statistics: &State<'_, Arc<RwLock<Statistics>>>,
+) {
+    if options.prometheus_metrics_enabled {
+        statistics.write().unwrap().hls_segment_generation += 1;
+    }
+}
+
+#[inline]
+pub(crate) fn track_storyboard_access(
+    options: &State<'_, Options>,
+    statistics: &State<'_, Arc<RwLock<Statistics>>>,
+) {
+    if options.prometheus_metrics_enabled {
+        statistics.write().unwrap().storyboard_access += 1;
+    }
+}
+
+#[inline]
+pub(crate) fn track_storyboard_generation(
+    options: &State<'_, Options>,
+    statistics: &State<'_, Arc<RwLock<Statistics>>>,
+) {
+    if options.prometheus_metrics_enabled {
+        statistics.write().unwrap().storyboard_generation += 1;
+    }
+}
+
+#[inline]
+pub(crate) fn track_clip_access(
+    options: &State<'_, Options>,
+    statistics: &State<'_, Arc<RwLock<Statistics>>>,
+) {
+    if options.prometheus_metrics_enabled {
+        statistics.write().unwrap().clip_access += 1;
+    }
+}
+
+#[inline]
+pub(crate) fn track_clip_generation(options: &Options, statistics: &RwLock<Statistics>) {
+    if options.prometheus_metrics_enabled {
+        statistics.write().unwrap().clip_generation += 1;
+    }
+}
+
+#[inline]
+pub(crate) fn track_cover_art_access(
+    options: &State<'_, Options>,
+    statistics: &State<'_, Arc<RwLock<Statistics>>>,
+) {
+    if options.prometheus_metrics_enabled {
+        statistics.write().unwrap().cover_art_access += 1;
+    }
+}
+
+#[inline]
+pub(crate) fn track_cover_art_generation(
+    options: &State<'_, Options>,
+    statistics: &State<'_, Arc<RwLock<Statistics>>>,
+) {
+    if options.prometheus_metrics_enabled {
+        statistics.write().unwrap().cover_art_generation += 1;
+    }
+}
+
+#[inline]
+pub(crate) fn track_first_page_access(
+    options: &State<'_, Options>,
+    statistics: &State<'_, Arc<RwLock<Statistics>>>,
+) {
+    if options.prometheus_metrics_enabled {
+        statistics.write().unwrap().first_page_access += 1;
+    }
+}
+
+#[inline]
+pub(crate) fn track_first_page_generation(
+    options: &State<'_, Options>,
+    statistics: &State<'_, Arc<RwLock<Statistics>>>,
+) {
+    if options.prometheus_metrics_enabled {
+        statistics.write().unwrap().first_page_generation += 1;
+    }
+}
+
+#[inline]
+pub(crate) fn track_text_preview_access(
+    options: &State<'_, Options>,
+    statistics: &State<'_, Arc<RwLock<Statistics>>>,
+) {
+    if options.prometheus_metrics_enabled {
+        statistics.write().unwrap().text_preview_access += 1;
+    }
+}
+
+#[inline]
+pub(crate) fn track_download_access(
+    options: &State<'_, Options>,
+    statistics: &State<'_, Arc<RwLock<Statistics>>>,
+) {
+    if options.prometheus_metrics_enabled {
+        statistics.write().unwrap().download_access += 1;
+    }
+}
+
+#[inline]
+pub(crate) fn track_authorized_list_v2(
+    options: &State<'_, Options>,
+    statistics: &State<'_, Arc<RwLock<Statistics>>>,
+) {
+    if options.prometheus_metrics_enabled {
+        statistics.write().unwrap().authorized_list_v2 += 1;
+    }
+}
+
+#[inline]
+pub(crate) fn track_unauthorized_list_v2(
+    options: &State<'_, Options>,
+    statistics: &State<'_, Arc<RwLock<Statistics>>>,
+) {
+    if options.prometheus_metrics_enabled {
+        statistics.write().unwrap().unauthorized_list_v2 += 1;
+    }
+}
+
+#[inline]
+pub(crate) fn track_authorized_browse(
+    options: &State<'_, Options>,
+    statistics: &State<'_, Arc<RwLock<Statistics>>>,
+) {
+    if options.prometheus_metrics_enabled {
+        statistics.write().unwrap().authorized_browse += 1;
+    }
+}
+
+#[inline]
+pub(crate) fn track_unauthorized_browse(
+    options: &State<'_, Options>,
+    statistics: &State<'_, Arc<RwLock<Statistics>>>,
+) {
+    if options.prometheus_metrics_enabled {
+        statistics.write().unwrap().unauthorized_browse += 1;
+    }
+}
+
+#[inline]
+pub(crate) fn track_authorized_breadcrumbs(
+    options: &State<'_, Options>,
+    statistics: &State<'_, Arc<RwLock<Statistics>>>,
+) {
+    if options.prometheus_metrics_enabled {
+        statistics.write().unwrap().authorized_breadcrumbs += 1;
+    }
+}
+
+#[inline]
+pub(crate) fn track_unauthorized_breadcrumbs(
+    options: &State<'_, Options>,
+    statistics: &State<'_, Arc<RwLock<Statistics>>>,
+) {
+    if options.prometheus_metrics_enabled {
+        statistics.write().unwrap().unauthorized_breadcrumbs += 1;
+    }
+}
+
+#[inline]
+pub(crate) fn track_favorite_change(
+    options: &State<'_, Options>,
+    statistics: &State<'_, Arc<RwLock<Statistics>>>,
+) {
+    if options.prometheus_metrics_enabled {
+        statistics.write().unwrap().favorite_change += 1;
+    }
+}
+
+#[inline]
+pub(crate) fn track_favorite_list(
+    options: &State<'_, Options>,
+    statistics: &State<'_, Arc<RwLock<Statistics>>>,
+) {
+    if options.prometheus_metrics_enabled {
+        statistics.write().unwrap().favorite_list += 1;
+    }
+}
+
+#[inline]
+pub(crate) fn track_album_access(
+    options: &State<'_, Options>,
+    statistics: &State<'_, Arc<RwLock<Statistics>>>,
+) {
+    if options.prometheus_metrics_enabled {
+        statistics.write().unwrap().album_access += 1;
+    }
+}
+
+#[inline]
+pub(crate) fn track_album_change(
+    options: &State<'_, Options>,
+    statistics: &State<'_, Arc<RwLock<Statistics>>>,
+) {
+    if options.prometheus_metrics_enabled {
+        statistics.write().unwrap().album_change += 1;
+    }
+}
+
+#[inline]
+pub(crate) fn track_annotation_change(
+    options: &State<'_, Options>,
+    statistics: &State<'_, Arc<RwLock<Statistics>>>,
+) {
+    if options.prometheus_metrics_enabled {
+        statistics.write().unwrap().annotation_change += 1;
+    }
+}
+
+#[inline]
+pub(crate) fn track_search(
+    options: &State<'_, Options>,
+    statistics: &State<'_, Arc<RwLock<Statistics>>>,
+) {
+    if options.prometheus_metrics_enabled {
+        statistics.write().unwrap().search += 1;
+    }
+}
+
+#[inline]
+pub(crate) fn track_comment_access(
+    options: &State<'_, Options>,
+    statistics: &State<'_, Arc<RwLock<Statistics>>>,
+) {
+    if options.prometheus_metrics_enabled {
+        statistics.write().unwrap().comment_access += 1;
+    }
+}
+
+#[inline]
+pub(crate) fn track_comment_change(
+    options: &State<'_, Options>,
+    statistics: &State<'_, Arc<RwLock<Statistics>>>,
+) {
+    if options.prometheus_metrics_enabled {
+        statistics.write().unwrap().comment_change += 1;
+    }
+}
+
+#[inline]
+pub(crate) fn track_upload(
+    options: &State<'_, Options>,
+    statistics: &State<'_, Arc<RwLock<Statistics>>>,
+) {
+    if options.prometheus_metrics_enabled {
+        statistics.write().unwrap().upload += 1;
+    }
 }
 
-impl Default for Statistics {
-    fn default() -> Self {
-        let mut authorized_list_files = HashMap::new();
-        authorized_list_files.insert(FileType::Preview, 0);
-        authorized_list_files.insert(FileType::Extra, 0);
-        authorized_list_files.insert(FileType::Folder, 0);
+#[inline]
+pub(crate) fn track_manage(
+    options: &State<'_, Options>,
+    statistics: &State<'_, Arc<RwLock<Statistics>>>,
+) {
+    if options.prometheus_metrics_enabled {
+        statistics.write().unwrap().manage += 1;
+    }
+}
+
+#[inline]
+pub(crate) fn track_duplicate_report(
+    options: &State<'_, Options>,
+    statistics: &State<'_, Arc<RwLock<Statistics>>>,
+) {
+    if options.prometheus_metrics_enabled {
+        statistics.write().unwrap().duplicate_report += 1;
+    }
+}
+
+#[inline]
+pub(crate) fn track_map(
+    options: &State<'_, Options>,
+    statistics: &State<'_, Arc<RwLock<Statistics>>>,
+) {
+    if options.prometheus_metrics_enabled {
+        statistics.write().unwrap().map += 1;
+    }
+}
+
+#[inline]
+pub(crate) fn track_unauthorized_static(
+    options: &State<'_, Options>,
+    statistics: &State<'_, Arc<RwLock<Statistics>>>,
+    path: &str,
+) {
+    // only keep track of the accesses if the
+    // prometheus exporting has been
+    // enabled!
+    if options.prometheus_metrics_enabled {
+        statistics.write().unwrap().inc_unathorized_static(path);
+    }
+}
+
+#[inline]
+pub(crate) fn track_authorized_not_found(
+    options: &State<'_, Options>,
+    statistics: &State<'_, Arc<RwLock<Statistics>>>,
+) {
+    // only keep track of the accesses if the
+    // prometheus exporting has been
+    // enabled!
+    if options.prometheus_metrics_enabled {
+        statistics.write().unwrap().authorized_not_found += 1;
+    }
+}
+
+#[inline]
+pub(crate) fn track_unauthorized_thumb(
+    options: &State<'_, Options>,
+    statistics: &State<'_, Arc<RwLock<Statistics>>>,
+) {
+    // only keep track of the accesses if the
+    // prometheus exporting has been
+    // enabled!
+    if options.prometheus_metrics_enabled {
+        statistics.write().unwrap().unauthorized_thumb += 1;
+    }
+}
+
+#[inline]
+pub(crate) fn track_authorized_thumb(
+    options: &State<'_, Options>,
+    statistics: &State<'_, Arc<RwLock<Statistics>>>,
+) {
+    // only keep track of the accesses if the
+    // prometheus exporting has been
+    // enabled!
+    if options.prometheus_metrics_enabled {
+        statistics.write().unwrap().authorized_thumb += 1;
+    }
+}
+
+#[derive(Debug)]
+pub struct Statistics {
+    pub authorized_static: HashMap<String, u64>,
+    pub unathorized_static: HashMap<String, u64>,
+    pub unauthorized_dynamic: u64,
+    pub authorized_dynamic: u64,
+    pub authorized_not_found: u64,
+    pub authorized_thumb: u64,
+    pub unauthorized_thumb: u64,
+    pub picture_thumb_access: u64,
+    pub picture_thumb_generation: u64,
+    pub video_thumb_access: u64,
+    pub video_thumb_generation: u64,
+    pub embedded_preview_access: u64,
+    pub embedded_preview_generation: u64,
+    pub render_access: u64,
+    pub render_generation: u64,
+    pub hls_segment_access: u64,
+    pub hls_segment_generation: u64,
+    pub storyboard_access: u64,
+    pub storyboard_generation: u64,
+    pub clip_access: u64,
+    pub clip_generation: u64,
+    pub cover_art_access: u64,
+    pub cover_art_generation: u64,
+    pub first_page_access: u64,
+    pub first_page_generation: u64,
+    pub text_preview_access: u64,
+    pub download_access: u64,
+    pub authorized_list_v2: u64,
+    pub unauthorized_list_v2: u64,
+    pub authorized_browse: u64,
+    pub unauthorized_browse: u64,
+    pub authorized_breadcrumbs: u64,
+    pub unauthorized_breadcrumbs: u64,
+    pub favorite_change: u64,
+    pub favorite_list: u64,
+    pub album_access: u64,
+    pub album_change: u64,
+    pub annotation_change: u64,
+    pub search: u64,
+    pub comment_access: u64,
+    pub comment_change: u64,
+    pub upload: u64,
+    pub manage: u64,
+    pub duplicate_report: u64,
+    pub map: u64,
+    pub authorized_list_files: HashMap<FileType, u64>,
+    pub unauthorized_list_files: HashMap<FileType, u64>,
+    pub authorized_first_level_folders: u64,
+    pub unauthorized_first_level_folders: u64,
+}
+
+impl Default for Statistics {
+    fn default() -> Self {
+        let mut authorized_list_files = HashMap::new();
+        authorized_list_files.insert(FileType::Preview, 0);
+        authorized_list_files.insert(FileType::Extra, 0);
+        authorized_list_files.insert(FileType::Folder, 0);
+
+        let mut unauthorized_list_files = HashMap::new();
+        unauthorized_list_files.insert(FileType::Preview, 0);
+        unauthorized_list_files.insert(FileType::Extra, 0);
+        unauthorized_list_files.insert(FileType::Folder, 0);
+
+        Self {
+            authorized_static: HashMap::new(),
+            unathorized_static: HashMap::new(),
+            authorized_dynamic: 0,
+            unauthorized_dynamic: 0,
+            authorized_not_found: 0,
+            authorized_thumb: 0,
+            unauthorized_thumb: 0,
+            picture_thumb_access: 0,
+            picture_thumb_generation: 0,
+            video_thumb_access: 0,
+            video_thumb_generation: 0,
+            embedded_preview_access: 0,
+            embedded_preview_generation: 0,
+            render_access: 0,
+            render_generation: 0,
+            hls_segment_access: 0,
+            hls_segment_generation: 0,
+            storyboard_access: 0,
+            storyboard_generation: 0,
+            clip_access: 0,
+            clip_generation: 0,
+            cover_art_access: 0,
+            cover_art_generation: 0,
+            first_page_access: 0,
+            first_page_generation: 0,
+            text_preview_access: 0,
+            download_access: 0,
+            authorized_list_v2: 0,
+            unauthorized_list_v2: 0,
+            authorized_browse: 0,
+            unauthorized_browse: 0,
+            authorized_breadcrumbs: 0,
+            unauthorized_breadcrumbs: 0,
+            favorite_change: 0,
+            favorite_list: 0,
+            album_access: 0,
+            album_change: 0,
+            annotation_change: 0,
+            search: 0,
+            comment_access: 0,
+            comment_change: 0,
+            upload: 0,
+            manage: 0,
+            duplicate_report: 0,
+            map: 0,
+            authorized_list_files,
+            unauthorized_list_files,
+            authorized_first_level_folders: 0,
+            unauthorized_first_level_folders: 0,
+        }
+    }
+}
+
+impl Statistics {
+    pub(crate) fn inc_authorized_list_files(&mut self, file_type: FileType) {
+        if let Some(original_value) = self.authorized_list_files.get_mut(&file_type) {
+            *original_value += 1;
+        } else {
+            self.authorized_list_files.insert(file_type, 1);
+        }
+    }
+
+    pub(crate) fn inc_unauthorized_list_files(&mut self, file_type: FileType) {
+        if let Some(original_value) = self.unauthorized_list_files.get_mut(&file_type) {
+            *original_value += 1;
+        } else {
+            self.unauthorized_list_files.insert(file_type, 1);
+        }
+    }
+
+    pub(crate) fn inc_authorized_static(&mut self, page: &str) {
+        if let Some(original_value) = self.authorized_static.get_mut(page) {
+            *original_value += 1;
+        } else {
+            self.authorized_static.insert(page.to_owned(), 1);
+        }
+    }
+
+    pub(crate) fn inc_unathorized_static(&mut self, page: &str) {
+        if let Some(original_value) = self.unathorized_static.get_mut(page) {
+            *original_value += 1;
+        } else {
+            self.unathorized_static.insert(page.to_owned(), 1);
+        }
+    }
+
+    pub(crate) fn render_to_prometheus(&self) -> String {
+        let mut s = String::new();
+
+        let mut pc = PrometheusMetric::build()
+            .with_name("nas_gallery_authorized_access_to_static_content")
+            .with_metric_type(MetricType::Counter)
+            .with_help("Authorized access to static content")
+            .build();
+
+        self.authorized_static.iter().for_each(|(key, val)| {
+            pc.render_and_append_instance(
+                &PrometheusInstance::new()
+                    .with_label("path", key.as_ref())
+                    .with_value(*val),
+            );
+        });
+        s.push_str(&pc.render());
+
+        let mut pc = PrometheusMetric::build()
+            .with_name("nas_gallery_unauthorized_access_to_static_content")
+            .with_metric_type(MetricType::Counter)
+            .with_help("Unauthorized access to static content")
+            .build();
+
+        self.unathorized_static.iter().for_each(|(key, val)| {
+            pc.render_and_append_instance(
+                &PrometheusInstance::new()
+                    .with_label("path", key.as_ref())
+                    .with_value(*val),
+            );
+        });
+        s.push_str(&pc.render());
+
+        s.push_str(
+            &PrometheusMetric::build()
+                .with_name("nas_gallery_authorized_access_to_dynamic_content")
+                .with_metric_type(MetricType::Counter)
+                .with_help("Authorized access to dynamic content")
+                .build()
+                .render_and_append_instance(
+                    &PrometheusInstance::new().with_value(self.authorized_dynamic),
+                )
+                .render(),
+        );
+
+        s.push_str(
+            &PrometheusMetric::build()
+                .with_name("nas_gallery_authorized_not_found")
+                .with_metric_type(MetricType::Counter)
+                .with_help("Authorized access to not found content")
+                .build()
+                .render_and_append_instance(
+                    &PrometheusInstance::new().with_value(self.authorized_not_found),
+                )
+                .render(),
+        );
+
+        s.push_str(
+            &PrometheusMetric::build()
+                .with_name("nas_gallery_authorized_thumb")
+                .with_metric_type(MetricType::Counter)
+                .with_help("Authorized access to thumbnail")
+                .build()
+                .render_and_append_instance(
+                    &PrometheusInstance::new().with_value(self.authorized_thumb),
+                )
+                .render(),
+        );
+
+        s.push_str(
+            &PrometheusMetric::build()
+                .with_name("nas_gallery_unauthorized_thumb")
+                .with_metric_type(MetricType::Counter)
+                .with_help("Authorized unaccess to thumbnail")
+                .build()
+                .render_and_append_instance(
+                    &PrometheusInstance::new().with_value(self.unauthorized_thumb),
+                )
+                .render(),
+        );
+
+        s.push_str(
+            &PrometheusMetric::build()
+                .with_name("nas_gallery_picture_thumb_access")
+                .with_metric_type(MetricType::Counter)
+                .with_help("Authorized unaccess picute thumb")
+                .build()
+                .render_and_append_instance(
+                    &PrometheusInstance::new().with_value(self.picture_thumb_access),
+                )
+                .render(),
+        );
+
+        s.push_str(
+            &PrometheusMetric::build()
+                .with_name("nas_gallery_picture_thumb_generation")
+                .with_metric_type(MetricType::Counter)
+                .with_help("Picture thumb generation (cache miss)")
+                .build()
+                .render_and_append_instance(
+                    &PrometheusInstance::new().with_value(self.picture_thumb_generation),
+                )
+                .render(),
+        );
+
+        s.push_str(
+            &PrometheusMetric::build()
+                .with_name("nas_gallery_video_thumb_access")
+                .with_metric_type(MetricType::Counter)
+                .with_help("Authorized unaccess picute thumb")
+                .build()
+                .render_and_append_instance(
+                    &PrometheusInstance::new().with_value(self.video_thumb_access),
+                )
+                .render(),
+        );
+
+        s.push_str(
+            &PrometheusMetric::build()
+                .with_name("nas_gallery_video_thumb_generation")
+                .with_metric_type(MetricType::Counter)
+                .with_help("Video thumb generation (cache miss)")
+                .build()
+                .render_and_append_instance(
+                    &PrometheusInstance::new().with_value(self.video_thumb_generation),
+                )
+                .render(),
+        );
+
+        s.push_str(
+            &PrometheusMetric::build()
+                .with_name("nas_gallery_embedded_preview_access")
+                .with_metric_type(MetricType::Counter)
+                .with_help("Embedded preview access")
+                .build()
+                .render_and_append_instance(
+                    &PrometheusInstance::new().with_value(self.embedded_preview_access),
+                )
+                .render(),
+        );
+
+        s.push_str(
+            &PrometheusMetric::build()
+                .with_name("nas_gallery_embedded_preview_generation")
+                .with_metric_type(MetricType::Counter)
+                .with_help("Embedded preview extraction (cache miss)")
+                .build()
+                .render_and_append_instance(
+                    &PrometheusInstance::new().with_value(self.embedded_preview_generation),
+                )
+                .render(),
+        );
+
+        s.push_str(
+            &PrometheusMetric::build()
+                .with_name("nas_gallery_render_access")
+                .with_metric_type(MetricType::Counter)
+                .with_help("Screen size render access")
+                .build()
+                .render_and_append_instance(
+                    &PrometheusInstance::new().with_value(self.render_access),
+                )
+                .render(),
+        );
+
+        s.push_str(
+            &PrometheusMetric::build()
+                .with_name("nas_gallery_render_generation")
+                .with_metric_type(MetricType::Counter)
+                .with_help("Screen size render generation (cache miss)")
+                .build()
+                .render_and_append_instance(
+                    &PrometheusInstance::new().with_value(self.render_generation),
+                )
+                .render(),
+        );
+
+        s.push_str(
+            &PrometheusMetric::build()
+                .with_name("nas_gallery_hls_segment_access")
+                .with_metric_type(MetricType::Counter)
+                .with_help("HLS segment access")
+                .build()
+                .render_and_append_instance(
+                    &PrometheusInstance::new().with_value(self.hls_segment_access),
+                )
+                .render(),
+        );
+
+        s.push_str(
+            &PrometheusMetric::build()
+                .with_name("nas_gallery_hls_segment_generation")
+                .with_metric_type(MetricType::Counter)
+                .with_help("HLS segment generation (cache miss)")
+                .build()
+                .render_and_append_instance(
+                    &PrometheusInstance::new().with_value(self.hls_segment_generation),
+                )
+                .render(),
+        );
+
+        s.push_str(
+            &PrometheusMetric::build()
+                .with_name("nas_gallery_storyboard_access")
+                .with_metric_type(MetricType::Counter)
+                .with_help("Storyboard access")
+                .build()
+                .render_and_append_instance(
+                    &PrometheusInstance::new().with_value(self.storyboard_access),
+                )
+                .render(),
+        );
+
+        s.push_str(
+            &PrometheusMetric::build()
+                .with_name("nas_gallery_storyboard_generation")
+                .with_metric_type(MetricType::Counter)
+                .with_help("Storyboard generation (cache miss)")
+                .build()
+                .render_and_append_instance(
+                    &PrometheusInstance::new().with_value(self.storyboard_generation),
+                )
+                .render(),
+        );
+
+        s.push_str(
+            &PrometheusMetric::build()
+                .with_name("nas_gallery_clip_access")
+                .with_metric_type(MetricType::Counter)
+                .with_help("Number of preview clip requests")
+                .build()
+                .render_and_append_instance(&PrometheusInstance::new().with_value(self.clip_access))
+                .render(),
+        );
 
-        let mut unauthorized_list_files = HashMap::new();
-        unauthorized_list_files.insert(FileType::Preview, 0);
-        unauthorized_list_files.insert(FileType::Extra, 0);
-        unauthorized_list_files.insert(FileType::Folder, 0);
+        s.push_str(
+            &PrometheusMetric::build()
+                .with_name("nas_gallery_clip_generation")
+                .with_metric_type(MetricType::Counter)
+                .with_help("Number of preview clips generated")
+                .build()
+                .render_and_append_instance(
+                    &PrometheusInstance::new().with_value(self.clip_generation),
+                )
+                .render(),
+        );
 
-        Self {
-            authorized_static: HashMap::new(),
-            unathorized_static: HashMap::new(),
-            authorized_dynamic: 0,
-            unauthorized_dynamic: 0,
-            authorized_not_found: 0,
-            authorized_thumb: 0,
-            unauthorized_thumb: 0,
-            picture_thumb_access: 0,
-            picture_thumb_generation: 0,
-            video_thumb_access: 0,
-            video_thumb_generation: 0,
-            authorized_list_files,
-            unauthorized_list_files,
-            authorized_first_level_folders: 0,
-            unauthorized_first_level_folders: 0,
-        }
-    }
-}
+        s.push_str(
+            &PrometheusMetric::build()
+                .with_name("nas_gallery_cover_art_access")
+                .with_metric_type(MetricType::Counter)
+                .with_help("Number of audio cover art requests")
+                .build()
+                .render_and_append_instance(
+                    &PrometheusInstance::new().with_value(self.cover_art_access),
+                )
+                .render(),
+        );
 
-impl Statistics {
-    pub(crate) fn inc_authorized_list_files(&mut self, file_type: FileType) {
-        if let Some(original_value) = self.authorized_list_files.get_mut(&file_type) {
-            *original_value += 1;
-        } else {
-            self.authorized_list_files.insert(file_type, 1);
-        }
-    }
+        s.push_str(
+            &PrometheusMetric::build()
+                .with_name("nas_gallery_cover_art_generation")
+                .with_metric_type(MetricType::Counter)
+                .with_help("Number of audio cover art extractions")
+                .build()
+                .render_and_append_instance(
+                    &PrometheusInstance::new().with_value(self.cover_art_generation),
+                )
+                .render(),
+        );
 
-    pub(crate) fn inc_unauthorized_list_files(&mut self, file_type: FileType) {
-        if let Some(original_value) = self.unauthorized_list_files.get_mut(&file_type) {
-            *original_value += 1;
-        } else {
-            self.unauthorized_list_files.insert(file_type, 1);
-        }
-    }
+        s.push_str(
+            &PrometheusMetric::build()
+                .with_name("nas_gallery_first_page_access")
+                .with_metric_type(MetricType::Counter)
+                .with_help("Number of document first page requests")
+                .build()
+                .render_and_append_instance(
+                    &PrometheusInstance::new().with_value(self.first_page_access),
+                )
+                .render(),
+        );
 
-    pub(crate) fn inc_authorized_static(&mut self, page: &str) {
-        if let Some(original_value) = self.authorized_static.get_mut(page) {
-            *original_value += 1;
-        } else {
-            self.authorized_static.insert(page.to_owned(), 1);
-        }
-    }
+        s.push_str(
+            &PrometheusMetric::build()
+                .with_name("nas_gallery_first_page_generation")
+                .with_metric_type(MetricType::Counter)
+                .with_help("Number of document first pages rasterized")
+                .build()
+                .render_and_append_instance(
+                    &PrometheusInstance::new().with_value(self.first_page_generation),
+                )
+                .render(),
+        );
 
-    pub(crate) fn inc_unathorized_static(&mut self, page: &str) {
-        if let Some(original_value) = self.unathorized_static.get_mut(page) {
-            *original_value += 1;
-        } else {
-            self.unathorized_static.insert(page.to_owned(), 1);
-        }
-    }
+        s.push_str(
+            &PrometheusMetric::build()
+                .with_name("nas_gallery_text_preview_access")
+                .with_metric_type(MetricType::Counter)
+                .with_help("Number of text previews served")
+                .build()
+                .render_and_append_instance(
+                    &PrometheusInstance::new().with_value(self.text_preview_access),
+                )
+                .render(),
+        );
 
-    pub(crate) fn render_to_prometheus(&self) -> String {
-        let mut s = String::new();
+        s.push_str(
+            &PrometheusMetric::build()
+                .with_name("nas_gallery_download_access")
+                .with_metric_type(MetricType::Counter)
+                .with_help("Number of files downloaded")
+                .build()
+                .render_and_append_instance(
+                    &PrometheusInstance::new().with_value(self.download_access),
+                )
+                .render(),
+        );
 
-        let mut pc = PrometheusMetric::build()
-            .with_name("nas_gallery_authorized_access_to_static_content")
-            .with_metric_type(MetricType::Counter)
-            .with_help("Authorized access to static content")
-            .build();
+        s.push_str(
+            &PrometheusMetric::build()
+                .with_name("nas_gallery_authorized_list_v2")
+                .with_metric_type(MetricType::Counter)
+                .with_help("Number of authorized v2 listings")
+                .build()
+                .render_and_append_instance(
+                    &PrometheusInstance::new().with_value(self.authorized_list_v2),
+                )
+                .render(),
+        );
 
-        self.authorized_static.iter().for_each(|(key, val)| {
-            pc.render_and_append_instance(
-                &PrometheusInstance::new()
-                    .with_label("path", key.as_ref())
-                    .with_value(*val),
-            );
-        });
-        s.push_str(&pc.render());
+        s.push_str(
+            &PrometheusMetric::build()
+                .with_name("nas_gallery_unauthorized_list_v2")
+                .with_metric_type(MetricType::Counter)
+                .with_help("Number of unauthorized v2 listings")
+                .build()
+                .render_and_append_instance(
+                    &PrometheusInstance::new().with_value(self.unauthorized_list_v2),
+                )
+                .render(),
+        );
 
-        let mut pc = PrometheusMetric::build()
-            .with_name("nas_gallery_unauthorized_access_to_static_content")
-            .with_metric_type(MetricType::Counter)
-            .with_help("Unauthorized access to static content")
-            .build();
+        s.push_str(
+            &PrometheusMetric::build()
+                .with_name("nas_gallery_authorized_browse")
+                .with_metric_type(MetricType::Counter)
+                .with_help("Number of authorized folder browses")
+                .build()
+                .render_and_append_instance(
+                    &PrometheusInstance::new().with_value(self.authorized_browse),
+                )
+                .render(),
+        );
 
-        self.unathorized_static.iter().for_each(|(key, val)| {
-            pc.render_and_append_instance(
-                &PrometheusInstance::new()
-                    .with_label("path", key.as_ref())
-                    .with_value(*val),
-            );
-        });
-        s.push_str(&pc.render());
+        s.push_str(
+            &PrometheusMetric::build()
+                .with_name("nas_gallery_unauthorized_browse")
+                .with_metric_type(MetricType::Counter)
+                .with_help("Number of unauthorized folder browses")
+                .build()
+                .render_and_append_instance(
+                    &PrometheusInstance::new().with_value(self.unauthorized_browse),
+                )
+                .render(),
+        );
 
         s.push_str(
             &PrometheusMetric::build()
-                .with_name("nas_gallery_authorized_access_to_dynamic_content")
+                .with_name("nas_gallery_authorized_breadcrumbs")
                 .with_metric_type(MetricType::Counter)
-                .with_help("Authorized access to dynamic content")
+                .with_help("Number of authorized breadcrumb requests")
                 .build()
                 .render_and_append_instance(
-                    &PrometheusInstance::new().with_value(self.authorized_dynamic),
+                    &PrometheusInstance::new().with_value(self.authorized_breadcrumbs),
                 )
                 .render(),
         );
 
         s.push_str(
             &PrometheusMetric::build()
-                .with_name("nas_gallery_authorized_not_found")
+                .with_name("nas_gallery_unauthorized_breadcrumbs")
                 .with_metric_type(MetricType::Counter)
-                .with_help("Authorized access to not found content")
+                .with_help("Number of unauthorized breadcrumb requests")
                 .build()
                 .render_and_append_instance(
-                    &PrometheusInstance::new().with_value(self.authorized_not_found),
+                    &PrometheusInstance::new().with_value(self.unauthorized_breadcrumbs),
                 )
                 .render(),
         );
 
         s.push_str(
             &PrometheusMetric::build()
-                .with_name("nas_gallery_authorized_thumb")
+                .with_name("nas_gallery_favorite_change")
                 .with_metric_type(MetricType::Counter)
-                .with_help("Authorized access to thumbnail")
+                .with_help("Number of favorites added or removed")
                 .build()
                 .render_and_append_instance(
-                    &PrometheusInstance::new().with_value(self.authorized_thumb),
+                    &PrometheusInstance::new().with_value(self.favorite_change),
                 )
                 .render(),
         );
 
         s.push_str(
             &PrometheusMetric::build()
-                .with_name("nas_gallery_unauthorized_thumb")
+                .with_name("nas_gallery_favorite_list")
                 .with_metric_type(MetricType::Counter)
-                .with_help("Authorized unaccess to thumbnail")
+                .with_help("Number of favorite listings")
                 .build()
                 .render_and_append_instance(
-                    &PrometheusInstance::new().with_value(self.unauthorized_thumb),
+                    &PrometheusInstance::new().with_value(self.favorite_list),
                 )
                 .render(),
         );
 
         s.push_str(
             &PrometheusMetric::build()
-                .with_name("nas_gallery_picture_thumb_access")
+                .with_name("nas_gallery_album_access")
                 .with_metric_type(MetricType::Counter)
-                .with_help("Authorized unaccess picute thumb")
+                .with_help("Number of album listings and views")
                 .build()
                 .render_and_append_instance(
-                    &PrometheusInstance::new().with_value(self.picture_thumb_access),
+                    &PrometheusInstance::new().with_value(self.album_access),
                 )
                 .render(),
         );
 
         s.push_str(
             &PrometheusMetric::build()
-                .with_name("nas_gallery_picture_thumb_generation")
+                .with_name("nas_gallery_album_change")
                 .with_metric_type(MetricType::Counter)
-                .with_help("Picture thumb generation (cache miss)")
+                .with_help("Number of albums created, updated or deleted")
                 .build()
                 .render_and_append_instance(
-                    &PrometheusInstance::new().with_value(self.picture_thumb_generation),
+                    &PrometheusInstance::new().with_value(self.album_change),
                 )
                 .render(),
         );
 
         s.push_str(
             &PrometheusMetric::build()
-                .with_name("nas_gallery_video_thumb_access")
+                .with_name("nas_gallery_annotation_change")
                 .with_metric_type(MetricType::Counter)
-                .with_help("Authorized unaccess picute thumb")
+                .with_help("Number of tag and caption edits")
                 .build()
                 .render_and_append_instance(
-                    &PrometheusInstance::new().with_value(self.video_thumb_access),
+                    &PrometheusInstance::new().with_value(self.annotation_change),
                 )
                 .render(),
         );
 
         s.push_str(
             &PrometheusMetric::build()
-                .with_name("nas_gallery_video_thumb_generation")
+                .with_name("nas_gallery_search")
                 .with_metric_type(MetricType::Counter)
-                .with_help("Video thumb generation (cache miss)")
+                .with_help("Number of searches")
+                .build()
+                .render_and_append_instance(&PrometheusInstance::new().with_value(self.search))
+                .render(),
+        );
+
+        s.push_str(
+            &PrometheusMetric::build()
+                .with_name("nas_gallery_comment_access")
+                .with_metric_type(MetricType::Counter)
+                .with_help("Number of comment thread reads")
                 .build()
                 .render_and_append_instance(
-                    &PrometheusInstance::new().with_value(self.video_thumb_generation),
+                    &PrometheusInstance::new().with_value(self.comment_access),
+                )
+                .render(),
+        );
+
+        s.push_str(
+            &PrometheusMetric::build()
+                .with_name("nas_gallery_comment_change")
+                .with_metric_type(MetricType::Counter)
+                .with_help("Number of comments added or deleted")
+                .build()
+                .render_and_append_instance(
+                    &PrometheusInstance::new().with_value(self.comment_change),
+                )
+                .render(),
+        );
+
+        s.push_str(
+            &PrometheusMetric::build()
+                .with_name("nas_gallery_upload")
+                .with_metric_type(MetricType::Counter)
+                .with_help("Number of uploads started")
+                .build()
+                .render_and_append_instance(&PrometheusInstance::new().with_value(self.upload))
+                .render(),
+        );
+
+        s.push_str(
+            &PrometheusMetric::build()
+                .with_name("nas_gallery_manage")
+                .with_metric_type(MetricType::Counter)
+                .with_help("Number of rename, move, delete and trash operations")
+                .build()
+                .render_and_append_instance(&PrometheusInstance::new().with_value(self.manage))
+                .render(),
+        );
+
+        s.push_str(
+            &PrometheusMetric::build()
+                .with_name("nas_gallery_duplicate_report")
+                .with_metric_type(MetricType::Counter)
+                .with_help("Number of duplicate reports requested")
+                .build()
+                .render_and_append_instance(
+                    &PrometheusInstance::new().with_value(self.duplicate_report),
                 )
                 .render(),
         );
 
+        s.push_str(
+            &PrometheusMetric::build()
+                .with_name("nas_gallery_map")
+                .with_metric_type(MetricType::Counter)
+                .with_help("Number of map requests")
+                .build()
+                .render_and_append_instance(&PrometheusInstance::new().with_value(self.map))
+                .render(),
+        );
+
         let mut pc = PrometheusMetric::build()
             .with_name("nas_gallery_authorized_list_files")
             .with_metric_type(MetricType::Counter)
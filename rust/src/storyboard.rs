@@ -0,0 +1,259 @@
+use crate::api::{authorize_video, status_response};
+use crate::forwarded_identity::ForwardedIdentity;
+use crate::json_store::unique_id;
+use crate::options::Options;
+use crate::permission::Permission;
+use crate::statistics::*;
+use crate::video_probe::VideoInfo;
+use crate::{
+    add_access_control_allow_origin_if_needed, encode_path, failure_marker,
+    generate_cache_folder_path, has_failed,
+};
+use rocket::http::{ContentType, Status};
+use rocket::{Response, State};
+use std::io::Cursor;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::{Arc, RwLock};
+
+/// The layout of the sprite sheet of a video: a grid of
+/// tiles taken at regular intervals
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Layout {
+    interval: f64,
+    tiles: u64,
+    columns: u64,
+    rows: u64,
+    tile_width: u64,
+    tile_height: u64,
+}
+
+impl Layout {
+    fn new(options: &Options, info: &VideoInfo) -> Self {
+        // one tile per second at most, and never more
+        // than storyboard_max_tiles tiles
+        let interval = (info.duration / options.storyboard_max_tiles as f64).max(1.0);
+        let tiles = ((info.duration / interval).ceil() as u64).max(1);
+        let columns = options.storyboard_columns.min(tiles);
+        let rows = (tiles + columns - 1) / columns;
+        let tile_width = options.storyboard_tile_width;
+        // ffmpeg wants even sizes
+        let tile_height = ((tile_width * info.height / info.width.max(1)) / 2 * 2).max(2);
+
+        Self {
+            interval,
+            tiles,
+            columns,
+            rows,
+            tile_width,
+            tile_height,
+        }
+    }
+}
+
+fn vtt_timestamp(seconds: f64) -> String {
+    let millis = (seconds * 1000.0).round() as u64;
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        millis / 3_600_000,
+        (millis / 60_000) % 60,
+        (millis / 1000) % 60,
+        millis % 1000
+    )
+}
+
+/// None if ffmpeg cannot make the sheet, it is not
+/// attempted again until the video is replaced
+fn generate_storyboard_image(
+    options: &State<'_, Options>,
+    statistics: &State<'_, Arc<RwLock<Statistics>>>,
+    original_path: &PathBuf,
+    layout: &Layout,
+) -> Option<PathBuf> {
+    // a change of the storyboard options gives another sheet
+    let file_name = original_path.file_name().unwrap().to_str().unwrap();
+    let output_file_name =
+        generate_cache_folder_path(options, "storyboard", original_path).join(format!(
+            "{}.{}-{}x{}-{}x{}.jpg",
+            file_name,
+            layout.tiles,
+            layout.columns,
+            layout.rows,
+            layout.tile_width,
+            layout.tile_height
+        ));
+    trace!("output_file_name == {:#?}", output_file_name);
+    track_storyboard_access(options, statistics);
+
+    // if we already have a sprite sheet, do not regenerate it
+    if output_file_name.exists() {
+        return Some(output_file_name);
+    }
+    if has_failed(&output_file_name, original_path) {
+        return None;
+    }
+    track_storyboard_generation(options, statistics);
+
+    // written aside then renamed, the concurrent
+    // requests never see a half written sheet
+    let partial_file_name =
+        output_file_name.with_file_name(format!("{}.{}.partial.jpg", file_name, unique_id()));
+    let mut cmd = Command::new("ffmpeg");
+    let cmd = cmd.args(&[
+        "-i",
+        original_path.to_str().unwrap(),
+        "-vf",
+        &format!(
+            "fps=1/{:.3},scale={}:{},tile={}x{}",
+            layout.interval, layout.tile_width, layout.tile_height, layout.columns, layout.rows
+        ),
+        "-frames:v",
+        "1",
+        "-q:v",
+        "5",
+        "-y",
+        partial_file_name.to_str().unwrap(),
+    ]);
+    trace!("about to send == {:#?}", cmd);
+    let output = cmd.output();
+    trace!("{:?}", output);
+
+    let generated = match output {
+        Ok(output) if output.status.success() => {
+            std::fs::rename(&partial_file_name, &output_file_name).map_err(|err| err.to_string())
+        }
+        Ok(output) => Err(String::from_utf8_lossy(&output.stderr).into_owned()),
+        Err(err) => Err(err.to_string()),
+    };
+    match generated {
+        Ok(_) => {
+            let _ = std::fs::remove_file(failure_marker(&output_file_name));
+            Some(output_file_name)
+        }
+        Err(err) => {
+            error!(
+                "ffmpeg failed to generate the storyboard of {:?}: {}",
+                original_path, err
+            );
+            let _ = std::fs::remove_file(&partial_file_name);
+            if let Err(err) = std::fs::write(failure_marker(&output_file_name), b"") {
+                warn!(
+                    "cannot record the failure of {:?}: {}",
+                    output_file_name, err
+                );
+            }
+            None
+        }
+    }
+}
+
+/// the WebVTT is not cached: it is cheap to build
+/// and it contains the public path of the video
+fn generate_storyboard_vtt(
+    options: &State<'_, Options>,
+    original_path: &PathBuf,
+    info: &VideoInfo,
+    layout: &Layout,
+) -> Option<String> {
+    let image_url = format!("/storyboard_image/{}", encode_path(options, original_path)?);
+
+    let mut vtt = String::from("WEBVTT\n");
+    (0..layout.tiles).for_each(|tile| {
+        let start = tile as f64 * layout.interval;
+        let end = (start + layout.interval).min(info.duration);
+        vtt.push_str(&format!(
+            "\n{} --> {}\n{}#xywh={},{},{},{}\n",
+            vtt_timestamp(start),
+            vtt_timestamp(end),
+            image_url,
+            (tile % layout.columns) * layout.tile_width,
+            (tile / layout.columns) * layout.tile_height,
+            layout.tile_width,
+            layout.tile_height
+        ));
+    });
+
+    Some(vtt)
+}
+
+#[get("/storyboard/<path..>")]
+pub(crate) fn storyboard<'r>(
+    options: State<'_, Options>,
+    statistics: State<'_, Arc<RwLock<Statistics>>>,
+    forwarded_identity: ForwardedIdentity,
+    path: PathBuf,
+) -> Response<'r> {
+    let (path, info) = match authorize_video(
+        &options,
+        &statistics,
+        &forwarded_identity,
+        path,
+        Permission::Preview,
+    ) {
+        Ok(authorized) => authorized,
+        Err(status) => return status_response(status),
+    };
+
+    options.audit(
+        &forwarded_identity.email,
+        "video",
+        path.to_str().unwrap(),
+        "storyboard",
+        true,
+    );
+
+    let layout = Layout::new(&options, &info);
+    trace!("layout == {:?}", layout);
+    let vtt = match generate_storyboard_vtt(&options, &path, &info, &layout) {
+        Some(vtt) => vtt,
+        None => return status_response(Status::NotFound),
+    };
+
+    let mut response = Response::new();
+    response.set_status(Status::Ok);
+    response.set_header(ContentType::new("text", "vtt"));
+    add_access_control_allow_origin_if_needed(&mut response, &options);
+    response.set_sized_body(Cursor::new(vtt));
+    response
+}
+
+#[get("/storyboard_image/<path..>")]
+pub(crate) fn storyboard_image<'r>(
+    options: State<'_, Options>,
+    statistics: State<'_, Arc<RwLock<Statistics>>>,
+    forwarded_identity: ForwardedIdentity,
+    path: PathBuf,
+) -> Response<'r> {
+    let (path, info) = match authorize_video(
+        &options,
+        &statistics,
+        &forwarded_identity,
+        path,
+        Permission::Preview,
+    ) {
+        Ok(authorized) => authorized,
+        Err(status) => return status_response(status),
+    };
+
+    let layout = Layout::new(&options, &info);
+    trace!("layout == {:?}", layout);
+    let image_file_name = match generate_storyboard_image(&options, &statistics, &path, &layout) {
+        Some(image_file_name) => image_file_name,
+        None => return status_response(Status::NotFound),
+    };
+
+    match std::fs::File::open(&image_file_name) {
+        Ok(file) => {
+            let mut response = Response::new();
+            response.set_status(Status::Ok);
+            response.set_header(ContentType::JPEG);
+            add_access_control_allow_origin_if_needed(&mut response, &options);
+            response.set_sized_body(file);
+            response
+        }
+        Err(err) => {
+            error!("cannot open storyboard {:?}: {}", image_file_name, err);
+            status_response(Status::InternalServerError)
+        }
+    }
+}
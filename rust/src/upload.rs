@@ -0,0 +1,567 @@
+use crate::api::{json_response, status_response};
+use crate::forwarded_identity::ForwardedIdentity;
+use crate::image_format::AcceptedImageFormats;
+use crate::json_store::{unique_id, JsonStore};
+use crate::media_type::MediaKind;
+use crate::options::Options;
+use crate::permission::Capability;
+use crate::preview_clip::PreviewClips;
+use crate::statistics::*;
+use crate::{add_access_control_allow_origin_if_needed, send_thumb};
+use chrono::{DateTime, Duration, Utc};
+use rocket::http::Status;
+use rocket::{Data, Response, State};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fs::OpenOptions;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+
+/// an upload without any chunk for this long is discarded
+const UPLOAD_SESSION_HOURS: i64 = 24;
+
+/// What happens when the uploaded file name already
+/// exists in the destination folder
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictPolicy {
+    /// IMG_0042.jpg becomes IMG_0042 (1).jpg
+    #[default]
+    Rename,
+    /// the upload is refused
+    Reject,
+}
+
+/// An upload in progress, the bytes received so
+/// far are in the partial file
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct UploadSession {
+    pub owner: String,
+    /// the host path of the destination folder
+    pub folder: String,
+    pub name: String,
+    pub size: u64,
+    pub created: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+struct UploadStatus {
+    id: String,
+    offset: u64,
+    size: u64,
+    /// the public path, once completed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    path: Option<String>,
+}
+
+/// The resumable uploads: the client declares the file, then
+/// sends it in chunks that can be retried from the last offset
+/// the server acknowledged. The sessions survive a restart.
+#[derive(Debug)]
+pub struct Uploads {
+    sessions: JsonStore<BTreeMap<String, UploadSession>>,
+    partial_folder: PathBuf,
+    /// the sessions receiving a chunk right now
+    busy: Mutex<HashSet<String>>,
+}
+
+impl Uploads {
+    pub fn new(data_folder_path: &str) -> Self {
+        Self {
+            sessions: JsonStore::open(data_folder_path, "uploads"),
+            partial_folder: Path::new(data_folder_path).join("uploads"),
+            busy: Mutex::new(HashSet::new()),
+        }
+    }
+
+    fn partial_path(&self, id: &str) -> PathBuf {
+        self.partial_folder.join(format!("{}.partial", id))
+    }
+
+    fn offset(&self, id: &str) -> u64 {
+        self.partial_path(id)
+            .metadata()
+            .map(|metadata| metadata.len())
+            .unwrap_or(0)
+    }
+
+    fn session(&self, id: &str, owner: &str) -> Result<UploadSession, Status> {
+        match self.sessions.read(|sessions| sessions.get(id).cloned()) {
+            Some(session) if session.owner == owner => Ok(session),
+            Some(_) => Err(Status::Unauthorized),
+            None => Err(Status::NotFound),
+        }
+    }
+
+    fn status(&self, id: &str, session: &UploadSession) -> UploadStatus {
+        UploadStatus {
+            id: id.to_owned(),
+            offset: self.offset(id),
+            size: session.size,
+            path: None,
+        }
+    }
+
+    fn start(&self, session: UploadSession) -> std::io::Result<String> {
+        let id = unique_id();
+
+        std::fs::create_dir_all(&self.partial_folder)?;
+        std::fs::File::create(self.partial_path(&id))?;
+        self.sessions.update(|sessions| {
+            sessions.insert(id.clone(), session);
+        })?;
+        Ok(id)
+    }
+
+    fn finish(&self, id: &str) {
+        if let Err(err) = self.sessions.update(|sessions| sessions.remove(id)) {
+            error!("cannot save the upload sessions: {}", err);
+        }
+        let _ = std::fs::remove_file(self.partial_path(id));
+    }
+
+    /// when the client last sent a chunk: the partial
+    /// file is appended to by every chunk
+    fn last_activity(&self, id: &str, session: &UploadSession) -> DateTime<Utc> {
+        self.partial_path(id)
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .map(DateTime::<Utc>::from)
+            .map_or(session.created, |modified| modified.max(session.created))
+    }
+
+    /// forgets the uploads abandoned by the clients, the ones
+    /// receiving a chunk right now are still alive
+    fn remove_expired(&self) {
+        let oldest = Utc::now() - Duration::hours(UPLOAD_SESSION_HOURS);
+        let busy = self.busy.lock().unwrap();
+        let expired = self.sessions.read(|sessions| {
+            sessions
+                .iter()
+                .filter(|(id, session)| {
+                    !busy.contains(*id) && self.last_activity(id, session) < oldest
+                })
+                .map(|(id, _)| id.to_owned())
+                .collect::<Vec<_>>()
+        });
+        expired.iter().for_each(|id| {
+            debug!("removing the expired upload {}", id);
+            self.finish(id);
+        });
+    }
+
+    /// marks the session busy until the guard is dropped, even
+    /// by a panic. None if another request is writing to it
+    fn lock(&self, id: &str) -> Option<BusySession<'_>> {
+        if self.busy.lock().unwrap().insert(id.to_owned()) {
+            Some(BusySession {
+                uploads: self,
+                id: id.to_owned(),
+            })
+        } else {
+            None
+        }
+    }
+}
+
+struct BusySession<'a> {
+    uploads: &'a Uploads,
+    id: String,
+}
+
+impl Drop for BusySession<'_> {
+    fn drop(&mut self) {
+        // a panic elsewhere must not keep the session busy
+        let mut busy = self
+            .uploads
+            .busy
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        busy.remove(&self.id);
+    }
+}
+
+/// refuses the names that would escape the folder or hide the file
+pub(crate) fn is_valid_file_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('.')
+        && !name.contains(['/', '\\'])
+        && !name.chars().any(|c| c.is_control())
+}
+
+/// the names the file can take, following the conflict policy
+fn candidate_paths(
+    folder: &Path,
+    name: &str,
+    policy: ConflictPolicy,
+) -> impl Iterator<Item = PathBuf> {
+    let first = folder.join(name);
+    let name = Path::new(name);
+    let stem = name.file_stem().unwrap().to_str().unwrap().to_owned();
+    let extension = name
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| format!(".{}", extension))
+        .unwrap_or_default();
+    let folder = folder.to_owned();
+    let renamed = (1..).map_while(move |counter| {
+        (policy == ConflictPolicy::Rename)
+            .then(|| folder.join(format!("{} ({}){}", stem, counter, extension)))
+    });
+    std::iter::once(first).chain(renamed)
+}
+
+/// the first free name, following the conflict policy
+fn destination_path(folder: &Path, name: &str, policy: ConflictPolicy) -> Option<PathBuf> {
+    candidate_paths(folder, name, policy).find(|path| !path.exists())
+}
+
+/// creates the destination file, empty, under the first free
+/// name. Unlike checking the name beforehand, two uploads
+/// cannot get the same one. None if the name is taken and
+/// the policy rejects the conflicts
+fn claim_destination(
+    folder: &Path,
+    name: &str,
+    policy: ConflictPolicy,
+) -> std::io::Result<Option<PathBuf>> {
+    for path in candidate_paths(folder, name, policy) {
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(_) => return Ok(Some(path)),
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(None)
+}
+
+/// moves the completed file into the library, the data
+/// folder can be on another file system. The copy is made
+/// under a temporary name and renamed once complete, the
+/// file never appears truncated
+pub(crate) fn move_file(from: &Path, to: &Path) -> std::io::Result<()> {
+    std::fs::rename(from, to).or_else(|_| {
+        let partial_path = to.with_file_name(format!(
+            ".{}.{}.partial",
+            to.file_name().unwrap().to_str().unwrap(),
+            unique_id()
+        ));
+        let copied =
+            std::fs::copy(from, &partial_path).and_then(|_| std::fs::rename(&partial_path, to));
+        if copied.is_err() {
+            let _ = std::fs::remove_file(&partial_path);
+        }
+        copied?;
+        std::fs::remove_file(from)
+    })
+}
+
+/// declares a new upload into the folder. The file name
+/// must have the extension of a known media type
+#[post("/uploads/<path..>?<name>&<size>")]
+pub(crate) fn start_upload<'r>(
+    options: State<'_, Options>,
+    statistics: State<'_, Arc<RwLock<Statistics>>>,
+    uploads: State<'_, Uploads>,
+    forwarded_identity: ForwardedIdentity,
+    path: PathBuf,
+    name: String,
+    size: u64,
+) -> Response<'r> {
+    let folder = match options.libraries.resolve(&path) {
+        Some(folder) => folder,
+        None => return status_response(Status::NotFound),
+    };
+    trace!("requesting: {:?}", &folder);
+    trace!("Authenticated as {}", &forwarded_identity);
+    let email = &forwarded_identity.email;
+    let is_folder_allowed = options.has_capability(&folder, email, Capability::Upload);
+    trace!("is_folder_allowed == {}", is_folder_allowed);
+
+    if !is_folder_allowed {
+        track_unauthorized_dynamic(&options, &statistics);
+        return status_response(Status::Unauthorized);
+    }
+
+    if !folder.is_dir() || options.hidden_files.is_hidden(&folder) {
+        track_authorized_not_found(&options, &statistics);
+        return status_response(Status::NotFound);
+    }
+
+    let path = folder.join(&name);
+    if !is_valid_file_name(&name) || options.hidden_files.is_hidden(&path) {
+        return status_response(Status::BadRequest);
+    }
+    if options.media_types.lookup(&path).is_none() {
+        return status_response(Status::UnsupportedMediaType);
+    }
+    if size > options.upload_max_bytes {
+        return status_response(Status::PayloadTooLarge);
+    }
+    // checked again at the end, the file might appear meanwhile
+    if destination_path(&folder, &name, options.upload_conflict_policy).is_none() {
+        return status_response(Status::Conflict);
+    }
+
+    uploads.remove_expired();
+    track_upload(&options, &statistics);
+    let session = UploadSession {
+        owner: email.to_owned(),
+        folder: folder.to_str().unwrap().to_owned(),
+        name,
+        size,
+        created: Utc::now(),
+    };
+    let id = match uploads.start(session.clone()) {
+        Ok(id) => id,
+        Err(err) => {
+            error!("cannot start the upload: {}", err);
+            return status_response(Status::InternalServerError);
+        }
+    };
+
+    options.audit(email, "upload", path.to_str().unwrap(), "start", true);
+    json_response(&options, Status::Created, &uploads.status(&id, &session))
+}
+
+/// tells where to resume an interrupted upload
+#[get("/uploads/<id>")]
+pub(crate) fn upload_status<'r>(
+    options: State<'_, Options>,
+    uploads: State<'_, Uploads>,
+    forwarded_identity: ForwardedIdentity,
+    id: String,
+) -> Response<'r> {
+    match uploads.session(&id, &forwarded_identity.email) {
+        Ok(session) => json_response(&options, Status::Ok, &uploads.status(&id, &session)),
+        Err(status) => status_response(status),
+    }
+}
+
+/// appends a chunk, offset must be the number of bytes already
+/// received. The last chunk moves the file into its folder
+#[put("/uploads/<id>/<offset>", data = "<data>")]
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn upload_chunk<'r>(
+    options: State<'_, Options>,
+    statistics: State<'_, Arc<RwLock<Statistics>>>,
+    uploads: State<'_, Uploads>,
+    preview_clips: State<'_, PreviewClips>,
+    forwarded_identity: ForwardedIdentity,
+    id: String,
+    offset: u64,
+    data: Data,
+) -> Response<'r> {
+    let session = match uploads.session(&id, &forwarded_identity.email) {
+        Ok(session) => session,
+        Err(status) => return status_response(status),
+    };
+
+    let _busy = match uploads.lock(&id) {
+        Some(busy) => busy,
+        None => return status_response(Status::Conflict),
+    };
+    receive_chunk(
+        &options,
+        &statistics,
+        &uploads,
+        &preview_clips,
+        &forwarded_identity,
+        &id,
+        &session,
+        offset,
+        data,
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+fn receive_chunk<'r>(
+    options: &State<'_, Options>,
+    statistics: &State<'_, Arc<RwLock<Statistics>>>,
+    uploads: &Uploads,
+    preview_clips: &PreviewClips,
+    forwarded_identity: &ForwardedIdentity,
+    id: &str,
+    session: &UploadSession,
+    offset: u64,
+    data: Data,
+) -> Response<'r> {
+    // the client must resume from what we have
+    let current_offset = uploads.offset(id);
+    if offset != current_offset {
+        return json_response(options, Status::Conflict, &uploads.status(id, session));
+    }
+
+    let remaining = session.size - current_offset;
+    let limit = remaining.min(options.upload_chunk_max_bytes);
+    let partial_path = uploads.partial_path(id);
+    let written = OpenOptions::new()
+        .append(true)
+        .open(&partial_path)
+        .and_then(|mut file| {
+            // one more byte to know if the client sends too much
+            std::io::copy(&mut data.open().take(limit + 1), &mut file)
+        });
+    let written = match written {
+        Ok(written) => written,
+        Err(err) => {
+            // the client resumes from the acknowledged offset
+            warn!("cannot receive the chunk of upload {}: {}", id, err);
+            let _ = OpenOptions::new()
+                .write(true)
+                .open(&partial_path)
+                .and_then(|file| file.set_len(current_offset));
+            return status_response(Status::InternalServerError);
+        }
+    };
+    if written > limit {
+        let _ = OpenOptions::new()
+            .write(true)
+            .open(&partial_path)
+            .and_then(|file| file.set_len(current_offset));
+        return status_response(Status::PayloadTooLarge);
+    }
+
+    let mut status = uploads.status(id, session);
+    if status.offset < session.size {
+        return json_response(options, Status::Ok, &status);
+    }
+
+    match complete_upload(
+        options,
+        statistics,
+        uploads,
+        preview_clips,
+        forwarded_identity,
+        id,
+        session,
+    ) {
+        Ok(path) => {
+            status.path = options.libraries.to_public(&path);
+            json_response(options, Status::Created, &status)
+        }
+        Err(status) => status_response(status),
+    }
+}
+
+/// checks the file once more and puts it in its folder
+fn complete_upload(
+    options: &State<'_, Options>,
+    statistics: &State<'_, Arc<RwLock<Statistics>>>,
+    uploads: &Uploads,
+    preview_clips: &PreviewClips,
+    forwarded_identity: &ForwardedIdentity,
+    id: &str,
+    session: &UploadSession,
+) -> Result<PathBuf, Status> {
+    let email = &forwarded_identity.email;
+    let folder = PathBuf::from(&session.folder);
+    let requested_path = folder.join(&session.name);
+
+    // the rules might have changed during the upload
+    if !options.has_capability(&folder, email, Capability::Upload) {
+        uploads.finish(id);
+        track_unauthorized_dynamic(options, statistics);
+        return Err(Status::Unauthorized);
+    }
+
+    // the partial file has no extension, so the
+    // content is checked under the final name
+    let partial_path = uploads.partial_path(id);
+    let named_partial_path = partial_path.with_file_name(format!("{}-{}", id, session.name));
+    if let Err(err) = std::fs::rename(&partial_path, &named_partial_path) {
+        error!("cannot rename the upload {}: {}", id, err);
+        return Err(Status::InternalServerError);
+    }
+    if !options
+        .media_types
+        .content_matches_extension(&named_partial_path)
+    {
+        let _ = std::fs::remove_file(&named_partial_path);
+        uploads.finish(id);
+        options.audit(
+            email,
+            "upload",
+            requested_path.to_str().unwrap(),
+            "complete",
+            false,
+        );
+        return Err(Status::UnsupportedMediaType);
+    }
+
+    let path = match claim_destination(&folder, &session.name, options.upload_conflict_policy) {
+        Ok(Some(path)) => path,
+        Ok(None) => {
+            let _ = std::fs::remove_file(&named_partial_path);
+            uploads.finish(id);
+            options.audit(
+                email,
+                "upload",
+                requested_path.to_str().unwrap(),
+                "complete",
+                false,
+            );
+            return Err(Status::Conflict);
+        }
+        Err(err) => {
+            error!("cannot create the upload {} in {:?}: {}", id, folder, err);
+            let _ = std::fs::rename(&named_partial_path, &partial_path);
+            return Err(Status::InternalServerError);
+        }
+    };
+    // replaces the empty file claiming the name
+    if let Err(err) = move_file(&named_partial_path, &path) {
+        error!("cannot move the upload {} to {:?}: {}", id, path, err);
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::rename(&named_partial_path, &partial_path);
+        return Err(Status::InternalServerError);
+    }
+    uploads.finish(id);
+    options.audit(email, "upload", path.to_str().unwrap(), "complete", true);
+
+    // the uploader will look at the thumbnails right away
+    options.upload_thumb_sizes.iter().for_each(|size| {
+        send_thumb(
+            options,
+            statistics,
+            &AcceptedImageFormats::default(),
+            *size,
+            &path,
+        );
+    });
+    if options
+        .media_types
+        .lookup(&path)
+        .is_some_and(|media_type| media_type.kind == MediaKind::Video)
+    {
+        preview_clips.enqueue(&path);
+    }
+
+    Ok(path)
+}
+
+#[delete("/uploads/<id>")]
+pub(crate) fn cancel_upload<'r>(
+    options: State<'_, Options>,
+    uploads: State<'_, Uploads>,
+    forwarded_identity: ForwardedIdentity,
+    id: String,
+) -> Response<'r> {
+    let email = &forwarded_identity.email;
+    let session = match uploads.session(&id, email) {
+        Ok(session) => session,
+        Err(status) => return status_response(status),
+    };
+    match uploads.lock(&id) {
+        Some(_busy) => uploads.finish(&id),
+        None => return status_response(Status::Conflict),
+    }
+
+    let path = Path::new(&session.folder).join(&session.name);
+    options.audit(email, "upload", path.to_str().unwrap(), "cancel", true);
+
+    let mut response = status_response(Status::NoContent);
+    add_access_control_allow_origin_if_needed(&mut response, &options);
+    response
+}
@@ -0,0 +1,90 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+/// every playlist and segment of a video needs its probe,
+/// they are kept until the video changes
+static PROBES: Mutex<BTreeMap<PathBuf, (SystemTime, VideoInfo)>> = Mutex::new(BTreeMap::new());
+const MAX_CACHED_PROBES: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VideoInfo {
+    /// in seconds
+    pub duration: f64,
+    pub width: u64,
+    pub height: u64,
+}
+
+/// the duration and the size of the first video stream
+pub fn probe(path: &Path) -> Option<VideoInfo> {
+    let modified = path
+        .metadata()
+        .and_then(|metadata| metadata.modified())
+        .ok()?;
+    let cached = PROBES
+        .lock()
+        .unwrap()
+        .get(path)
+        .filter(|(probed, _)| *probed == modified)
+        .map(|(_, info)| *info);
+    if cached.is_some() {
+        return cached;
+    }
+
+    let info = run_ffprobe(path)?;
+    let mut probes = PROBES.lock().unwrap();
+    if probes.len() >= MAX_CACHED_PROBES {
+        probes.clear();
+    }
+    probes.insert(path.to_owned(), (modified, info));
+    Some(info)
+}
+
+/// asks ffprobe for the duration and the size
+/// of the first video stream
+fn run_ffprobe(path: &Path) -> Option<VideoInfo> {
+    let mut cmd = Command::new("ffprobe");
+    let cmd = cmd.args(&[
+        "-v",
+        "error",
+        "-select_streams",
+        "v:0",
+        "-show_entries",
+        "stream=width,height:format=duration",
+        "-of",
+        "default=noprint_wrappers=1",
+        path.to_str().unwrap(),
+    ]);
+    trace!("{:#?}", cmd);
+    let output = cmd.output().ok()?;
+    trace!("{:?}", output);
+
+    if !output.status.success() {
+        warn!("ffprobe failed on {:?}: {:?}", path, output);
+        return None;
+    }
+
+    let mut duration = None;
+    let mut width = None;
+    let mut height = None;
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let mut tokens = line.splitn(2, '=');
+            Some((tokens.next()?, tokens.next()?))
+        })
+        .for_each(|(key, value)| match key {
+            "duration" => duration = value.parse::<f64>().ok(),
+            "width" => width = value.parse::<u64>().ok(),
+            "height" => height = value.parse::<u64>().ok(),
+            _ => {}
+        });
+
+    Some(VideoInfo {
+        duration: duration?,
+        width: width?,
+        height: height?,
+    })
+}